@@ -0,0 +1,363 @@
+//! A load generator: simulated traders place and cancel orders around a mid price that follows
+//! a random walk, and the latency of every request is recorded per endpoint.
+//!
+//! Each trader sends one request at a time, so throughput is bounded by the server's latency.
+//! Requests aren't retried; raise the server's rate limits for the API key used.
+use crate::Failure;
+use clap::ValueEnum;
+use fintech_client::{Client, ClientError, RetryPolicy};
+use fintech_common::core::types::{Amount, Order, Price, Quantity, Side};
+use hdrhistogram::Histogram;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+/// Longer latencies are recorded as this, one minute
+const MAX_LATENCY_MICROS: u64 = 60_000_000;
+
+/// How order sizes are drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SizeDistribution {
+    /// Evenly between 1 and one less than twice the mean
+    Uniform,
+    /// Mostly small orders with a long tail of large ones
+    Exponential,
+}
+
+/// The order flow and how long to run it
+#[derive(clap::Args, Debug, Clone)]
+pub struct Options {
+    /// Number of simulated traders
+    #[arg(long, default_value_t = 8)]
+    pub traders: usize,
+    /// How long to run, in seconds
+    #[arg(long, default_value_t = 10)]
+    pub duration: u64,
+    /// The mid price at the start, prices and sizes are whole units
+    #[arg(long, default_value_t = 1_000)]
+    pub mid: u64,
+    /// Largest move of the mid before each order
+    #[arg(long, default_value_t = 1)]
+    pub volatility: u64,
+    /// Orders are priced up to this far from the mid on either side, so some of them cross
+    #[arg(long, default_value_t = 10)]
+    pub spread: u64,
+    #[arg(long, value_enum, default_value_t = SizeDistribution::Uniform)]
+    pub sizes: SizeDistribution,
+    /// Mean order size
+    #[arg(long, default_value_t = 10)]
+    pub mean_size: u64,
+    /// Share of requests that cancel one of the trader's resting orders
+    #[arg(long, default_value_t = 0.2)]
+    pub cancel_ratio: f64,
+    /// Share of requests that read the order book
+    #[arg(long, default_value_t = 0.1)]
+    pub read_ratio: f64,
+    /// Deposited into every trader's account before the run
+    #[arg(long, default_value = "1000000000")]
+    pub funding: Amount,
+    /// Seed for the order flow, random if not given
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// The requests a trader sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Endpoint {
+    Order,
+    Cancel,
+    Orderbook,
+}
+
+impl Endpoint {
+    fn label(&self) -> &'static str {
+        match self {
+            Endpoint::Order => "POST /order",
+            Endpoint::Cancel => "POST /cancel",
+            Endpoint::Orderbook => "GET /orderbook",
+        }
+    }
+}
+
+/// What one trader saw of one endpoint
+#[derive(Debug)]
+struct Stats {
+    /// Microseconds until the response arrived, rejections included
+    latency: Histogram<u64>,
+    /// Answered with an application error, e.g. cancelling an order that has just been filled
+    rejected: u64,
+    /// No usable response: transport errors, rate limits and 5xx
+    failed: u64,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Stats {
+            // Fixed bounds, histograms that grow don't merge reliably
+            latency: Histogram::new_with_bounds(1, MAX_LATENCY_MICROS, 3).expect("the bounds are valid"),
+            rejected: 0,
+            failed: 0,
+        }
+    }
+
+    fn record<T>(&mut self, started: Instant, result: &Result<T, ClientError>) {
+        match result {
+            Ok(_) => {}
+            Err(ClientError::Application(_)) => self.rejected += 1,
+            Err(_) => {
+                self.failed += 1;
+                return;
+            }
+        }
+        self.latency.saturating_record(started.elapsed().as_micros() as u64);
+    }
+
+    fn merge(&mut self, other: &Stats) {
+        self.latency.add(&other.latency).expect("histograms have the same bounds");
+        self.rejected += other.rejected;
+        self.failed += other.failed;
+    }
+}
+
+/// One endpoint's line in the report, latencies in microseconds
+#[derive(Debug, Serialize, PartialEq)]
+pub struct EndpointReport {
+    pub endpoint: &'static str,
+    /// Answered requests, rejections included
+    pub requests: u64,
+    pub rejected: u64,
+    pub failed: u64,
+    pub per_second: f64,
+    pub p50: u64,
+    pub p99: u64,
+    pub p999: u64,
+    pub max: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Report {
+    pub seed: u64,
+    pub traders: usize,
+    pub seconds: f64,
+    pub per_second: f64,
+    pub endpoints: Vec<EndpointReport>,
+}
+
+impl Report {
+    fn new(seed: u64, traders: usize, elapsed: Duration, stats: &BTreeMap<Endpoint, Stats>) -> Self {
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        let endpoints: Vec<_> = stats
+            .iter()
+            .map(|(endpoint, stats)| EndpointReport {
+                endpoint: endpoint.label(),
+                requests: stats.latency.len(),
+                rejected: stats.rejected,
+                failed: stats.failed,
+                per_second: stats.latency.len() as f64 / seconds,
+                p50: stats.latency.value_at_quantile(0.5),
+                p99: stats.latency.value_at_quantile(0.99),
+                p999: stats.latency.value_at_quantile(0.999),
+                max: stats.latency.max(),
+            })
+            .collect();
+        Report {
+            seed,
+            traders,
+            seconds,
+            per_second: endpoints.iter().map(|e| e.requests).sum::<u64>() as f64 / seconds,
+            endpoints,
+        }
+    }
+}
+
+/// Milliseconds with microsecond precision
+fn millis(micros: u64) -> String {
+    format!("{:.3}ms", micros as f64 / 1_000.0)
+}
+
+fn format_report(report: &Report) -> String {
+    let mut out = format!(
+        "{} traders for {:.1}s (seed {}): {:.1} requests/s\n",
+        report.traders, report.seconds, report.seed, report.per_second
+    );
+    out.push_str(&format!(
+        "{:<15} {:>9} {:>9} {:>7} {:>10} {:>10} {:>10} {:>10} {:>10}\n",
+        "ENDPOINT", "REQUESTS", "REJECTED", "FAILED", "REQ/S", "P50", "P99", "P999", "MAX"
+    ));
+    for e in &report.endpoints {
+        out.push_str(&format!(
+            "{:<15} {:>9} {:>9} {:>7} {:>10.1} {:>10} {:>10} {:>10} {:>10}\n",
+            e.endpoint,
+            e.requests,
+            e.rejected,
+            e.failed,
+            e.per_second,
+            millis(e.p50),
+            millis(e.p99),
+            millis(e.p999),
+            millis(e.max)
+        ));
+    }
+    out
+}
+
+/// Draws an order size, at least 1
+fn draw_size(rng: &mut impl Rng, sizes: SizeDistribution, mean: u64) -> u64 {
+    let mean = mean.max(1);
+    match sizes {
+        SizeDistribution::Uniform => rng.random_range(1..=2 * mean - 1),
+        SizeDistribution::Exponential => {
+            let u: f64 = rng.random();
+            ((-(1.0 - u).ln() * mean as f64).round() as u64).max(1)
+        }
+    }
+}
+
+/// Moves the shared mid by up to `volatility` either way and returns the new one, never below 1
+fn walk(mid: &AtomicU64, rng: &mut impl Rng, volatility: u64) -> u64 {
+    let step = rng.random_range(0..=2 * volatility) as i64 - volatility as i64;
+    let next = |m: u64| m.saturating_add_signed(step).max(1);
+    let previous = mid
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |m| Some(next(m)))
+        .expect("the update always succeeds");
+    next(previous)
+}
+
+/// One trader's loop until the deadline
+async fn trade(
+    client: Client,
+    signer: String,
+    options: Options,
+    mid: Arc<AtomicU64>,
+    mut rng: StdRng,
+    deadline: Instant,
+) -> BTreeMap<Endpoint, Stats> {
+    let mut stats = BTreeMap::new();
+    // Ordinals of orders that rested on the book when placed, they may have been filled since
+    let mut resting: Vec<u64> = vec![];
+
+    while Instant::now() < deadline {
+        let roll: f64 = rng.random();
+        let started = Instant::now();
+        if roll < options.read_ratio {
+            let result = client.orderbook().await;
+            stats.entry(Endpoint::Orderbook).or_insert_with(Stats::new).record(started, &result);
+        } else if roll < options.read_ratio + options.cancel_ratio && !resting.is_empty() {
+            let ordinal = resting.swap_remove(rng.random_range(0..resting.len()));
+            let result = client.cancel(&signer, ordinal).await;
+            stats.entry(Endpoint::Cancel).or_insert_with(Stats::new).record(started, &result);
+        } else {
+            let mid = walk(&mid, &mut rng, options.volatility);
+            let offset = rng.random_range(0..=2 * options.spread) as i64 - options.spread as i64;
+            let order = Order {
+                price: Price::units(mid.saturating_add_signed(offset).max(1)),
+                amount: Quantity::units(draw_size(&mut rng, options.sizes, options.mean_size)),
+                side: if rng.random_bool(0.5) { Side::Buy } else { Side::Sell },
+                signer: signer.clone(),
+            };
+            let started = Instant::now();
+            let result = client.place_order(&order).await;
+            stats.entry(Endpoint::Order).or_insert_with(Stats::new).record(started, &result);
+            if let Ok(receipt) = result
+                && receipt.matches.iter().map(|m| m.amount).sum::<Quantity>() < order.amount
+            {
+                resting.push(receipt.ordinal);
+            }
+        }
+    }
+    stats
+}
+
+/// Funds the traders, runs them for the configured duration and prints the report
+pub async fn run(client: &Client, options: Options) -> Result<(), Failure> {
+    // Latencies are only meaningful for single attempts
+    let client = client.clone().with_retry_policy(RetryPolicy::NONE);
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let signers: Vec<String> = (0..options.traders).map(|i| format!("bench-{:x}-{}", seed, i)).collect();
+    for signer in &signers {
+        client.deposit(signer, options.funding).await?;
+    }
+
+    let mid = Arc::new(AtomicU64::new(options.mid));
+    let started = Instant::now();
+    let deadline = started + Duration::from_secs(options.duration);
+    let traders: Vec<_> = signers
+        .into_iter()
+        .enumerate()
+        .map(|(i, signer)| {
+            let rng = StdRng::seed_from_u64(seed.wrapping_add(i as u64));
+            tokio::spawn(trade(client.clone(), signer, options.clone(), mid.clone(), rng, deadline))
+        })
+        .collect();
+
+    let mut stats: BTreeMap<Endpoint, Stats> = BTreeMap::new();
+    for trader in traders {
+        let trader = trader.await.expect("traders don't panic");
+        for (endpoint, s) in trader {
+            stats.entry(endpoint).or_insert_with(Stats::new).merge(&s);
+        }
+    }
+
+    let report = Report::new(seed, options.traders, started.elapsed(), &stats);
+    if options.json {
+        println!("{}", serde_json::to_string_pretty(&report).expect("reports serialize"));
+    } else {
+        print!("{}", format_report(&report));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use fintech_common::errors::ApplicationError;
+
+    #[test]
+    fn test_draw_size_stays_positive_around_the_mean() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let uniform: Vec<u64> = (0..1_000).map(|_| draw_size(&mut rng, SizeDistribution::Uniform, 10)).collect();
+        assert!(uniform.iter().all(|s| (1..=19).contains(s)));
+        let exponential: Vec<u64> =
+            (0..10_000).map(|_| draw_size(&mut rng, SizeDistribution::Exponential, 10)).collect();
+        assert!(exponential.iter().all(|s| *s >= 1));
+        let mean = exponential.iter().sum::<u64>() as f64 / exponential.len() as f64;
+        assert!((9.0..11.0).contains(&mean), "mean {}", mean);
+    }
+
+    #[test]
+    fn test_Report_new_merges_stats_per_endpoint() {
+        let started = Instant::now();
+        let mut a = Stats::new();
+        a.record(started, &Ok::<_, ClientError>(()));
+        a.record::<()>(started, &Err(ClientError::Application(ApplicationError::OrderNotFound(1))));
+        let mut b = Stats::new();
+        b.record::<()>(started, &Err(ClientError::Disconnected("gone".to_string())));
+        b.latency.record(5_000).unwrap();
+        a.merge(&b);
+        let stats = BTreeMap::from([(Endpoint::Cancel, a)]);
+
+        let report = Report::new(1, 2, Duration::from_secs(2), &stats);
+        let cancel = &report.endpoints[0];
+        assert_eq!(
+            (cancel.endpoint, cancel.requests, cancel.rejected, cancel.failed),
+            ("POST /cancel", 3, 1, 1)
+        );
+        assert_eq!(cancel.p99, cancel.max);
+        assert!((4_990..=5_010).contains(&cancel.max), "max {}", cancel.max);
+        assert_eq!(report.per_second, 1.5);
+        assert!(format_report(&report).contains("POST /cancel"));
+    }
+}
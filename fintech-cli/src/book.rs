@@ -0,0 +1,75 @@
+use fintech_common::core::types::{PartialOrder, Price, Quantity, Side};
+
+/// All resting orders at one price
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Level {
+    pub price: Price,
+    /// Open units at this price
+    pub amount: Quantity,
+    /// Number of orders at this price
+    pub orders: usize,
+}
+
+/// Aggregates one side of the book into price levels, best price first
+pub fn levels(orderbook: &[PartialOrder], side: Side) -> Vec<Level> {
+    let mut levels: Vec<Level> = vec![];
+    for order in orderbook.iter().filter(|o| o.side == side) {
+        match levels.iter_mut().find(|l| l.price == order.price) {
+            Some(level) => {
+                level.amount = level.amount.saturating_add(order.remaining);
+                level.orders += 1;
+            }
+            None => levels.push(Level {
+                price: order.price,
+                amount: order.remaining,
+                orders: 1,
+            }),
+        }
+    }
+    match side {
+        Side::Buy => levels.sort_by_key(|l| std::cmp::Reverse(l.price)),
+        Side::Sell => levels.sort_by_key(|l| l.price),
+    }
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn order(ordinal: u64, side: Side, price: u64, remaining: u64, signer: &str) -> PartialOrder {
+        PartialOrder {
+            price: Price::units(price),
+            amount: Quantity::units(remaining),
+            remaining: Quantity::units(remaining),
+            side,
+            signer: signer.to_string(),
+            ordinal,
+        }
+    }
+
+    #[test]
+    fn test_levels_aggregates_best_price_first() {
+        let book = vec![
+            order(1, Side::Buy, 9, 2, "A"),
+            order(2, Side::Buy, 10, 1, "B"),
+            order(3, Side::Buy, 9, 3, "B"),
+            order(4, Side::Sell, 12, 1, "A"),
+            order(5, Side::Sell, 11, 4, "B"),
+        ];
+        assert_eq!(
+            levels(&book, Side::Buy),
+            vec![
+                Level { price: Price::units(10), amount: Quantity::units(1), orders: 1 },
+                Level { price: Price::units(9), amount: Quantity::units(5), orders: 2 },
+            ]
+        );
+        assert_eq!(
+            levels(&book, Side::Sell).iter().map(|l| l.price).collect::<Vec<_>>(),
+            vec![Price::units(11), Price::units(12)]
+        );
+    }
+}
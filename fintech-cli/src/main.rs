@@ -1,178 +1,502 @@
-use std::io;
-use reqwest::Client;
-use serde::{Deserialize, Serialize};
+mod bench;
+mod book;
+mod repl;
+mod scenario;
+mod tui;
 
-#[derive(Debug, Serialize, Deserialize)]
-enum Side {
+use clap::{Parser, Subcommand, ValueEnum};
+use fintech_client::{Client, ClientError};
+use fintech_common::core::types::{
+    Amount, Auction, Instrument, Order, PartialOrder, Precision, Price, Quantity, Receipt, Side, TradingSession,
+};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+/// The side of an order as given on the command line or in a scenario
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SideArg {
     Buy,
     Sell,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Order {
-    price: f64,
-    amount: f64,
-    side: Side,
-    signer: String,
+impl From<SideArg> for Side {
+    fn from(side: SideArg) -> Self {
+        match side {
+            SideArg::Buy => Side::Buy,
+            SideArg::Sell => Side::Sell,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-struct Orderbook {
-    // Add fields as needed
-    orders: Vec<Order>,
+/// Command line client for the fintech trading platform.
+///
+/// Exits with 0 on success, 1 if the server rejected the request or checks of a scenario failed,
+/// 2 on invalid arguments or scenario files and 3 if the server couldn't be reached.
+#[derive(Parser, Debug)]
+#[command(name = "fintech-cli")]
+struct Args {
+    /// Base URL of the server
+    #[arg(long, global = true, env = "FINTECH_SERVER", default_value = "http://localhost:3030")]
+    server: String,
+
+    /// API key sent with every request
+    #[arg(long, global = true, env = "FINTECH_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn read_from_stdin(label: &str) -> String {
-    let mut buffer = String::new();
-    println!("{}", label);
-    io::stdin()
-        .read_line(&mut buffer)
-        .expect("Couldn't read from stdin");
-    buffer.trim().to_owned()
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Deposit funds into an account
+    Deposit {
+        #[arg(long)]
+        account: String,
+        #[arg(long)]
+        amount: Amount,
+    },
+    /// Withdraw funds from an account
+    Withdraw {
+        #[arg(long)]
+        account: String,
+        #[arg(long)]
+        amount: Amount,
+    },
+    /// Transfer funds between accounts
+    Send {
+        #[arg(long)]
+        sender: String,
+        #[arg(long)]
+        recipient: String,
+        #[arg(long)]
+        amount: Amount,
+    },
+    /// Place a limit order
+    Order {
+        #[arg(long, value_enum)]
+        side: SideArg,
+        /// Up to as many decimals as the listed instrument allows, 2 without one, e.g. `10.25`
+        #[arg(long)]
+        price: Price,
+        /// Up to as many decimals as the listed instrument allows, 4 without one
+        #[arg(long)]
+        amount: Quantity,
+        #[arg(long)]
+        signer: String,
+    },
+    /// Show the resting orders
+    Orderbook {
+        /// Print the server's JSON instead of a summary
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show an account's balance
+    Balance {
+        #[arg(long)]
+        account: String,
+    },
+    /// Stop trading: the instrument only accepts cancels, the whole venue nothing at all
+    Halt {
+        /// The listed instrument, the whole venue if not set
+        #[arg(long)]
+        symbol: Option<String>,
+    },
+    /// Lift a halt of the instrument or the whole venue
+    Resume {
+        /// The listed instrument, the whole venue if not set
+        #[arg(long)]
+        symbol: Option<String>,
+    },
+    /// Show the call auction and the price it would execute at
+    Auction,
+    /// Prompt for operations until `quit`
+    Interactive,
+    /// Run a scenario file and report which checks passed
+    Run {
+        /// The YAML scenario
+        file: PathBuf,
+        /// Stop at the first failed check
+        #[arg(long)]
+        fail_fast: bool,
+    },
+    /// Simulate traders against the server and report throughput and latency per endpoint
+    Bench(bench::Options),
+    /// Full-screen order book, trades and open orders, with keys to place and cancel orders
+    Tui {
+        /// The account to trade as
+        #[arg(long)]
+        account: String,
+    },
 }
 
-#[tokio::main]
-async fn main() {
-    println!("Hello, accounting world!");
+/// Why a command failed
+#[derive(Debug)]
+enum Failure {
+    /// The server answered with an error
+    Rejected(ClientError),
+    /// The server couldn't be reached or the response couldn't be read
+    Unreachable(ClientError),
+    /// The terminal couldn't be drawn to
+    Terminal(std::io::Error),
+    /// The arguments don't fit the listed instrument
+    InvalidArguments(String),
+    /// A scenario file couldn't be read
+    InvalidScenario(String),
+    /// Checks of a scenario failed
+    ChecksFailed(usize),
+}
 
-    let client = Client::new();
+impl From<ClientError> for Failure {
+    fn from(e: ClientError) -> Self {
+        match e {
+            ClientError::Transport(_) | ClientError::WebSocket(_) | ClientError::Decode(_) => Failure::Unreachable(e),
+            _ => Failure::Rejected(e),
+        }
+    }
+}
 
-    loop {
-        let input = read_from_stdin(
-            "Choose operation [deposit, withdraw, send, print, orderbook, order, quit], confirm with return:",
-        );
-        match input.as_str() {
-            "deposit" => {
-                let account = read_from_stdin("Account:");
-
-                let raw_amount: Result<f64, _> = read_from_stdin("Amount:").parse();
-                if let Ok(amount) = raw_amount {
-                    let _ = client.post("http://localhost:3030/deposit")
-                        .json(&serde_json::json!({
-                            "account": account,
-                            "amount": amount
-                        }))
-                        .send()
-                        .await;
-                    println!("Deposited {} into account '{}'", amount, account)
-                } else {
-                    eprintln!("Not a number: '{:?}'", raw_amount);
-                }
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Failure::Rejected(_) | Failure::Terminal(_) | Failure::ChecksFailed(_) => ExitCode::from(1),
+            Failure::InvalidScenario(_) | Failure::InvalidArguments(_) => ExitCode::from(2),
+            Failure::Unreachable(_) => ExitCode::from(3),
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Rejected(e) => write!(f, "Rejected: {}", e),
+            Failure::Unreachable(e) => write!(f, "Couldn't reach the server: {}", e),
+            Failure::Terminal(e) => write!(f, "Terminal error: {}", e),
+            Failure::InvalidArguments(e) => write!(f, "Invalid arguments: {}", e),
+            Failure::InvalidScenario(e) => write!(f, "Invalid scenario: {}", e),
+            Failure::ChecksFailed(failed) => write!(f, "{} checks failed", failed),
+        }
+    }
+}
+
+/// A connection to one server
+struct Session {
+    client: Client,
+}
+
+impl Session {
+    fn new(server: String, api_key: Option<String>) -> Self {
+        let client = Client::new(server);
+        Session {
+            client: match api_key {
+                Some(key) => client.with_api_key(key),
+                None => client,
+            },
+        }
+    }
+
+    /// Decimals of the listed instrument's prices and amounts
+    async fn precision(&self) -> Result<Precision, Failure> {
+        Ok(precision_of(&self.client.instruments().await?))
+    }
+
+    /// Runs one operation and prints its outcome
+    async fn run(&self, command: Command) -> Result<(), Failure> {
+        match command {
+            Command::Deposit { account, amount } => {
+                self.client.deposit(&account, amount).await?;
+                println!("Deposited {} into account '{}'", amount, account);
             }
-            "withdraw" => {
-                let account = read_from_stdin("Account:");
-                let raw_amount: Result<f64, _> = read_from_stdin("Amount:").parse();
-                if let Ok(amount) = raw_amount {
-                    let _ = client.post("http://localhost:3030/withdraw")
-                        .json(&serde_json::json!({
-                            "account": account,
-                            "amount": amount
-                        }))
-                        .send()
-                        .await;
-                    println!("Withdrew {} from account '{}'", amount, account)
-                } else {
-                    eprintln!("Not a number: '{:?}'", raw_amount);
-                }
+            Command::Withdraw { account, amount } => {
+                self.client.withdraw(&account, amount).await?;
+                println!("Withdrew {} from account '{}'", amount, account);
             }
-            "send" => {
-                let sender = read_from_stdin("Sender Account:");
-                let recipient = read_from_stdin("Recipient Account:");
-                let raw_amount: Result<f64, _> = read_from_stdin("Amount:").parse();
-                if let Ok(amount) = raw_amount {
-                    let _ = client.post("http://localhost:3030/send")
-                        .json(&serde_json::json!({
-                            "sender": sender,
-                            "recipient": recipient,
-                            "amount": amount
-                        }))
-                        .send()
-                        .await;
-                } else {
-                    eprintln!("Not a number: '{:?}'", raw_amount);
-                }
+            Command::Send { sender, recipient, amount } => {
+                self.client.send(&sender, &recipient, amount).await?;
+                println!("Sent {} from '{}' to '{}'", amount, sender, recipient);
+            }
+            Command::Order { side, price, amount, signer } => {
+                let precision = self.precision().await?;
+                precision.check(price, amount).map_err(Failure::InvalidArguments)?;
+                let order = Order {
+                    price,
+                    amount,
+                    side: side.into(),
+                    signer,
+                };
+                let receipt = self.client.place_order(&order).await?;
+                print!("{}", format_receipt(&order, &receipt, precision));
             }
-            "order" => {
-                let raw_price: Result<f64, _> = read_from_stdin("Price:").parse();
-                let raw_amount: Result<f64, _> = read_from_stdin("Amount:").parse();
-                let side_input = read_from_stdin("Side (buy/sell):");
-                let signer = read_from_stdin("Signer:");
-
-                if let (Ok(price), Ok(amount)) = (raw_price, raw_amount) {
-                    let side = match side_input.as_str() {
-                        "buy" => Side::Buy,
-                        "sell" => Side::Sell,
-                        _ => {
-                            eprintln!("Invalid side: '{}'", side_input);
-                            continue;
-                        }
-                    };
-                    let order = Order {
-                        price,
-                        amount,
-                        side,
-                        signer,
-                    };
-                    // Send order to server instead of using local trading_platform
-                    let response = client.post("http://localhost:3030/order")
-                        .json(&order)
-                        .send()
-                        .await;
-                    match response {
-                        Ok(res) => {
-                            if res.status().is_success() {
-                                println!("Order processed successfully");
-                            } else {
-                                eprintln!("Error processing order: {}", res.status());
-                            }
-                        },
-                        Err(e) => eprintln!("Error sending order: {:?}", e),
-                    }
+            Command::Orderbook { json } => {
+                let orderbook = self.client.orderbook().await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&orderbook).expect("orders serialize"));
                 } else {
-                    eprintln!("Invalid price or amount");
+                    print!("{}", format_orderbook(&orderbook, self.precision().await?));
                 }
             }
-            "orderbook" => {
-                let response = client.get("http://localhost:3030/orderbook")
-                    .send()
-                    .await;
-                
-                let orderbook = match response {
-                    Ok(res) => res.json::<Orderbook>().await.unwrap_or_else(|e| {
-                        eprintln!("Error parsing orderbook: {:?}", e);
-                        Orderbook::default()
-                    }),
-                    Err(e) => {
-                        eprintln!("Error fetching orderbook: {:?}", e);
-                        Orderbook::default()
-                    }
-                };
-                println!("Orderbook: {:?}", orderbook);
+            Command::Balance { account } => {
+                let balance = self.client.balance(&account).await?;
+                println!("Balance of '{}': {}", account, balance);
             }
-            "print" => {
-                // Fetch accounts from server instead of using local trading_platform
-                let response = client.get("http://localhost:3030/accounts")
-                    .send()
-                    .await;
-                
-                match response {
-                    Ok(res) => {
-                        if let Ok(accounts) = res.text().await {
-                            println!("The ledger: {}", accounts);
-                        } else {
-                            eprintln!("Error reading accounts response");
-                        }
-                    },
-                    Err(e) => eprintln!("Error fetching accounts: {:?}", e),
-                }
+            Command::Halt { symbol } => {
+                let session = self.client.halt(symbol.as_deref()).await?;
+                println!("Halted {}", symbol.map_or("the venue".to_string(), |s| format!("'{}'", s)));
+                println!("{}", format_session(&session));
             }
-            "quit" => {
-                println!("Quitting...");
-                break;
+            Command::Resume { symbol } => {
+                let session = self.client.resume(symbol.as_deref()).await?;
+                println!("Resumed {}", symbol.map_or("the venue".to_string(), |s| format!("'{}'", s)));
+                println!("{}", format_session(&session));
             }
-            _ => {
-                eprintln!("Invalid option: '{}'", input);
+            Command::Auction => println!("{}", format_auction(&self.client.auction().await?)),
+            Command::Interactive => repl::run(self).await,
+            Command::Run { file, fail_fast } => {
+                let yaml = std::fs::read_to_string(&file)
+                    .map_err(|e| Failure::InvalidScenario(format!("{}: {}", file.display(), e)))?;
+                let scenario = scenario::Scenario::parse(&yaml)
+                    .map_err(|e| Failure::InvalidScenario(format!("{}: {}", file.display(), e)))?;
+                let report = scenario::run(&self.client, &scenario, fail_fast)
+                    .await
+                    .map_err(Failure::InvalidScenario)?;
+                if report.failed > 0 {
+                    return Err(Failure::ChecksFailed(report.failed));
+                }
             }
+            Command::Bench(options) => bench::run(&self.client, options).await?,
+            Command::Tui { account } => tui::run(self.client.clone(), account).await.map_err(Failure::Terminal)?,
         }
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// The precision of the listed instrument, the default one without an instrument
+fn precision_of(instruments: &[Instrument]) -> Precision {
+    instruments.first().map_or_else(Precision::default, |instrument| instrument.precision)
+}
+
+/// The session in one line, e.g. `Session: Halted (resumes to Continuous)`
+fn format_session(session: &TradingSession) -> String {
+    let mut out = format!("Session: {:?}", session.state);
+    if let Some(resume_to) = session.resume_to {
+        out.push_str(&format!(" (resumes to {:?})", resume_to));
+    }
+    if session.venue_halted {
+        out.push_str(", venue halted");
+    }
+    out
+}
+
+/// The auction in one line, e.g. `Auction: 3 at 10, 1 left over on the SELL side`
+fn format_auction(auction: &Auction) -> String {
+    match (&auction.indicative, auction.active) {
+        (_, false) => "Auction: none running".to_string(),
+        (None, true) => "Auction: no orders cross yet".to_string(),
+        (Some(uncross), true) => {
+            let mut out = format!("Auction: {} at {}", uncross.volume, uncross.price);
+            if let Some(side) = &uncross.surplus_side {
+                out.push_str(&format!(", {} left over on the {} side", uncross.surplus, side_label(side)));
+            }
+            out
+        }
+    }
+}
+
+fn side_label(side: &Side) -> &'static str {
+    match side {
+        Side::Buy => "BUY",
+        Side::Sell => "SELL",
+    }
+}
+
+/// What happened to an order: its ordinal, every match and what rests on the book, with the decimals of `precision`
+fn format_receipt(order: &Order, receipt: &Receipt, precision: Precision) -> String {
+    let price = |price: Price| precision.format_price(price);
+    let quantity = |quantity: Quantity| precision.format_quantity(quantity);
+    let mut out = format!(
+        "Order #{} {} {} @ {} for '{}'\n",
+        receipt.ordinal,
+        side_label(&order.side),
+        quantity(order.amount),
+        price(order.price),
+        order.signer
+    );
+    let mut filled = Quantity::ZERO;
+    // A match's amount is what traded with that maker
+    for (i, maker) in receipt.matches.iter().enumerate() {
+        filled = filled.saturating_add(maker.amount);
+        out.push_str(&format!(
+            "  matched {} @ {} with #{} ({})",
+            quantity(maker.amount),
+            price(maker.price),
+            maker.ordinal,
+            maker.signer
+        ));
+        // Fees are in the same order as the matches
+        match receipt.fees.get(i) {
+            Some(fee) if !fee.taker_fee.is_zero() => out.push_str(&format!(", fee {}\n", fee.taker_fee)),
+            _ => out.push('\n'),
+        }
+    }
+    let resting = order.amount.saturating_sub(filled);
+    if receipt.matches.is_empty() {
+        out.push_str("  no matches, resting on the book\n");
+    } else if !resting.is_zero() {
+        out.push_str(&format!("  {} resting on the book\n", quantity(resting)));
+    } else {
+        out.push_str("  filled\n");
+    }
+    out
+}
+
+/// The resting orders as a table, with the decimals of `precision`
+fn format_orderbook(orderbook: &[PartialOrder], precision: Precision) -> String {
+    if orderbook.is_empty() {
+        return "The order book is empty\n".to_string();
+    }
+    let mut out = format!("{:>8} {:<4} {:>10} {:>10} {:>10}  {}\n", "ORDINAL", "SIDE", "PRICE", "REMAINING", "AMOUNT", "SIGNER");
+    for order in orderbook {
+        out.push_str(&format!(
+            "{:>8} {:<4} {:>10} {:>10} {:>10}  {}\n",
+            order.ordinal,
+            side_label(&order.side),
+            precision.format_price(order.price),
+            precision.format_quantity(order.remaining),
+            precision.format_quantity(order.amount),
+            order.signer
+        ));
+    }
+    out
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let session = Session::new(args.server, args.api_key);
+    match session.run(args.command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use fintech_common::core::types::{FillFee, Uncross};
+
+    fn resting(ordinal: u64, side: Side, price: u64, amount: u64, remaining: u64) -> PartialOrder {
+        PartialOrder {
+            price: Price::units(price),
+            amount: Quantity::units(amount),
+            remaining: Quantity::units(remaining),
+            side,
+            signer: "ALICE".to_string(),
+            ordinal,
+        }
+    }
+
+    #[test]
+    fn test_format_receipt_shows_matches_and_rest() {
+        let order = Order {
+            price: Price::units(10),
+            amount: Quantity::units(3),
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+        };
+        let mut receipt = Receipt {
+            ordinal: 4,
+            matches: vec![resting(1, Side::Sell, 9, 2, 0)],
+            fees: vec![],
+        };
+        let precision = Precision { price: 1, quantity: 0 };
+        assert_eq!(
+            format_receipt(&order, &receipt, precision),
+            "Order #4 BUY 3 @ 10.0 for 'BOB'\n  matched 2 @ 9.0 with #1 (ALICE)\n  1 resting on the book\n"
+        );
+        receipt.fees = vec![FillFee {
+            maker_ordinal: 1,
+            maker_fee: Amount::ZERO,
+            maker_rebate: Amount::ZERO,
+            taker_fee: "0.18".parse().unwrap(),
+        }];
+        assert!(format_receipt(&order, &receipt, precision).contains("with #1 (ALICE), fee 0.18\n"));
+
+        let receipt = Receipt {
+            ordinal: 5,
+            matches: vec![],
+            fees: vec![],
+        };
+        assert!(format_receipt(&order, &receipt, precision).ends_with("no matches, resting on the book\n"));
+    }
+
+    #[test]
+    fn test_format_orderbook_lists_orders() {
+        assert_eq!(format_orderbook(&[], Precision::default()), "The order book is empty\n");
+        let table = format_orderbook(&[resting(1, Side::Sell, 10, 5, 3)], Precision::default());
+        let lines: Vec<_> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1].split_whitespace().collect::<Vec<_>>(),
+            ["1", "SELL", "10.00", "3.0000", "5.0000", "ALICE"]
+        );
+    }
+
+    #[test]
+    fn test_precision_of_the_listed_instrument() {
+        assert_eq!(precision_of(&[]), Precision::default());
+        let instrument = Instrument {
+            symbol: "BTC-USD".to_string(),
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            tick_size: "0.5".parse().unwrap(),
+            lot_size: Quantity::units(1),
+            precision: Precision { price: 1, quantity: 0 },
+            min_quantity: Quantity::ZERO,
+            max_quantity: None,
+            min_notional: Amount::ZERO,
+            price_band_bps: None,
+            status: Default::default(),
+        };
+        assert_eq!(precision_of(&[instrument]), Precision { price: 1, quantity: 0 });
+    }
+
+    #[test]
+    fn test_format_session_shows_halts() {
+        let mut session = TradingSession::default();
+        assert_eq!(format_session(&session), "Session: Continuous");
+        session.halt();
+        session.venue_halted = true;
+        assert_eq!(format_session(&session), "Session: Halted (resumes to Continuous), venue halted");
+    }
+
+    #[test]
+    fn test_format_auction_shows_the_indicative_price() {
+        let mut auction = Auction::default();
+        assert_eq!(format_auction(&auction), "Auction: none running");
+        auction.active = true;
+        assert_eq!(format_auction(&auction), "Auction: no orders cross yet");
+        auction.indicative = Some(Uncross {
+            price: Price::units(10),
+            volume: Quantity::units(3),
+            surplus: Quantity::units(1),
+            surplus_side: Some(Side::Sell),
+        });
+        assert_eq!(format_auction(&auction), "Auction: 3 at 10, 1 left over on the SELL side");
+    }
+
+    #[test]
+    fn test_Args_reject_negative_and_too_precise_amounts() {
+        assert!(Args::try_parse_from(["fintech-cli", "deposit", "--account", "A", "--amount", "100"]).is_ok());
+        assert!(Args::try_parse_from(["fintech-cli", "deposit", "--account", "A", "--amount", "1.5"]).is_ok());
+        assert!(Args::try_parse_from(["fintech-cli", "deposit", "--account", "A", "--amount", "1.0000001"]).is_err());
+        assert!(Args::try_parse_from(["fintech-cli", "deposit", "--account", "A", "--amount", "-1"]).is_err());
+    }
+}
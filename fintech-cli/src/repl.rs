@@ -0,0 +1,88 @@
+use crate::{Command, Session, SideArg};
+use std::{fmt::Display, io, str::FromStr};
+
+fn read_from_stdin(label: &str) -> String {
+    let mut buffer = String::new();
+    println!("{}", label);
+    io::stdin()
+        .read_line(&mut buffer)
+        .expect("Couldn't read from stdin");
+    buffer.trim().to_owned()
+}
+
+/// Reads a non-negative decimal, `None` (after telling the user) if it isn't one
+fn read_amount<T: FromStr<Err: Display>>(label: &str) -> Option<T> {
+    match read_from_stdin(label).parse() {
+        Ok(amount) => Some(amount),
+        Err(e) => {
+            eprintln!("{}", e);
+            None
+        }
+    }
+}
+
+/// Prompts for the parameters of an operation, `None` if the input was invalid
+fn prompt(operation: &str) -> Option<Command> {
+    let command = match operation {
+        "deposit" => Command::Deposit {
+            account: read_from_stdin("Account:"),
+            amount: read_amount("Amount:")?,
+        },
+        "withdraw" => Command::Withdraw {
+            account: read_from_stdin("Account:"),
+            amount: read_amount("Amount:")?,
+        },
+        "send" => Command::Send {
+            sender: read_from_stdin("Sender Account:"),
+            recipient: read_from_stdin("Recipient Account:"),
+            amount: read_amount("Amount:")?,
+        },
+        "order" => {
+            let price = read_amount("Price:")?;
+            let amount = read_amount("Amount:")?;
+            let side = match read_from_stdin("Side (buy/sell):").as_str() {
+                "buy" => SideArg::Buy,
+                "sell" => SideArg::Sell,
+                other => {
+                    eprintln!("Invalid side: '{}'", other);
+                    return None;
+                }
+            };
+            Command::Order {
+                side,
+                price,
+                amount,
+                signer: read_from_stdin("Signer:"),
+            }
+        }
+        "orderbook" => Command::Orderbook { json: false },
+        "balance" => Command::Balance {
+            account: read_from_stdin("Account:"),
+        },
+        _ => {
+            eprintln!("Invalid option: '{}'", operation);
+            return None;
+        }
+    };
+    Some(command)
+}
+
+/// The interactive prompt loop. Failed operations are reported and the loop carries on.
+pub async fn run(session: &Session) {
+    println!("Hello, accounting world!");
+
+    loop {
+        let input = read_from_stdin(
+            "Choose operation [deposit, withdraw, send, balance, orderbook, order, quit], confirm with return:",
+        );
+        if input == "quit" {
+            println!("Quitting...");
+            break;
+        }
+        if let Some(command) = prompt(&input)
+            && let Err(e) = Box::pin(session.run(command)).await
+        {
+            eprintln!("{}", e);
+        }
+    }
+}
@@ -0,0 +1,498 @@
+//! Runs scenario files: a list of steps executed in order against a server, with assertions on
+//! balances and the order book, variables and loops. Every action and assertion is a check in the report.
+//!
+//! ```yaml
+//! name: Partial fill
+//! vars:
+//!   seller: "alice-${run}"
+//! steps:
+//!   - deposit: { account: "${seller}", amount: 100 }
+//!   - order: { signer: "${seller}", side: sell, price: "10.5", amount: 2, save_as: ask }
+//!   - expect_book: { side: sell, price: "10.5", amount: 2 }
+//!   - withdraw: { account: "${seller}", amount: 500 }
+//!     expect_error: AccountUnderFunded
+//!   - cancel: { signer: "${seller}", ordinal: "${ask}" }
+//!   - repeat:
+//!       times: 3
+//!       var: i
+//!       steps:
+//!         - deposit: { account: "trader-${i}-${run}", amount: 10 }
+//! ```
+//!
+//! `${name}` is replaced by the variable's value; a string that is only a reference keeps the
+//! variable's type, so it can stand in for numbers. `run` is predefined with an id unique to each run.
+//! Prices and amounts with decimals are quoted like `"10.5"`, whole numbers don't have to be.
+use crate::{SideArg, book};
+use fintech_client::{Client, ClientError};
+use fintech_common::core::types::{Amount, Order, Price, Quantity};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use std::{collections::BTreeMap, time::SystemTime};
+
+type Vars = BTreeMap<String, Value>;
+
+/// A scenario file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Variables defined before the first step, in order, so they can refer to earlier ones
+    #[serde(default)]
+    pub vars: Mapping,
+    /// Kept as YAML until they run, so variables are resolved with their values at that point
+    pub steps: Vec<Value>,
+}
+
+impl Scenario {
+    pub fn parse(yaml: &str) -> Result<Self, String> {
+        serde_yaml::from_str(yaml).map_err(|e| e.to_string())
+    }
+}
+
+/// What a step does
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum Action {
+    Deposit {
+        account: String,
+        amount: Amount,
+    },
+    Withdraw {
+        account: String,
+        amount: Amount,
+    },
+    Send {
+        sender: String,
+        recipient: String,
+        amount: Amount,
+    },
+    Order {
+        signer: String,
+        side: SideArg,
+        price: Price,
+        amount: Quantity,
+        /// Stores the order's ordinal in this variable
+        #[serde(default)]
+        save_as: Option<String>,
+    },
+    Cancel {
+        signer: String,
+        ordinal: u64,
+    },
+    /// Defines or overwrites variables
+    Set(Vars),
+    /// Runs `steps` `times` times, with `var` counting from 1
+    Repeat {
+        times: u64,
+        #[serde(default)]
+        var: Option<String>,
+        steps: Vec<Value>,
+    },
+    ExpectBalance {
+        account: String,
+        equals: Amount,
+    },
+    /// The open amount and/or number of orders at one price level, 0 for an empty level
+    ExpectBook {
+        side: SideArg,
+        price: Price,
+        #[serde(default)]
+        amount: Option<Quantity>,
+        #[serde(default)]
+        orders: Option<usize>,
+    },
+}
+
+/// A step with its resolved variables
+#[derive(Debug, PartialEq)]
+struct Step {
+    action: Action,
+    /// The [`ApplicationError`](fintech_common::errors::ApplicationError) variant the step must fail with
+    expect_error: Option<String>,
+}
+
+/// Resolves the variables in a step and reads it. The steps inside a `repeat` are left alone,
+/// their variables are resolved when they run.
+fn parse_step(step: &Value, vars: &Vars) -> Result<Step, String> {
+    let Value::Mapping(map) = step else {
+        return Err(format!("A step is a map like `deposit: {{...}}`, found {:?}", step));
+    };
+    let mut map = map.clone();
+    let expect_error = match map.remove("expect_error") {
+        Some(Value::String(name)) => Some(name),
+        Some(other) => return Err(format!("expect_error names an error, found {:?}", other)),
+        None => None,
+    };
+    if map.len() != 1 {
+        return Err(format!("A step has exactly one action, found {}", map.len()));
+    }
+    let nested = match map.get_mut("repeat") {
+        Some(Value::Mapping(repeat)) => repeat.remove("steps"),
+        _ => None,
+    };
+    let mut resolved = substitute(Value::Mapping(map), vars)?;
+    if let (Some(steps), Some(Value::Mapping(repeat))) = (nested, resolved.get_mut("repeat")) {
+        repeat.insert(Value::from("steps"), steps);
+    }
+    // serde_yaml only reads enums from `!tags`, serde_json reads them from single-key maps
+    let action = serde_json::to_value(resolved)
+        .and_then(serde_json::from_value)
+        .map_err(|e| e.to_string())?;
+    Ok(Step { action, expect_error })
+}
+
+fn substitute(value: Value, vars: &Vars) -> Result<Value, String> {
+    match value {
+        Value::String(s) => interpolate(&s, vars),
+        Value::Sequence(seq) => seq.into_iter().map(|v| substitute(v, vars)).collect::<Result<_, _>>().map(Value::Sequence),
+        Value::Mapping(map) => map
+            .into_iter()
+            .map(|(k, v)| Ok((k, substitute(v, vars)?)))
+            .collect::<Result<Mapping, String>>()
+            .map(Value::Mapping),
+        other => Ok(other),
+    }
+}
+
+fn interpolate(s: &str, vars: &Vars) -> Result<Value, String> {
+    let lookup = |name: &str| vars.get(name).ok_or_else(|| format!("Undefined variable '{}'", name));
+    // A lone reference keeps the variable's type
+    if let Some(name) = s.strip_prefix("${").and_then(|rest| rest.strip_suffix('}'))
+        && !name.contains('}')
+    {
+        return lookup(name).cloned();
+    }
+    let mut out = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed variable in '{}'", s))?
+            + start;
+        match lookup(&rest[start + 2..end])? {
+            Value::String(value) => out.push_str(value),
+            Value::Number(value) => out.push_str(&value.to_string()),
+            Value::Bool(value) => out.push_str(&value.to_string()),
+            other => return Err(format!("Can't put {:?} into a string", other)),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(Value::String(out))
+}
+
+/// The name of an error's variant, e.g. `AccountNotFound`
+fn error_name(e: &ClientError) -> Option<String> {
+    match e {
+        ClientError::Application(e) => match serde_json::to_value(e).ok()? {
+            serde_json::Value::Object(map) => map.keys().next().cloned(),
+            serde_json::Value::String(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Tallies the checks
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    pub passed: usize,
+    pub failed: usize,
+}
+
+/// The fail-fast stop
+struct Stop;
+
+struct Runner<'a> {
+    client: &'a Client,
+    vars: Vars,
+    report: Report,
+    fail_fast: bool,
+}
+
+impl Runner<'_> {
+    async fn run_steps(&mut self, steps: &[Value], depth: usize) -> Result<(), Stop> {
+        for (i, step) in steps.iter().enumerate() {
+            let indent = "  ".repeat(depth + 1);
+            let step = match parse_step(step, &self.vars) {
+                Ok(step) => step,
+                Err(e) => {
+                    self.record(Err(format!("step {}: {}", i + 1, e)), &indent)?;
+                    continue;
+                }
+            };
+            match step.action {
+                Action::Set(vars) => self.vars.extend(vars),
+                Action::Repeat { times, var, steps } => {
+                    for n in 1..=times {
+                        if let Some(var) = &var {
+                            self.vars.insert(var.clone(), Value::from(n));
+                        }
+                        Box::pin(self.run_steps(&steps, depth + 1)).await?;
+                    }
+                }
+                action => {
+                    let outcome = match (self.apply(action).await, step.expect_error) {
+                        (Ok(Ok(done)), Some(expected)) => Err(format!("{}: expected {}, but it succeeded", done, expected)),
+                        (Ok(checked), None) => checked,
+                        (Err(e), Some(expected)) if error_name(&e).as_deref() == Some(expected.as_str()) => {
+                            Ok(format!("fails with {}", expected))
+                        }
+                        (Err(e), _) => Err(e.to_string()),
+                        (Ok(Err(failed)), Some(_)) => Err(failed),
+                    };
+                    self.record(outcome, &indent)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, outcome: Result<String, String>, indent: &str) -> Result<(), Stop> {
+        match outcome {
+            Ok(description) => {
+                self.report.passed += 1;
+                println!("{}PASS {}", indent, description);
+                Ok(())
+            }
+            Err(reason) => {
+                self.report.failed += 1;
+                println!("{}FAIL {}", indent, reason);
+                if self.fail_fast { Err(Stop) } else { Ok(()) }
+            }
+        }
+    }
+
+    /// Runs an action. Requests that fail are the outer error, assertions that fail the inner one.
+    async fn apply(&mut self, action: Action) -> Result<Result<String, String>, ClientError> {
+        Ok(Ok(match action {
+            Action::Deposit { account, amount } => {
+                self.client.deposit(&account, amount).await?;
+                format!("deposit {} into {}", amount, account)
+            }
+            Action::Withdraw { account, amount } => {
+                self.client.withdraw(&account, amount).await?;
+                format!("withdraw {} from {}", amount, account)
+            }
+            Action::Send { sender, recipient, amount } => {
+                self.client.send(&sender, &recipient, amount).await?;
+                format!("send {} from {} to {}", amount, sender, recipient)
+            }
+            Action::Order { signer, side, price, amount, save_as } => {
+                let receipt = self
+                    .client
+                    .place_order(&Order {
+                        price,
+                        amount,
+                        side: side.into(),
+                        signer: signer.clone(),
+                    })
+                    .await?;
+                if let Some(var) = save_as {
+                    self.vars.insert(var, Value::from(receipt.ordinal));
+                }
+                let filled: Quantity = receipt.matches.iter().map(|m| m.amount).sum();
+                format!("order #{} {:?} {} @ {} by {}, {} filled", receipt.ordinal, side, amount, price, signer, filled)
+            }
+            Action::Cancel { signer, ordinal } => {
+                let order = self.client.cancel(&signer, ordinal).await?;
+                format!("cancel #{} by {}, {} were open", ordinal, signer, order.remaining)
+            }
+            Action::ExpectBalance { account, equals } => {
+                let balance = self.client.balance(&account).await?;
+                if balance != equals {
+                    return Ok(Err(format!("balance of {}: expected {}, got {}", account, equals, balance)));
+                }
+                format!("balance of {} is {}", account, equals)
+            }
+            Action::ExpectBook { side, price, amount, orders } => {
+                let orderbook = self.client.orderbook().await?;
+                return Ok(check_level(&orderbook, side, price, amount, orders));
+            }
+            Action::Set(_) | Action::Repeat { .. } => unreachable!("control steps aren't applied"),
+        }))
+    }
+}
+
+/// Compares a price level to the expectation
+fn check_level(
+    orderbook: &[fintech_common::core::types::PartialOrder],
+    side: SideArg,
+    price: Price,
+    amount: Option<Quantity>,
+    orders: Option<usize>,
+) -> Result<String, String> {
+    let level = book::levels(orderbook, side.into())
+        .into_iter()
+        .find(|l| l.price == price)
+        .unwrap_or(book::Level {
+            price,
+            amount: Quantity::ZERO,
+            orders: 0,
+        });
+    let description = format!("{:?} level {}", side, price);
+    if let Some(amount) = amount
+        && level.amount != amount
+    {
+        return Err(format!("{}: expected amount {}, got {}", description, amount, level.amount));
+    }
+    if let Some(orders) = orders
+        && level.orders != orders
+    {
+        return Err(format!("{}: expected {} orders, got {}", description, orders, level.orders));
+    }
+    Ok(format!("{} has {} in {} orders", description, level.amount, level.orders))
+}
+
+/// The predefined `run` variable and the scenario's variables
+fn initial_vars(scenario: &Scenario, run_id: &str) -> Result<Vars, String> {
+    let mut vars = Vars::from([("run".to_string(), Value::from(run_id))]);
+    for (name, value) in &scenario.vars {
+        let Value::String(name) = name else {
+            return Err(format!("Variable names are strings, found {:?}", name));
+        };
+        let value = substitute(value.clone(), &vars)?;
+        vars.insert(name.clone(), value);
+    }
+    Ok(vars)
+}
+
+/// Runs the scenario and prints every check. Fails if the variables can't be resolved.
+pub async fn run(client: &Client, scenario: &Scenario, fail_fast: bool) -> Result<Report, String> {
+    if let Some(name) = &scenario.name {
+        println!("Scenario: {}", name);
+    }
+    let run_id = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let vars = initial_vars(scenario, &format!("{:x}", run_id))?;
+    let mut runner = Runner {
+        client,
+        vars,
+        report: Report::default(),
+        fail_fast,
+    };
+    let _ = runner.run_steps(&scenario.steps, 0).await;
+    println!("{} passed, {} failed", runner.report.passed, runner.report.failed);
+    Ok(runner.report)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use fintech_common::core::types::{PartialOrder, Side};
+
+    fn vars() -> Vars {
+        Vars::from([
+            ("who".to_string(), Value::from("ALICE")),
+            ("price".to_string(), Value::from(10)),
+        ])
+    }
+
+    fn yaml(s: &str) -> Value {
+        serde_yaml::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_interpolate_keeps_types_of_lone_references() {
+        assert_eq!(interpolate("${price}", &vars()), Ok(Value::from(10)));
+        assert_eq!(interpolate("${who}-${price}!", &vars()), Ok(Value::from("ALICE-10!")));
+        assert_eq!(interpolate("plain", &vars()), Ok(Value::from("plain")));
+        assert!(interpolate("${nope}", &vars()).unwrap_err().contains("nope"));
+        assert!(interpolate("${who", &vars()).is_err());
+    }
+
+    #[test]
+    fn test_parse_step_resolves_variables() {
+        let step = parse_step(
+            &yaml("{ order: { signer: '${who}', side: buy, price: '${price}', amount: '2.5', save_as: bid } }"),
+            &vars(),
+        )
+        .unwrap();
+        assert_eq!(
+            step.action,
+            Action::Order {
+                signer: "ALICE".to_string(),
+                side: SideArg::Buy,
+                price: Price::units(10),
+                amount: "2.5".parse().unwrap(),
+                save_as: Some("bid".to_string()),
+            }
+        );
+
+        let step = parse_step(
+            &yaml("{ withdraw: { account: x, amount: 5 }, expect_error: AccountUnderFunded }"),
+            &vars(),
+        )
+        .unwrap();
+        assert_eq!(step.expect_error.as_deref(), Some("AccountUnderFunded"));
+
+        assert!(parse_step(&yaml("{ deposit: { account: x, amount: 1 }, send: {} }"), &vars()).is_err());
+        assert!(parse_step(&yaml("{ deposit: { account: x, amount: 1, extra: 1 } }"), &vars()).is_err());
+        // Floats would round, decimals are quoted
+        assert!(parse_step(&yaml("{ deposit: { account: x, amount: 1.5 } }"), &vars()).is_err());
+    }
+
+    #[test]
+    fn test_parse_step_defers_variables_in_repeated_steps() {
+        let step = parse_step(
+            &yaml("{ repeat: { times: '${price}', var: i, steps: [ { deposit: { account: 'a-${i}', amount: 1 } } ] } }"),
+            &vars(),
+        )
+        .unwrap();
+        let Action::Repeat { times, var, steps } = step.action else {
+            panic!("not a repeat: {:?}", step.action);
+        };
+        assert_eq!((times, var.as_deref()), (10, Some("i")));
+        assert_eq!(steps[0], yaml("{ deposit: { account: 'a-${i}', amount: 1 } }"));
+    }
+
+    #[test]
+    fn test_check_level_compares_aggregated_levels() {
+        let book = vec![
+            PartialOrder {
+                price: Price::units(10),
+                amount: Quantity::units(3),
+                remaining: Quantity::units(2),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 1,
+            },
+            PartialOrder {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                remaining: Quantity::units(1),
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+                ordinal: 2,
+            },
+        ];
+        assert!(check_level(&book, SideArg::Sell, Price::units(10), Some(Quantity::units(3)), Some(2)).is_ok());
+        assert!(check_level(&book, SideArg::Sell, Price::units(10), Some(Quantity::units(4)), None).is_err());
+        assert!(check_level(&book, SideArg::Buy, Price::units(10), Some(Quantity::ZERO), Some(0)).is_ok());
+    }
+
+    #[test]
+    fn test_initial_vars_resolve_in_order() {
+        let scenario = Scenario::parse("vars: { b: 'x-${run}', a: '${b}' }\nsteps: []").unwrap();
+        let vars = initial_vars(&scenario, "42").unwrap();
+        assert_eq!(vars["a"], Value::from("x-42"));
+    }
+
+    #[test]
+    fn test_example_scenario_parses() {
+        let scenario = Scenario::parse(include_str!("../scenarios/partial_fill.yaml")).unwrap();
+        let mut vars = initial_vars(&scenario, "test").unwrap();
+        // Saved by the steps as they run
+        vars.insert("ask".to_string(), Value::from(1));
+        for step in &scenario.steps {
+            parse_step(step, &vars).unwrap();
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::book::{Level, levels};
+use fintech_common::core::types::{Amount, Order, PartialOrder, Precision, Side, Trade};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::VecDeque;
+
+/// Number of trades kept for the tape
+const MAX_TRADES: usize = 100;
+
+/// Which field of the order form has the cursor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Field {
+    Price,
+    Amount,
+}
+
+/// The order being entered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OrderForm {
+    pub side: Side,
+    pub price: String,
+    pub amount: String,
+    pub focus: Field,
+}
+
+impl OrderForm {
+    fn new(side: Side) -> Self {
+        OrderForm {
+            side,
+            price: String::new(),
+            amount: String::new(),
+            focus: Field::Price,
+        }
+    }
+
+    fn focused(&mut self) -> &mut String {
+        match self.focus {
+            Field::Price => &mut self.price,
+            Field::Amount => &mut self.amount,
+        }
+    }
+}
+
+/// What the event loop should do after a key press
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Place(Order),
+    Cancel(u64),
+    Refresh,
+    Quit,
+}
+
+/// Everything the screen shows
+#[derive(Debug)]
+pub struct App {
+    /// The account whose orders and balance are shown, and who signs new orders
+    pub account: String,
+    /// Decimals of the listed instrument, orders are entered and shown with them
+    pub precision: Precision,
+    pub orderbook: Vec<PartialOrder>,
+    pub bids: Vec<Level>,
+    pub asks: Vec<Level>,
+    /// Most recent first
+    pub trades: VecDeque<Trade>,
+    pub balance: Option<Amount>,
+    /// Index into [`App::open_orders`]
+    pub selected: usize,
+    pub form: Option<OrderForm>,
+    /// The outcome of the last action or the last error
+    pub status: String,
+}
+
+impl App {
+    pub fn new(account: String, precision: Precision) -> Self {
+        App {
+            account,
+            precision,
+            orderbook: vec![],
+            bids: vec![],
+            asks: vec![],
+            trades: VecDeque::new(),
+            balance: None,
+            selected: 0,
+            form: None,
+            status: String::new(),
+        }
+    }
+
+    /// The account's resting orders, oldest first
+    pub fn open_orders(&self) -> Vec<&PartialOrder> {
+        let mut orders: Vec<_> = self.orderbook.iter().filter(|o| o.signer == self.account).collect();
+        orders.sort_by_key(|o| o.ordinal);
+        orders
+    }
+
+    pub fn set_orderbook(&mut self, orderbook: Vec<PartialOrder>) {
+        self.bids = levels(&orderbook, Side::Buy);
+        self.asks = levels(&orderbook, Side::Sell);
+        self.orderbook = orderbook;
+        self.selected = self.selected.min(self.open_orders().len().saturating_sub(1));
+    }
+
+    pub fn push_trade(&mut self, trade: Trade) {
+        self.trades.push_front(trade);
+        self.trades.truncate(MAX_TRADES);
+    }
+
+    pub fn on_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        match self.form.as_mut() {
+            Some(form) => match key.code {
+                KeyCode::Esc => {
+                    self.form = None;
+                }
+                KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                    form.focus = match form.focus {
+                        Field::Price => Field::Amount,
+                        Field::Amount => Field::Price,
+                    };
+                }
+                KeyCode::Char(c) if c.is_ascii_digit() || c == '.' => form.focused().push(c),
+                KeyCode::Backspace => {
+                    form.focused().pop();
+                }
+                KeyCode::Enter => match (
+                    self.precision.parse_price(&form.price),
+                    self.precision.parse_quantity(&form.amount),
+                ) {
+                    (Ok(price), Ok(amount)) if !amount.is_zero() => {
+                        let order = Order {
+                            price,
+                            amount,
+                            side: form.side.clone(),
+                            signer: self.account.clone(),
+                        };
+                        self.form = None;
+                        return Action::Place(order);
+                    }
+                    (Err(e), _) | (_, Err(e)) if !form.price.is_empty() && !form.amount.is_empty() => {
+                        self.status = e.to_string()
+                    }
+                    _ => self.status = "Enter a price and a non-zero amount".to_string(),
+                },
+                _ => {}
+            },
+            None => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+                KeyCode::Char('b') => self.form = Some(OrderForm::new(Side::Buy)),
+                KeyCode::Char('s') => self.form = Some(OrderForm::new(Side::Sell)),
+                KeyCode::Char('r') => return Action::Refresh,
+                KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+                KeyCode::Down => {
+                    self.selected = (self.selected + 1).min(self.open_orders().len().saturating_sub(1))
+                }
+                KeyCode::Char('c') | KeyCode::Delete => match self.open_orders().get(self.selected) {
+                    Some(order) => return Action::Cancel(order.ordinal),
+                    None => self.status = "No open order selected".to_string(),
+                },
+                _ => {}
+            },
+        }
+        Action::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use fintech_common::core::types::{Price, Quantity};
+
+    fn order(ordinal: u64, side: Side, price: u64, remaining: u64, signer: &str) -> PartialOrder {
+        PartialOrder {
+            price: Price::units(price),
+            amount: Quantity::units(remaining),
+            remaining: Quantity::units(remaining),
+            side,
+            signer: signer.to_string(),
+            ordinal,
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_App_on_key_places_an_order_from_the_form() {
+        let mut app = App::new("ALICE".to_string(), Precision::default());
+        assert_eq!(app.on_key(key(KeyCode::Char('s'))), Action::None);
+        for c in ['1', '2', '.', '5'] {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        app.on_key(key(KeyCode::Tab));
+        app.on_key(key(KeyCode::Char('x')));
+        app.on_key(key(KeyCode::Char('3')));
+
+        assert_eq!(
+            app.on_key(key(KeyCode::Enter)),
+            Action::Place(Order {
+                price: "12.5".parse().unwrap(),
+                amount: Quantity::units(3),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+        );
+        assert_eq!(app.form, None);
+    }
+
+    #[test]
+    fn test_App_on_key_rejects_more_decimals_than_the_instrument_allows() {
+        let mut app = App::new("ALICE".to_string(), Precision { price: 1, quantity: 0 });
+        app.on_key(key(KeyCode::Char('b')));
+        for c in ['1', '2', '.', '2', '5'] {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        app.on_key(key(KeyCode::Tab));
+        app.on_key(key(KeyCode::Char('3')));
+
+        assert_eq!(app.on_key(key(KeyCode::Enter)), Action::None);
+        assert_eq!(app.status, "'12.25' has more than 1 decimals");
+        assert!(app.form.is_some());
+    }
+
+    #[test]
+    fn test_App_on_key_cancels_the_selected_open_order() {
+        let mut app = App::new("ALICE".to_string(), Precision::default());
+        assert_eq!(app.on_key(key(KeyCode::Char('c'))), Action::None);
+        app.set_orderbook(vec![
+            order(1, Side::Buy, 9, 2, "ALICE"),
+            order(2, Side::Buy, 9, 2, "BOB"),
+            order(3, Side::Sell, 11, 1, "ALICE"),
+        ]);
+        app.on_key(key(KeyCode::Down));
+        app.on_key(key(KeyCode::Down));
+        assert_eq!(app.on_key(key(KeyCode::Char('c'))), Action::Cancel(3));
+
+        // The selection follows the book shrinking
+        app.set_orderbook(vec![order(1, Side::Buy, 9, 2, "ALICE")]);
+        assert_eq!(app.selected, 0);
+        assert_eq!(app.on_key(key(KeyCode::Char('q'))), Action::Quit);
+    }
+}
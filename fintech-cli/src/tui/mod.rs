@@ -0,0 +1,150 @@
+//! A full-screen view of the order book, the trade tape and an account's open orders.
+//! The book and balance are polled, trades arrive over the server's WebSocket feed.
+mod app;
+mod ui;
+
+use app::{Action, App};
+use fintech_client::{Client, ClientError};
+use fintech_common::core::types::{Amount, PartialOrder, Quantity, Trade};
+use futures_util::StreamExt;
+use ratatui::crossterm::event::{self, Event as TermEvent, KeyEvent, KeyEventKind};
+use std::{io, time::Duration};
+use tokio::sync::mpsc;
+
+/// How often the book and balance are fetched
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long to wait before reconnecting to the trade feed
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// Everything the screen reacts to
+enum Event {
+    Key(KeyEvent),
+    Resize,
+    Orderbook(Result<Vec<PartialOrder>, ClientError>),
+    Balance(Result<Amount, ClientError>),
+    Trade(Trade),
+    /// The outcome of an action, shown in the status line
+    Status(String),
+}
+
+/// Runs the TUI until the user quits
+pub async fn run(client: Client, account: String) -> io::Result<()> {
+    let (tx, mut rx) = mpsc::channel(256);
+    spawn_input(tx.clone());
+    spawn_poller(client.clone(), account.clone(), tx.clone());
+    spawn_trades(client.clone(), tx.clone());
+
+    // Without the instrument the default decimals apply, the server checks orders anyway
+    let precision = client.instruments().await.map(|instruments| crate::precision_of(&instruments)).unwrap_or_default();
+    let mut terminal = ratatui::init();
+    let mut app = App::new(account, precision);
+    let result = loop {
+        if let Err(e) = terminal.draw(|frame| ui::draw(frame, &app)) {
+            break Err(e);
+        }
+        let Some(event) = rx.recv().await else {
+            break Ok(());
+        };
+        match event {
+            Event::Key(key) => match app.on_key(key) {
+                Action::Quit => break Ok(()),
+                Action::Place(order) => {
+                    let (client, tx) = (client.clone(), tx.clone());
+                    tokio::spawn(async move {
+                        let status = match client.place_order(&order).await {
+                            Ok(receipt) => {
+                                let filled: Quantity = receipt.matches.iter().map(|m| m.amount).sum();
+                                format!("Order #{} placed, {} of {} filled", receipt.ordinal, filled, order.amount)
+                            }
+                            Err(e) => format!("Order rejected: {}", e),
+                        };
+                        let _ = tx.send(Event::Status(status)).await;
+                    });
+                }
+                Action::Cancel(ordinal) => {
+                    let (client, tx, signer) = (client.clone(), tx.clone(), app.account.clone());
+                    tokio::spawn(async move {
+                        let status = match client.cancel(&signer, ordinal).await {
+                            Ok(order) => format!("Order #{} cancelled, {} were open", order.ordinal, order.remaining),
+                            Err(e) => format!("Cancel failed: {}", e),
+                        };
+                        let _ = tx.send(Event::Status(status)).await;
+                    });
+                }
+                Action::Refresh => {
+                    let (client, tx) = (client.clone(), tx.clone());
+                    tokio::spawn(async move {
+                        let _ = tx.send(Event::Orderbook(client.orderbook().await)).await;
+                    });
+                }
+                Action::None => {}
+            },
+            Event::Resize => {}
+            Event::Orderbook(Ok(orderbook)) => app.set_orderbook(orderbook),
+            Event::Balance(Ok(balance)) => app.balance = Some(balance),
+            Event::Balance(Err(ClientError::Application(_))) => app.balance = None,
+            Event::Orderbook(Err(e)) | Event::Balance(Err(e)) => app.status = format!("Refresh failed: {}", e),
+            Event::Trade(trade) => app.push_trade(trade),
+            Event::Status(status) => app.status = status,
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+/// Reads the terminal on a thread of its own, crossterm blocks
+fn spawn_input(tx: mpsc::Sender<Event>) {
+    std::thread::spawn(move || {
+        loop {
+            let event = match event::read() {
+                Ok(TermEvent::Key(key)) if key.kind == KeyEventKind::Press => Event::Key(key),
+                Ok(TermEvent::Resize(_, _)) => Event::Resize,
+                Ok(_) => continue,
+                Err(_) => break,
+            };
+            if tx.blocking_send(event).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_poller(client: Client, account: String, tx: mpsc::Sender<Event>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        loop {
+            interval.tick().await;
+            let (orderbook, balance) = tokio::join!(client.orderbook(), client.balance(&account));
+            if tx.send(Event::Orderbook(orderbook)).await.is_err() || tx.send(Event::Balance(balance)).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Follows the trade feed and reconnects when it drops
+fn spawn_trades(client: Client, tx: mpsc::Sender<Event>) {
+    tokio::spawn(async move {
+        loop {
+            let error = match client.subscribe_trades().await {
+                Ok(mut trades) => loop {
+                    match trades.next().await {
+                        Some(Ok(trade)) => {
+                            if tx.send(Event::Trade(trade)).await.is_err() {
+                                return;
+                            }
+                        }
+                        Some(Err(e)) => break e.to_string(),
+                        None => break "closed by the server".to_string(),
+                    }
+                },
+                Err(e) => e.to_string(),
+            };
+            if tx.send(Event::Status(format!("Trade feed: {}, reconnecting", error))).await.is_err() {
+                return;
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
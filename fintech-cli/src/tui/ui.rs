@@ -0,0 +1,173 @@
+use super::app::{App, Field};
+use crate::book::Level;
+use fintech_common::core::types::Side;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, TableState},
+};
+
+/// Width of the depth bars in characters
+const BAR_WIDTH: u64 = 20;
+
+const HELP: &str = "b buy · s sell · ↑↓ select · c cancel · r refresh · q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [top, orders, status] = Layout::vertical([
+        Constraint::Min(10),
+        Constraint::Length(8),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [ladder, trades] = Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(top);
+
+    draw_ladder(frame, app, ladder);
+    draw_trades(frame, app, trades);
+    draw_open_orders(frame, app, orders);
+    let status_line = if app.status.is_empty() {
+        Line::from(HELP.dark_gray())
+    } else {
+        Line::from(vec![Span::raw(app.status.as_str()), Span::raw("  "), HELP.dark_gray()])
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+
+    if app.form.is_some() {
+        draw_form(frame, app, frame.area());
+    }
+}
+
+/// Asks above the spread, best ask at the bottom, bids below it, best bid on top
+fn draw_ladder(frame: &mut Frame, app: &App, area: Rect) {
+    // Half the rows for each side, minus borders, header and the spread line
+    let depth = (area.height.saturating_sub(4) / 2) as usize;
+    let asks: Vec<&Level> = app.asks.iter().take(depth).collect();
+    let bids: Vec<&Level> = app.bids.iter().take(depth).collect();
+    let largest = asks.iter().chain(bids.iter()).map(|l| l.amount.raw()).max().unwrap_or(1).max(1);
+
+    let row = |level: &Level, color: Color| {
+        let bar = "█".repeat(level.amount.raw().saturating_mul(BAR_WIDTH).div_ceil(largest) as usize);
+        Row::new(vec![
+            Cell::from(app.precision.format_price(level.price)),
+            Cell::from(app.precision.format_quantity(level.amount)),
+            Cell::from(level.orders.to_string()),
+            Cell::from(bar),
+        ])
+        .style(Style::default().fg(color))
+    };
+
+    let mut rows: Vec<Row> = asks.iter().rev().map(|l| row(l, Color::Red)).collect();
+    let spread = match (app.bids.first(), app.asks.first()) {
+        (Some(bid), Some(ask)) => format!("spread {}", app.precision.format_price(ask.price.saturating_sub(bid.price))),
+        _ => "no spread".to_string(),
+    };
+    rows.push(Row::new(vec![Cell::from(""), Cell::from(spread.dark_gray())]));
+    rows.extend(bids.iter().map(|l| row(l, Color::Green)));
+
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(7),
+            Constraint::Min(0),
+        ],
+    )
+    .header(Row::new(vec!["PRICE", "SIZE", "ORDERS", "DEPTH"]).add_modifier(Modifier::BOLD))
+    .block(Block::default().borders(Borders::ALL).title(" Order book "));
+    frame.render_widget(table, area);
+}
+
+fn draw_trades(frame: &mut Frame, app: &App, area: Rect) {
+    let rows = app.trades.iter().map(|trade| {
+        let (label, color) = match trade.aggressor {
+            Side::Buy => ("BUY", Color::Green),
+            Side::Sell => ("SELL", Color::Red),
+        };
+        Row::new(vec![
+            Cell::from(label),
+            Cell::from(app.precision.format_quantity(trade.amount)),
+            Cell::from(app.precision.format_price(trade.price)),
+        ])
+        .style(Style::default().fg(color))
+    });
+    let table = Table::new(rows, [Constraint::Length(5), Constraint::Length(10), Constraint::Min(0)])
+        .header(Row::new(vec!["SIDE", "SIZE", "PRICE"]).add_modifier(Modifier::BOLD))
+        .block(Block::default().borders(Borders::ALL).title(" Trades "));
+    frame.render_widget(table, area);
+}
+
+fn draw_open_orders(frame: &mut Frame, app: &App, area: Rect) {
+    let balance = app.balance.map(|b| b.to_string()).unwrap_or_else(|| "-".to_string());
+    let title = format!(" Open orders of '{}', balance {} ", app.account, balance);
+    let orders = app.open_orders();
+    let rows = orders.iter().map(|order| {
+        let side = match order.side {
+            Side::Buy => "BUY".green(),
+            Side::Sell => "SELL".red(),
+        };
+        Row::new(vec![
+            Cell::from(format!("#{}", order.ordinal)),
+            Cell::from(side),
+            Cell::from(app.precision.format_price(order.price)),
+            Cell::from(format!(
+                "{}/{}",
+                app.precision.format_quantity(order.remaining),
+                app.precision.format_quantity(order.amount)
+            )),
+        ])
+    });
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(8),
+            Constraint::Length(5),
+            Constraint::Length(10),
+            Constraint::Min(0),
+        ],
+    )
+    .header(Row::new(vec!["ORDER", "SIDE", "PRICE", "OPEN"]).add_modifier(Modifier::BOLD))
+    .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+    .block(Block::default().borders(Borders::ALL).title(title));
+    let mut state = TableState::default().with_selected((!orders.is_empty()).then_some(app.selected));
+    frame.render_stateful_widget(table, area, &mut state);
+}
+
+fn draw_form(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(form) = &app.form else {
+        return;
+    };
+    let [_, row, _] = Layout::vertical([Constraint::Fill(1), Constraint::Length(6), Constraint::Fill(1)]).areas(area);
+    let [_, popup, _] =
+        Layout::horizontal([Constraint::Fill(1), Constraint::Length(40), Constraint::Fill(1)]).areas(row);
+
+    let field = |label: &'static str, value: &str, focused: bool| {
+        let style = if focused {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        Line::from(vec![Span::raw(label), Span::styled(format!("{:<12}", value), style)])
+    };
+    let (title, color) = match form.side {
+        Side::Buy => (" Buy ", Color::Green),
+        Side::Sell => (" Sell ", Color::Red),
+    };
+    let text = vec![
+        field("Price:  ", &form.price, form.focus == Field::Price),
+        field("Amount: ", &form.amount, form.focus == Field::Amount),
+        Line::from(""),
+        Line::from("enter place · tab next · esc close".dark_gray()),
+    ];
+    frame.render_widget(Clear, popup);
+    frame.render_widget(
+        Paragraph::new(text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(title)
+                .border_style(Style::default().fg(color)),
+        ),
+        popup,
+    );
+}
@@ -0,0 +1,162 @@
+use fintech_common::errors::{ApplicationError, ErrorResponse};
+use reqwest::StatusCode;
+use std::{fmt, time::Duration};
+use tokio_tungstenite::tungstenite;
+
+/// Everything that can go wrong talking to the platform
+#[derive(Debug)]
+pub enum ClientError {
+    /// The platform refused the request, e.g. an unknown account or insufficient funds
+    Application(ApplicationError),
+
+    /// The API key is missing or unknown
+    Unauthorized,
+
+    /// The API key's role lacks the permission
+    Forbidden(String),
+
+    /// The rate limit is exhausted (after all retries)
+    RateLimited { retry_after: Option<Duration> },
+
+    /// Any other unsuccessful response
+    Http { status: u16, message: String },
+
+    /// The request didn't get a response
+    Transport(reqwest::Error),
+
+    /// A WebSocket feed failed
+    WebSocket(Box<tungstenite::Error>),
+
+    /// The server closed a WebSocket feed, e.g. because the subscriber lagged behind
+    Disconnected(String),
+
+    /// A response didn't have the expected shape
+    Decode(String),
+}
+
+impl ClientError {
+    /// Maps an unsuccessful response to an error, preferring the [`ApplicationError`] in the body
+    pub(crate) fn from_response(status: StatusCode, retry_after: Option<Duration>, body: &str) -> Self {
+        if let Ok(response) = serde_json::from_str::<ErrorResponse>(body) {
+            return ClientError::Application(response.error);
+        }
+        // Other errors are a JSON string or plain text
+        let message = serde_json::from_str::<String>(body).unwrap_or_else(|_| body.to_string());
+        match status {
+            StatusCode::UNAUTHORIZED => ClientError::Unauthorized,
+            StatusCode::FORBIDDEN => ClientError::Forbidden(message),
+            StatusCode::TOO_MANY_REQUESTS => ClientError::RateLimited { retry_after },
+            _ => ClientError::Http {
+                status: status.as_u16(),
+                message,
+            },
+        }
+    }
+
+    /// Whether trying again may succeed. Writes are only retried with their idempotency key.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ClientError::Transport(e) => !e.is_builder() && !e.is_decode(),
+            ClientError::RateLimited { .. } => true,
+            ClientError::Application(ApplicationError::Unavailable(_)) => true,
+            ClientError::Http { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Application(e) => write!(f, "{}", e),
+            ClientError::Unauthorized => write!(f, "Missing or unknown API key"),
+            ClientError::Forbidden(message) => write!(f, "Forbidden: {}", message),
+            ClientError::RateLimited { retry_after: Some(after) } => {
+                write!(f, "Rate limit exceeded, retry after {:?}", after)
+            }
+            ClientError::RateLimited { retry_after: None } => write!(f, "Rate limit exceeded"),
+            ClientError::Http { status, message } => write!(f, "HTTP {}: {}", status, message),
+            ClientError::Transport(e) => write!(f, "Request failed: {}", e),
+            ClientError::WebSocket(e) => write!(f, "Trade stream failed: {}", e),
+            ClientError::Disconnected(reason) => write!(f, "Trade stream closed: {}", reason),
+            ClientError::Decode(e) => write!(f, "Unexpected response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Application(e) => Some(e),
+            ClientError::Transport(e) => Some(e),
+            ClientError::WebSocket(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Transport(e)
+    }
+}
+
+impl From<tungstenite::Error> for ClientError {
+    fn from(e: tungstenite::Error) -> Self {
+        ClientError::WebSocket(Box::new(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use fintech_common::core::types::Amount;
+
+    #[test]
+    fn test_ClientError_from_response_prefers_application_errors() {
+        let body = serde_json::to_string(&ErrorResponse::from(&ApplicationError::AccountUnderFunded(
+            "ALICE".to_string(),
+            Amount::units(100),
+        )))
+        .unwrap();
+        let e = ClientError::from_response(StatusCode::UNPROCESSABLE_ENTITY, None, &body);
+        assert!(matches!(
+            e,
+            ClientError::Application(ApplicationError::AccountUnderFunded(ref account, amount)) if account == "ALICE" && amount == Amount::units(100)
+        ));
+        assert!(!e.is_retryable());
+
+        let body = serde_json::to_string(&ErrorResponse::from(&ApplicationError::Unavailable(
+            "sequencer stopped".to_string(),
+        )))
+        .unwrap();
+        assert!(ClientError::from_response(StatusCode::SERVICE_UNAVAILABLE, None, &body).is_retryable());
+    }
+
+    #[test]
+    fn test_ClientError_from_response_maps_status_codes() {
+        assert!(matches!(
+            ClientError::from_response(StatusCode::UNAUTHORIZED, None, "\"Missing or unknown API key\""),
+            ClientError::Unauthorized
+        ));
+        assert!(matches!(
+            ClientError::from_response(StatusCode::FORBIDDEN, None, "\"Role Reader lacks Trade permission\""),
+            ClientError::Forbidden(ref message) if message == "Role Reader lacks Trade permission"
+        ));
+        let limited = ClientError::from_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            Some(Duration::from_secs(2)),
+            "\"Rate limit exceeded\"",
+        );
+        assert!(matches!(limited, ClientError::RateLimited { retry_after: Some(d) } if d == Duration::from_secs(2)));
+        assert!(limited.is_retryable());
+
+        let e = ClientError::from_response(StatusCode::BAD_GATEWAY, None, "upstream down");
+        assert!(matches!(e, ClientError::Http { status: 502, ref message } if message == "upstream down"));
+        assert!(e.is_retryable());
+        assert!(!ClientError::from_response(StatusCode::BAD_REQUEST, None, "\"Invalid body\"").is_retryable());
+    }
+}
@@ -0,0 +1,518 @@
+//! An async client for the Fintech Trading Platform's HTTP and WebSocket API.
+//!
+//! ```no_run
+//! use fintech_client::Client;
+//! use fintech_common::core::types::{Order, Price, Quantity, Side};
+//!
+//! # async fn run() -> Result<(), fintech_client::ClientError> {
+//! let client = Client::new("http://localhost:3030").with_api_key("trader-key");
+//! let receipt = client
+//!     .place_order(&Order { price: Price::units(10), amount: Quantity::units(1), side: Side::Buy, signer: "ALICE".to_string() })
+//!     .await?;
+//! client.cancel("ALICE", receipt.ordinal).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Writes carry an `idempotency-key` that stays the same across retries, so a retried
+//! request is applied once even if the first attempt's response got lost.
+mod error;
+mod retry;
+
+pub use error::ClientError;
+pub use retry::RetryPolicy;
+
+use fintech_common::core::types::{
+    AccountBalanceRequest, AccountUpdateRequest, Amount, Auction, BookTop, BookUpdate, CancelRequest, Candle, Depth, HaltRequest,
+    Instrument, Interval, L3Book, Order, PartialOrder, Receipt, SendRequest, SessionRequest, SessionState, Ticker, Trade,
+    TradingSession,
+};
+use futures_util::{StreamExt, stream::BoxStream};
+use reqwest::{Method, RequestBuilder, header::RETRY_AFTER};
+use serde::{Serialize, de::DeserializeOwned};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::{
+    self,
+    client::IntoClientRequest,
+    http::HeaderValue,
+    protocol::{CloseFrame, Message, frame::coding::CloseCode},
+};
+
+/// The header carrying the API key
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// The header carrying a write's idempotency key
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// The header naming the snapshot a read was served from
+const SNAPSHOT_SEQUENCE_HEADER: &str = "x-snapshot-sequence";
+
+/// Every trade on the platform, as it happens
+pub type TradeStream = BoxStream<'static, Result<Trade, ClientError>>;
+
+/// Every change to the resting orders, one update per command
+pub type BookStream = BoxStream<'static, Result<BookUpdate, ClientError>>;
+
+/// A connection to one platform server. Cheap to clone, clones share the connection pool.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, e.g. `http://localhost:3030`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Client {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Authenticates every request with `key`
+    pub fn with_api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Replaces the default [`RetryPolicy`]
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Deposit funds into an account, creating it if needed (admin)
+    pub async fn deposit(&self, account: &str, amount: Amount) -> Result<(), ClientError> {
+        let req = AccountUpdateRequest {
+            account: account.to_string(),
+            amount,
+        };
+        self.write::<_, String>("/deposit", &req).await.map(|_| ())
+    }
+
+    /// Withdraw funds from an account (admin)
+    pub async fn withdraw(&self, account: &str, amount: Amount) -> Result<(), ClientError> {
+        let req = AccountUpdateRequest {
+            account: account.to_string(),
+            amount,
+        };
+        self.write::<_, String>("/withdraw", &req).await.map(|_| ())
+    }
+
+    /// Transfer funds between accounts (admin)
+    pub async fn send(&self, sender: &str, recipient: &str, amount: Amount) -> Result<(), ClientError> {
+        let req = SendRequest {
+            sender: sender.to_string(),
+            recipient: recipient.to_string(),
+            amount,
+        };
+        self.write::<_, String>("/send", &req).await.map(|_| ())
+    }
+
+    /// Place a limit order, the receipt lists the immediate matches (trader)
+    pub async fn place_order(&self, order: &Order) -> Result<Receipt, ClientError> {
+        self.write("/order", order).await
+    }
+
+    /// Cancel a resting order, returns what was still resting (trader)
+    pub async fn cancel(&self, signer: &str, ordinal: u64) -> Result<PartialOrder, ClientError> {
+        let req = CancelRequest {
+            signer: signer.to_string(),
+            ordinal,
+        };
+        self.write("/cancel", &req).await
+    }
+
+    /// The resting orders, oldest first
+    pub async fn orderbook(&self) -> Result<Vec<PartialOrder>, ClientError> {
+        self.request(|| self.http.get(self.url("/orderbook"))).await
+    }
+
+    /// The best bid and offer with spread and mid price
+    pub async fn top(&self) -> Result<BookTop, ClientError> {
+        self.request(|| self.http.get(self.url("/book/top"))).await
+    }
+
+    /// Up to `levels` aggregated price levels per side, best prices first
+    pub async fn depth(&self, levels: usize) -> Result<Depth, ClientError> {
+        self.request(|| self.http.get(self.url(&format!("/book/depth?levels={}", levels)))).await
+    }
+
+    /// Every resting order without signers, and the sequence number of the snapshot it was read from.
+    /// Apply the updates of [`Client::subscribe_book`] with a later `sequence` to keep it current.
+    pub async fn orders(&self) -> Result<(L3Book, u64), ClientError> {
+        let (book, sequence) = self.request_with_sequence(|| self.http.get(self.url("/book/orders"))).await?;
+        let sequence = sequence.ok_or_else(|| ClientError::Decode(format!("No {} header", SNAPSHOT_SEQUENCE_HEADER)))?;
+        Ok((book, sequence))
+    }
+
+    /// Up to `limit` recent trades with an id after `since`, oldest first.
+    /// Pass the id of the last trade received to page on.
+    pub async fn trades(&self, since: u64, limit: usize) -> Result<Vec<Trade>, ClientError> {
+        self.request(|| self.http.get(self.url(&format!("/trades?since={}&limit={}", since, limit)))).await
+    }
+
+    /// The `interval` candles overlapping `from..to` (milliseconds since the Unix epoch), oldest first
+    pub async fn candles(&self, interval: Interval, from: u64, to: u64) -> Result<Vec<Candle>, ClientError> {
+        self.request(|| self.http.get(self.url(&format!("/candles?interval={}&from={}&to={}", interval, from, to)))).await
+    }
+
+    /// Statistics of the trades in the last 24 hours
+    pub async fn ticker(&self) -> Result<Ticker, ClientError> {
+        self.request(|| self.http.get(self.url("/ticker"))).await
+    }
+
+    /// The listed instruments, at most one
+    pub async fn instruments(&self) -> Result<Vec<Instrument>, ClientError> {
+        self.request(|| self.http.get(self.url("/instruments"))).await
+    }
+
+    /// List the instrument traded on the book (admin)
+    pub async fn create_instrument(&self, instrument: &Instrument) -> Result<Instrument, ClientError> {
+        self.write("/instruments", instrument).await
+    }
+
+    /// Change the definition of the instrument with the same symbol (admin)
+    pub async fn update_instrument(&self, instrument: &Instrument) -> Result<Instrument, ClientError> {
+        let path = format!("/instruments/{}", path_segment(&instrument.symbol));
+        self.write_with(Method::PUT, &path, instrument).await
+    }
+
+    /// The trading session
+    pub async fn session(&self) -> Result<TradingSession, ClientError> {
+        self.request(|| self.http.get(self.url("/session"))).await
+    }
+
+    /// Move the book to another session state (admin)
+    pub async fn set_session(&self, state: SessionState) -> Result<TradingSession, ClientError> {
+        self.write_with(Method::PUT, "/session", &SessionRequest { state }).await
+    }
+
+    /// Halt the listed instrument with `symbol`, or the whole venue without one (admin)
+    pub async fn halt(&self, symbol: Option<&str>) -> Result<TradingSession, ClientError> {
+        let req = HaltRequest {
+            symbol: symbol.map(String::from),
+        };
+        self.write("/session/halt", &req).await
+    }
+
+    /// Lift the halt of the listed instrument with `symbol`, or of the whole venue without one (admin)
+    pub async fn resume(&self, symbol: Option<&str>) -> Result<TradingSession, ClientError> {
+        let req = HaltRequest {
+            symbol: symbol.map(String::from),
+        };
+        self.write("/session/resume", &req).await
+    }
+
+    /// The call auction and its indicative price
+    pub async fn auction(&self) -> Result<Auction, ClientError> {
+        self.request(|| self.http.get(self.url("/auction"))).await
+    }
+
+    /// An account's balance
+    pub async fn balance(&self, account: &str) -> Result<Amount, ClientError> {
+        let req = AccountBalanceRequest {
+            account: account.to_string(),
+        };
+        self.request(|| self.http.post(self.url("/balance")).json(&req)).await
+    }
+
+    /// Subscribes to the trade feed. The stream ends when the server shuts down and
+    /// yields [`ClientError::Disconnected`] if the server drops a subscriber that fell behind.
+    pub async fn subscribe_trades(&self) -> Result<TradeStream, ClientError> {
+        self.subscribe("/ws/trades").await
+    }
+
+    /// Subscribes to the changes to the resting orders, see [`Client::orders`].
+    /// Ends like [`Client::subscribe_trades`]; after a disconnect, read the book again.
+    pub async fn subscribe_book(&self) -> Result<BookStream, ClientError> {
+        self.subscribe("/ws/book").await
+    }
+
+    /// Connects to a WebSocket feed of JSON messages
+    async fn subscribe<T: DeserializeOwned + Send + 'static>(
+        &self,
+        path: &str,
+    ) -> Result<BoxStream<'static, Result<T, ClientError>>, ClientError> {
+        let url = websocket_url(&self.url(path));
+        let mut request = url.into_client_request()?;
+        if let Some(key) = &self.api_key {
+            let value = HeaderValue::from_str(key).map_err(|e| ClientError::Decode(format!("Invalid API key: {}", e)))?;
+            request.headers_mut().insert(API_KEY_HEADER, value);
+        }
+        let (socket, _) = tokio_tungstenite::connect_async(request)
+            .await
+            .map_err(|e| match e {
+                // A rejected upgrade carries the same errors as any other request
+                tungstenite::Error::Http(response) => {
+                    let body = response.body().as_deref().map(String::from_utf8_lossy).unwrap_or_default();
+                    ClientError::from_response(response.status(), retry_after(response.headers()), &body)
+                }
+                e => e.into(),
+            })?;
+        Ok(socket
+            .filter_map(|message| async move {
+                match message {
+                    Ok(Message::Text(text)) => {
+                        Some(serde_json::from_str::<T>(&text).map_err(|e| ClientError::Decode(e.to_string())))
+                    }
+                    Ok(Message::Close(Some(CloseFrame { code, reason }))) if code != CloseCode::Normal => {
+                        Some(Err(ClientError::Disconnected(reason.to_string())))
+                    }
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e.into())),
+                }
+            })
+            .boxed())
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Sends a state-changing request with one idempotency key for all attempts
+    async fn write<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, ClientError> {
+        self.write_with(Method::POST, path, body).await
+    }
+
+    /// Like [`Client::write`] with another `method`
+    async fn write_with<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: &B,
+    ) -> Result<T, ClientError> {
+        let key = uuid::Uuid::new_v4().to_string();
+        self.request(|| {
+            self.http
+                .request(method.clone(), self.url(path))
+                .header(IDEMPOTENCY_KEY_HEADER, &key)
+                .json(body)
+        })
+        .await
+    }
+
+    /// Sends the request built by `build` until it succeeds, fails for good or runs out of attempts
+    async fn request<T: DeserializeOwned>(&self, build: impl Fn() -> RequestBuilder) -> Result<T, ClientError> {
+        self.request_with_sequence(build).await.map(|(value, _)| value)
+    }
+
+    /// Like [`Client::request`], with the snapshot a read was served from
+    async fn request_with_sequence<T: DeserializeOwned>(
+        &self,
+        build: impl Fn() -> RequestBuilder,
+    ) -> Result<(T, Option<u64>), ClientError> {
+        let mut attempt = 0;
+        loop {
+            let mut request = build();
+            if let Some(key) = &self.api_key {
+                request = request.header(API_KEY_HEADER, key);
+            }
+            match send(request).await {
+                Err(e) if e.is_retryable() && attempt + 1 < self.retry.max_attempts => {
+                    let backoff = self.retry.backoff(attempt);
+                    let delay = match e {
+                        ClientError::RateLimited { retry_after: Some(after) } => after.max(backoff),
+                        _ => backoff,
+                    };
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Sends one attempt and decodes its response and snapshot sequence, if any
+async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<(T, Option<u64>), ClientError> {
+    let response = request.send().await?;
+    let status = response.status();
+    let retry_after = retry_after(response.headers());
+    let sequence = response
+        .headers()
+        .get(SNAPSHOT_SEQUENCE_HEADER)
+        .and_then(|value| value.to_str().ok()?.parse().ok());
+    let body = response.text().await?;
+    if status.is_success() {
+        let value = serde_json::from_str(&body).map_err(|e| ClientError::Decode(e.to_string()))?;
+        Ok((value, sequence))
+    } else {
+        Err(ClientError::from_response(status, retry_after, &body))
+    }
+}
+
+/// The `Retry-After` header in seconds, if any
+fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Percent-encodes `segment` for one segment of a URL path, so a `/` or `?` in it stays part of it
+fn path_segment(segment: &str) -> String {
+    let mut url = reqwest::Url::parse("http://localhost/").expect("a valid base url");
+    url.path_segments_mut().expect("http urls have a path").push(segment);
+    url.path().trim_start_matches('/').to_string()
+}
+
+/// Swaps the scheme of an HTTP url for the matching WebSocket one
+fn websocket_url(url: &str) -> String {
+    if let Some(rest) = url.strip_prefix("https://") {
+        format!("wss://{}", rest)
+    } else if let Some(rest) = url.strip_prefix("http://") {
+        format!("ws://{}", rest)
+    } else {
+        url.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use fintech_common::errors::{ApplicationError, ErrorResponse};
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    /// Serves the canned `(status line, body)` responses in order and records the requests' headers
+    async fn serve(responses: Vec<(&'static str, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        let seen = requests.clone();
+        tokio::spawn(async move {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 4096];
+                let n = stream.read(&mut request).await.unwrap();
+                seen.lock().unwrap().push(String::from_utf8_lossy(&request[..n]).to_lowercase());
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\nretry-after: 0\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (format!("http://{}/", addr), requests)
+    }
+
+    fn header<'a>(request: &'a str, name: &str) -> Option<&'a str> {
+        request
+            .lines()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+            .map(str::trim)
+    }
+
+    #[tokio::test]
+    async fn test_Client_retries_writes_with_the_same_idempotency_key() {
+        let (url, requests) = serve(vec![
+            ("503 Service Unavailable", "\"busy\"".to_string()),
+            ("429 Too Many Requests", "\"Rate limit exceeded\"".to_string()),
+            ("200 OK", "\"Deposit successful\"".to_string()),
+        ])
+        .await;
+        let client = Client::new(url).with_api_key("admin-key").with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        });
+
+        client.deposit("ALICE", "100.5".parse().unwrap()).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        let keys: Vec<_> = requests.iter().map(|r| header(r, IDEMPOTENCY_KEY_HEADER).unwrap()).collect();
+        assert!(keys.iter().all(|k| *k == keys[0]));
+        assert!(requests.iter().all(|r| header(r, API_KEY_HEADER) == Some("admin-key")));
+        assert!(requests[0].starts_with("post /deposit "));
+        // Amounts are sent as decimal strings
+        assert!(requests[0].contains(r#""amount":"100.5""#));
+    }
+
+    #[tokio::test]
+    async fn test_Client_update_instrument_puts_to_the_symbol() {
+        let instrument = Instrument {
+            symbol: "BTC-USD".to_string(),
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            tick_size: "0.5".parse().unwrap(),
+            lot_size: "0.01".parse().unwrap(),
+            precision: Default::default(),
+            min_quantity: Default::default(),
+            max_quantity: None,
+            min_notional: Default::default(),
+            price_band_bps: Some(500),
+            status: Default::default(),
+        };
+        let (url, requests) = serve(vec![("200 OK", serde_json::to_string(&instrument).unwrap())]).await;
+
+        assert_eq!(Client::new(url).update_instrument(&instrument).await.unwrap(), instrument);
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("put /instruments/btc-usd "));
+        assert!(header(&requests[0], IDEMPOTENCY_KEY_HEADER).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_Client_returns_application_errors_without_retrying() {
+        let error = ErrorResponse::from(&ApplicationError::OrderNotFound(7));
+        let (url, requests) = serve(vec![("404 Not Found", serde_json::to_string(&error).unwrap())]).await;
+        let client = Client::new(url);
+
+        let result = client.cancel("ALICE", 7).await;
+
+        assert!(matches!(result, Err(ClientError::Application(ApplicationError::OrderNotFound(7)))));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_Client_gives_up_after_max_attempts() {
+        let (url, requests) = serve(vec![
+            ("502 Bad Gateway", "\"down\"".to_string()),
+            ("502 Bad Gateway", "\"down\"".to_string()),
+        ])
+        .await;
+        let client = Client::new(url).with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(1),
+        });
+
+        let result = client.orderbook().await;
+
+        assert!(matches!(result, Err(ClientError::Http { status: 502, .. })));
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        // Reads don't need a key
+        assert_eq!(header(&requests[0], IDEMPOTENCY_KEY_HEADER), None);
+    }
+
+    #[test]
+    fn test_path_segment_encodes_reserved_characters() {
+        assert_eq!(path_segment("BTC-USD"), "BTC-USD");
+        assert_eq!(path_segment("BTC/USD"), "BTC%2FUSD");
+        assert_eq!(path_segment("a b?c#d"), "a%20b%3Fc%23d");
+    }
+
+    #[test]
+    fn test_websocket_url_works() {
+        assert_eq!(websocket_url("http://localhost:8080/ws/trades"), "ws://localhost:8080/ws/trades");
+        assert_eq!(websocket_url("https://example.com/ws/trades"), "wss://example.com/ws/trades");
+    }
+}
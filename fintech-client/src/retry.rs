@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+/// How often and how patiently requests are retried. Only failures that may be
+/// transient are retried: lost connections, 5xx responses and exhausted rate limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per request including the first, at least 1
+    pub max_attempts: u32,
+    /// The delay before the first retry, doubled for every further one
+    pub base_delay: Duration,
+    /// The longest delay between attempts, unless the server asks for more with `Retry-After`
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Never retries
+    pub const NONE: RetryPolicy = RetryPolicy {
+        max_attempts: 1,
+        base_delay: Duration::ZERO,
+        max_delay: Duration::ZERO,
+    };
+
+    /// The delay after the `attempt`th failed attempt, counting from 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(2),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_RetryPolicy_backoff_doubles_up_to_max_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_secs(2));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(2));
+        assert_eq!(RetryPolicy::NONE.backoff(1), Duration::ZERO);
+    }
+}
@@ -0,0 +1,446 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+use std::{fmt, str::FromStr};
+
+/// Decimal places every [`Price`], [`Quantity`] and [`Amount`] is stored with. An instrument's [`Precision`] may allow fewer.
+pub const DECIMALS: u32 = 6;
+
+/// The raw value of one whole unit
+const ONE: u64 = 10u64.pow(DECIMALS);
+
+/// Why a decimal couldn't be read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseDecimalError(String);
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+/// Reads a non-negative decimal like `12.5` into its raw value
+fn parse(s: &str) -> Result<u64, ParseDecimalError> {
+    let invalid = || ParseDecimalError(format!("'{}' isn't a non-negative decimal", s));
+    let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if whole.is_empty() || !whole.bytes().all(|b| b.is_ascii_digit()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if s.ends_with('.') {
+        return Err(invalid());
+    }
+    if fraction.len() > DECIMALS as usize {
+        return Err(ParseDecimalError(format!("'{}' has more than {} decimals", s, DECIMALS)));
+    }
+    let too_large = || ParseDecimalError(format!("'{}' is too large", s));
+    let whole: u64 = whole.parse().map_err(|_| too_large())?;
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u64>().map_err(|_| invalid())? * 10u64.pow(DECIMALS - fraction.len() as u32)
+    };
+    whole
+        .checked_mul(ONE)
+        .and_then(|raw| raw.checked_add(fraction))
+        .ok_or_else(too_large)
+}
+
+/// Writes a raw value with as few decimals as needed
+fn format(raw: u64, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let (whole, fraction) = (raw / ONE, raw % ONE);
+    if fraction == 0 {
+        return write!(f, "{}", whole);
+    }
+    let fraction = format!("{:0width$}", fraction, width = DECIMALS as usize);
+    write!(f, "{}.{}", whole, fraction.trim_end_matches('0'))
+}
+
+/// Accepts decimal strings and, for whole units, non-negative integers
+struct Visitor;
+
+impl de::Visitor<'_> for Visitor {
+    type Value = u64;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a decimal string with up to {} decimals, or a whole number", DECIMALS)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<u64, E> {
+        parse(v).map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<u64, E> {
+        v.checked_mul(ONE)
+            .ok_or_else(|| E::custom(format!("{} is too large", v)))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<u64, E> {
+        u64::try_from(v)
+            .map_err(|_| E::custom(format!("{} is negative", v)))
+            .and_then(|v| self.visit_u64(v))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<u64, E> {
+        Err(E::custom(format!("{} is a float, send decimals as a string", v)))
+    }
+}
+
+macro_rules! decimal {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        /// Exact to a millionth, written to JSON as a string like `"10.25"`. Whole numbers are accepted as well.
+        #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        #[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+        #[cfg_attr(feature = "openapi", schema(value_type = String, example = "10.25"))]
+        pub struct $name(u64);
+
+        impl $name {
+            pub const ZERO: $name = $name(0);
+            pub const MAX: $name = $name(u64::MAX);
+
+            /// This many whole units.
+            /// # Panics
+            /// If the value doesn't fit, use [`FromStr`] for untrusted input
+            pub const fn units(units: u64) -> Self {
+                match units.checked_mul(ONE) {
+                    Some(raw) => $name(raw),
+                    None => panic!("too many units"),
+                }
+            }
+
+            /// The value with this many millionths of a unit
+            pub const fn from_raw(raw: u64) -> Self {
+                $name(raw)
+            }
+
+            /// Millionths of a unit
+            pub const fn raw(self) -> u64 {
+                self.0
+            }
+
+            pub const fn is_zero(self) -> bool {
+                self.0 == 0
+            }
+
+            /// Decimal places in use, 0 for whole units
+            pub const fn decimals(self) -> u32 {
+                let mut fraction = self.0 % ONE;
+                if fraction == 0 {
+                    return 0;
+                }
+                let mut decimals = DECIMALS;
+                while fraction % 10 == 0 {
+                    fraction /= 10;
+                    decimals -= 1;
+                }
+                decimals
+            }
+
+            /// `None` on overflow
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                self.0.checked_add(other.0).map($name)
+            }
+
+            /// `None` if `other` is larger
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map($name)
+            }
+
+            pub fn saturating_add(self, other: Self) -> Self {
+                $name(self.0.saturating_add(other.0))
+            }
+
+            pub fn saturating_sub(self, other: Self) -> Self {
+                $name(self.0.saturating_sub(other.0))
+            }
+
+            /// Approximately, for statistics
+            pub fn to_f64(self) -> f64 {
+                self.0 as f64 / ONE as f64
+            }
+        }
+
+        /// Saturates instead of overflowing
+        impl std::iter::Sum for $name {
+            fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                iter.fold($name::ZERO, $name::saturating_add)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                format(self.0, f)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                format(self.0, f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseDecimalError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                parse(s).map($name)
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_any(Visitor).map($name)
+            }
+        }
+    };
+}
+
+decimal! {
+    /// What one unit costs.
+    Price
+}
+
+decimal! {
+    /// A number of units to trade.
+    Quantity
+}
+
+decimal! {
+    /// Money: balances, transfers and the value of trades.
+    Amount
+}
+
+impl Price {
+    /// The value of `quantity` units at this price, rounded down to a millionth. `None` on overflow.
+    pub fn checked_mul(self, quantity: Quantity) -> Option<Amount> {
+        u64::try_from(self.0 as u128 * quantity.0 as u128 / ONE as u128)
+            .ok()
+            .map(Amount)
+    }
+
+    /// Halfway between this price and `other`, rounded down to a millionth
+    pub fn midpoint(self, other: Price) -> Price {
+        Price(self.0 / 2 + other.0 / 2 + (self.0 % 2 + other.0 % 2) / 2)
+    }
+}
+
+impl Amount {
+    /// The price of one unit if `quantity` units cost this amount, rounded down. `None` without a quantity.
+    pub fn checked_div(self, quantity: Quantity) -> Option<Price> {
+        if quantity.is_zero() {
+            return None;
+        }
+        u64::try_from(self.0 as u128 * ONE as u128 / quantity.0 as u128)
+            .ok()
+            .map(Price)
+    }
+
+}
+
+/// How many decimals an instrument's prices and quantities may have. As long as they add up to no more than
+/// [`DECIMALS`], the value of a trade is exact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Precision {
+    /// Decimals of prices
+    pub price: u32,
+    /// Decimals of quantities
+    pub quantity: u32,
+}
+
+impl Default for Precision {
+    fn default() -> Self {
+        Precision { price: 2, quantity: 4 }
+    }
+}
+
+impl Precision {
+    /// Checks that trade values can be exact
+    pub fn validate(&self) -> Result<(), String> {
+        if self.price + self.quantity > DECIMALS {
+            return Err(format!(
+                "price and quantity decimals ({} + {}) can't exceed {}",
+                self.price, self.quantity, DECIMALS
+            ));
+        }
+        Ok(())
+    }
+
+    /// Checks that an order's `price` and `quantity` have no more decimals than allowed
+    pub fn check(&self, price: Price, quantity: Quantity) -> Result<(), String> {
+        if price.decimals() > self.price {
+            return Err(format!("price {} has more than {} decimals", price, self.price));
+        }
+        if quantity.decimals() > self.quantity {
+            return Err(format!("quantity {} has more than {} decimals", quantity, self.quantity));
+        }
+        Ok(())
+    }
+
+    /// Reads a price with no more decimals than allowed
+    pub fn parse_price(&self, s: &str) -> Result<Price, ParseDecimalError> {
+        let price: Price = s.parse()?;
+        limit(s, price.decimals(), self.price)?;
+        Ok(price)
+    }
+
+    /// Reads a quantity with no more decimals than allowed
+    pub fn parse_quantity(&self, s: &str) -> Result<Quantity, ParseDecimalError> {
+        let quantity: Quantity = s.parse()?;
+        limit(s, quantity.decimals(), self.quantity)?;
+        Ok(quantity)
+    }
+
+    /// Writes a price with all the decimals allowed, e.g. `10.50`
+    pub fn format_price(&self, price: Price) -> String {
+        fixed(price.raw(), self.price.max(price.decimals()))
+    }
+
+    /// Writes a quantity with all the decimals allowed, e.g. `1.2500`
+    pub fn format_quantity(&self, quantity: Quantity) -> String {
+        fixed(quantity.raw(), self.quantity.max(quantity.decimals()))
+    }
+}
+
+/// Rejects what was read from `s` if it has more `decimals` than `allowed`
+fn limit(s: &str, decimals: u32, allowed: u32) -> Result<(), ParseDecimalError> {
+    if decimals > allowed {
+        return Err(ParseDecimalError(format!("'{}' has more than {} decimals", s, allowed)));
+    }
+    Ok(())
+}
+
+/// Writes a raw value with exactly `decimals` decimals, at most [`DECIMALS`]
+fn fixed(raw: u64, decimals: u32) -> String {
+    let (whole, fraction) = (raw / ONE, raw % ONE);
+    if decimals == 0 {
+        return whole.to_string();
+    }
+    let decimals = decimals.min(DECIMALS);
+    let fraction = fraction / 10u64.pow(DECIMALS - decimals);
+    format!("{}.{:0width$}", whole, fraction, width = decimals as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use serde::de::{IntoDeserializer, value::Error};
+
+    fn deserialize<'de>(deserializer: impl Deserializer<'de, Error = Error>) -> Result<Price, Error> {
+        Price::deserialize(deserializer)
+    }
+
+    #[test]
+    fn test_Price_parse_and_format_round_trip() {
+        for s in ["0", "1", "12.5", "10.25", "0.000001", "18446744073709.551615"] {
+            assert_eq!(s.parse::<Price>().unwrap().to_string(), s);
+        }
+        // Trailing and leading zeros aren't kept
+        assert_eq!("10.50".parse::<Price>().unwrap().to_string(), "10.5");
+        assert_eq!("007".parse::<Price>().unwrap(), Price::units(7));
+        assert_eq!("12.5".parse::<Amount>().unwrap().raw(), 12_500_000);
+        assert_eq!(Quantity::from_raw(1).to_string(), "0.000001");
+    }
+
+    #[test]
+    fn test_Price_parse_rejects_too_many_decimals() {
+        assert!("0.0000001".parse::<Price>().is_err());
+        assert!("1.1234567".parse::<Quantity>().is_err());
+        assert_eq!("1.123456".parse::<Quantity>().unwrap().decimals(), 6);
+    }
+
+    #[test]
+    fn test_Price_parse_rejects_overflow() {
+        assert!("18446744073709.551616".parse::<Price>().is_err());
+        assert!("18446744073710".parse::<Price>().is_err());
+        assert!("99999999999999999999".parse::<Price>().is_err());
+        assert!(deserialize(u64::MAX.into_deserializer()).is_err());
+        assert_eq!(deserialize(18_446_744_073_709u64.into_deserializer()), Ok(Price::units(18_446_744_073_709)));
+    }
+
+    #[test]
+    fn test_Price_parse_rejects_negative_and_malformed_values() {
+        for s in ["-1", "-0.5", "", ".", ".5", "1.", "1.2.3", "1e3", " 1", "+1"] {
+            assert!(s.parse::<Price>().is_err(), "{}", s);
+        }
+        assert!(deserialize((-1i64).into_deserializer()).is_err());
+        assert!(deserialize(1.5f64.into_deserializer()).is_err());
+        assert_eq!(deserialize("10.25".into_deserializer()), Ok("10.25".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_Price_parse_accepts_zero() {
+        for s in ["0", "0.0", "0.000000"] {
+            let zero: Price = s.parse().unwrap();
+            assert!(zero.is_zero());
+            assert_eq!(zero.decimals(), 0);
+            assert_eq!(zero.to_string(), "0");
+        }
+        assert_eq!(deserialize(0i64.into_deserializer()), Ok(Price::ZERO));
+    }
+
+    #[test]
+    fn test_Price_checked_mul_rounds_down() {
+        let price = |s: &str| s.parse::<Price>().unwrap();
+        let quantity = |s: &str| s.parse::<Quantity>().unwrap();
+        // Exact as long as the decimals add up to no more than six
+        assert_eq!(price("10.25").checked_mul(quantity("0.0004")), Some("0.0041".parse().unwrap()));
+        // 0.4999995 and 0.0000005
+        assert_eq!(price("1.5").checked_mul(quantity("0.333333")), Some("0.499999".parse().unwrap()));
+        assert_eq!(price("0.000001").checked_mul(quantity("0.5")), Some(Amount::ZERO));
+        assert_eq!(Price::MAX.checked_mul(Quantity::units(1)), Some(Amount::MAX));
+        assert_eq!(Price::MAX.checked_mul(Quantity::units(2)), None);
+    }
+
+    #[test]
+    fn test_Amount_checked_div_rounds_down() {
+        assert_eq!(Amount::units(1).checked_div(Quantity::units(3)), Some("0.333333".parse().unwrap()));
+        assert_eq!(Amount::units(1).checked_div(Quantity::ZERO), None);
+    }
+
+    #[test]
+    fn test_Precision_validate_limits_the_total_decimals() {
+        assert!(Precision::default().validate().is_ok());
+        assert!(Precision { price: 6, quantity: 0 }.validate().is_ok());
+        assert!(Precision { price: 3, quantity: 4 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_Precision_check_and_parse_reject_extra_decimals() {
+        let precision = Precision { price: 2, quantity: 3 };
+        assert!(precision.check("10.25".parse().unwrap(), "1.125".parse().unwrap()).is_ok());
+        assert!(precision.check("10.255".parse().unwrap(), Quantity::units(1)).is_err());
+        assert!(precision.check(Price::units(10), "1.0005".parse().unwrap()).is_err());
+
+        // Trailing zeros don't count
+        assert_eq!(precision.parse_price("10.500"), Ok("10.5".parse().unwrap()));
+        assert!(precision.parse_price("10.005").is_err());
+        assert!(precision.parse_price("-1").is_err());
+        assert_eq!(precision.parse_quantity("0.001"), Ok(Quantity::from_raw(1_000)));
+        assert!(precision.parse_quantity("0.0001").is_err());
+    }
+
+    #[test]
+    fn test_Precision_format_pads_to_the_allowed_decimals() {
+        let precision = Precision { price: 2, quantity: 4 };
+        assert_eq!(precision.format_price(Price::units(10)), "10.00");
+        assert_eq!(precision.format_price("10.5".parse().unwrap()), "10.50");
+        assert_eq!(precision.format_quantity("1.25".parse().unwrap()), "1.2500");
+        // Values from before a precision change keep their decimals
+        assert_eq!(precision.format_price("0.125".parse().unwrap()), "0.125");
+        assert_eq!(Precision { price: 0, quantity: 0 }.format_price(Price::units(10)), "10");
+
+        for s in ["0.00", "10.25", "18446744073709.55"] {
+            assert_eq!(precision.format_price(precision.parse_price(s).unwrap()), s);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::decimal::{Amount, Precision, Price, Quantity};
+use super::types::Order;
+use crate::errors::ApplicationError;
+use serde::{Deserialize, Serialize};
+
+/// Whether an instrument accepts orders
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum InstrumentStatus {
+    /// Orders are accepted
+    #[default]
+    Active,
+    /// New orders are rejected, resting orders can still be cancelled
+    Suspended,
+}
+
+/// Reference data of the traded instrument: what it is and which orders are allowed
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Instrument {
+    /// Unique name, e.g. `BTC-USD`
+    pub symbol: String,
+    /// The asset that's bought and sold
+    pub base: String,
+    /// The asset prices and balances are in
+    pub quote: String,
+    /// Prices must be a multiple of this
+    pub tick_size: Price,
+    /// Amounts must be a multiple of this
+    pub lot_size: Quantity,
+    /// Decimals of prices and amounts, tick and lot size can't have more
+    #[serde(default)]
+    pub precision: Precision,
+    /// Smallest amount of an order
+    #[serde(default)]
+    pub min_quantity: Quantity,
+    /// Largest amount of an order, unlimited if not set
+    #[serde(default)]
+    pub max_quantity: Option<Quantity>,
+    /// Smallest value (price times amount) of an order
+    #[serde(default)]
+    pub min_notional: Amount,
+    /// How far, in basis points, prices may be from the last trade. Unlimited if not set or before the first trade.
+    #[serde(default)]
+    pub price_band_bps: Option<u64>,
+    #[serde(default)]
+    pub status: InstrumentStatus,
+}
+
+impl Instrument {
+    /// Checks that the definition is consistent. The [`Precision`] must keep the value of every trade exact,
+    /// and tick and lot size may not have more decimals than it allows.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.symbol.trim().is_empty() {
+            return Err("the symbol can't be empty".to_string());
+        }
+        if self.tick_size.is_zero() || self.lot_size.is_zero() {
+            return Err("tick and lot size must be positive".to_string());
+        }
+        self.precision.validate()?;
+        if self.tick_size.decimals() > self.precision.price {
+            return Err(format!(
+                "tick size {} has more than {} decimals",
+                self.tick_size, self.precision.price
+            ));
+        }
+        if self.lot_size.decimals() > self.precision.quantity {
+            return Err(format!(
+                "lot size {} has more than {} decimals",
+                self.lot_size, self.precision.quantity
+            ));
+        }
+        if let Some(max) = self.max_quantity
+            && max < self.min_quantity
+        {
+            return Err(format!("max_quantity {} is below min_quantity {}", max, self.min_quantity));
+        }
+        if self.price_band_bps.is_some_and(|bps| bps > 10_000) {
+            return Err("the price band can't exceed 10000 bps".to_string());
+        }
+        Ok(())
+    }
+
+    /// The lowest and highest price allowed around the `last` trade's price, `None` without a band
+    pub fn band(&self, last: Price) -> Option<(Price, Price)> {
+        let bps = self.price_band_bps?;
+        let width = Price::from_raw((last.raw() as u128 * bps as u128 / 10_000) as u64);
+        Some((last.saturating_sub(width), last.saturating_add(width)))
+    }
+
+    /// Checks an order against the instrument, with the price of the `last` trade for the price band
+    /// # Errors
+    /// The first rule the order breaks
+    pub fn check(&self, order: &Order, last: Option<Price>) -> Result<(), ApplicationError> {
+        if self.status != InstrumentStatus::Active {
+            return Err(ApplicationError::InstrumentSuspended(self.symbol.clone()));
+        }
+        if order.price.is_zero() || !order.price.raw().is_multiple_of(self.tick_size.raw()) {
+            return Err(ApplicationError::PriceOffTick(order.price, self.tick_size));
+        }
+        if !order.amount.raw().is_multiple_of(self.lot_size.raw()) {
+            return Err(ApplicationError::QuantityOffLot(order.amount, self.lot_size));
+        }
+        let max = self.max_quantity.unwrap_or(Quantity::MAX);
+        if order.amount.is_zero() || order.amount < self.min_quantity || order.amount > max {
+            return Err(ApplicationError::QuantityOutOfRange(order.amount, self.min_quantity, max));
+        }
+        // Overflowing values are rejected by the platform
+        if let Some(value) = order.price.checked_mul(order.amount)
+            && value < self.min_notional
+        {
+            return Err(ApplicationError::NotionalTooSmall(value, self.min_notional));
+        }
+        if let Some((low, high)) = last.and_then(|last| self.band(last))
+            && (order.price < low || order.price > high)
+        {
+            return Err(ApplicationError::PriceOutsideBand(order.price, low, high));
+        }
+        Ok(())
+    }
+}
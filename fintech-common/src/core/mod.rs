@@ -1 +1,4 @@
+pub mod decimal;
+pub mod instrument;
+pub mod session;
 pub mod types;
\ No newline at end of file
@@ -0,0 +1,94 @@
+use crate::errors::ApplicationError;
+use serde::{Deserialize, Serialize};
+
+/// The phases of a trading day
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum SessionState {
+    /// Before the open: resting orders can be cancelled, new orders are rejected
+    PreOpen,
+    /// Orders match as they arrive
+    #[default]
+    Continuous,
+    /// A call auction: orders are collected without matching and execute together at one price
+    /// once the book moves on
+    Auction,
+    /// Stopped by an admin: resting orders can be cancelled, new orders are rejected
+    Halted,
+    /// After the close: the book is frozen
+    Closed,
+}
+
+impl SessionState {
+    /// Whether new orders are accepted
+    pub fn accepts_orders(self) -> bool {
+        matches!(self, SessionState::Continuous | SessionState::Auction)
+    }
+
+    /// Whether resting orders can be cancelled
+    pub fn accepts_cancels(self) -> bool {
+        self != SessionState::Closed
+    }
+}
+
+/// The session of the book and whether the whole venue is halted. Decides which orders and cancels
+/// are accepted, see [`SessionState`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct TradingSession {
+    /// The book's current state
+    pub state: SessionState,
+    /// While halted, the state a resume returns to. The schedule keeps moving it on.
+    #[serde(default)]
+    pub resume_to: Option<SessionState>,
+    /// Nothing is accepted while the venue is halted, not even cancels
+    #[serde(default)]
+    pub venue_halted: bool,
+}
+
+impl TradingSession {
+    /// Moves the book to `state`. While halted, the book stays halted and resumes to `state`.
+    pub fn set_state(&mut self, state: SessionState) {
+        match (self.state, state) {
+            (_, SessionState::Halted) => self.halt(),
+            (SessionState::Halted, _) => self.resume_to = Some(state),
+            _ => self.state = state,
+        }
+    }
+
+    /// Halts the book, remembering the state to resume to
+    pub fn halt(&mut self) {
+        if self.state != SessionState::Halted {
+            self.resume_to = Some(self.state);
+            self.state = SessionState::Halted;
+        }
+    }
+
+    /// Returns the book to the state it was halted in, or the one the schedule moved on to since
+    pub fn resume(&mut self) {
+        if self.state == SessionState::Halted {
+            self.state = self.resume_to.take().unwrap_or_default();
+        }
+    }
+
+    /// The state orders and cancels are checked against, [`SessionState::Halted`] while the venue is
+    pub fn effective_state(&self) -> SessionState {
+        if self.venue_halted { SessionState::Halted } else { self.state }
+    }
+
+    /// Fails unless new orders are accepted
+    pub fn check_order(&self) -> Result<(), ApplicationError> {
+        match self.effective_state() {
+            state if state.accepts_orders() => Ok(()),
+            state => Err(ApplicationError::MarketNotOpen(state)),
+        }
+    }
+
+    /// Fails unless resting orders can be cancelled
+    pub fn check_cancel(&self) -> Result<(), ApplicationError> {
+        if self.venue_halted || !self.state.accepts_cancels() {
+            return Err(ApplicationError::MarketNotOpen(self.effective_state()));
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
 use std::cmp::Reverse;
 use serde::{Deserialize, Serialize};
 
+pub use super::decimal::{Amount, DECIMALS, ParseDecimalError, Precision, Price, Quantity};
+pub use super::instrument::{Instrument, InstrumentStatus};
+pub use super::session::{SessionState, TradingSession};
+
 /// Simplified side of a position as well as order.
 #[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Ord , Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum Side {
     /// Want to buy
     Buy,
@@ -12,11 +17,12 @@ pub enum Side {
 
 /// An order for a specified symbol to buy or sell an amount at a given price.
 #[derive(Clone, PartialEq, Eq , Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Order {
     /// Max/min price (depending on the side)
-    pub price: u64,
+    pub price: Price,
     /// Number of units to trade
-    pub amount: u64,
+    pub amount: Quantity,
     /// The side of the order book (buy or sell)
     pub side: Side,
     /// The account signer
@@ -26,7 +32,7 @@ pub struct Order {
 impl Order {
 
     /// Convert an [`Order`] into a [`PartialOrder`] with the added parameters
-    pub fn into_partial_order(self, ordinal: u64, remaining: u64) -> PartialOrder {
+    pub fn into_partial_order(self, ordinal: u64, remaining: Quantity) -> PartialOrder {
         let Order {
             price,
             amount,
@@ -46,13 +52,14 @@ impl Order {
 
 /// A position represents an unfilled order that is kept in the system for later filling.
 #[derive(Clone, PartialEq, Debug, Eq, Ord , Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct PartialOrder {
     /// Price per unit
-    pub price: u64,
+    pub price: Price,
     /// Initial number of units in the order
-    pub amount: u64,
+    pub amount: Quantity,
     /// Remaining number of units after potential matches
-    pub remaining: u64,
+    pub remaining: Quantity,
     /// Buy or sell side of the book
     pub side: Side,
     /// Signer of the order
@@ -61,27 +68,369 @@ pub struct PartialOrder {
     pub ordinal: u64,
 }
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct AccountUpdateRequest { 
     /// The account to update
     pub account: String,
     /// The amount to add or remove
-    pub amount: u64,
+    pub amount: Amount,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct AccountBalanceRequest {
     /// The account to check the balance of
     pub account: String,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SendRequest {
     /// The sender account
     pub sender: String,
     /// The recipient account
     pub recipient: String,
     /// The amount to send
-    pub amount: u64,
+    pub amount: Amount,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct CancelRequest {
+    /// The account that placed the order
+    pub signer: String,
+    /// The order's ordinal from its receipt
+    pub ordinal: u64,
+}
+
+/// Which part of the venue to halt or resume
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct HaltRequest {
+    /// The listed instrument's symbol, the whole venue if not set
+    #[serde(default)]
+    pub symbol: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct SessionRequest {
+    /// The state to move the book to
+    pub state: SessionState,
+}
+
+/// An execution between a resting (maker) order and an incoming (taker) order.
+/// Trades don't reveal who traded.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Trade {
+    /// Sequential id, the first trade is 1
+    pub id: u64,
+    /// The maker's price, or the price of the auction that executed it
+    pub price: Price,
+    /// Number of units traded
+    pub amount: Quantity,
+    /// Ordinal of the resting order
+    pub maker_ordinal: u64,
+    /// Ordinal of the incoming order
+    pub taker_ordinal: u64,
+    /// The side of the incoming order. In an auction, the side of the order that arrived later.
+    pub aggressor: Side,
+    /// When the trade happened, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+/// All resting orders at one price, without revealing who placed them.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PriceLevel {
+    /// Price per unit
+    pub price: Price,
+    /// Open units of all orders at this price
+    pub amount: Quantity,
+    /// Number of resting orders at this price
+    pub orders: u64,
+}
+
+/// Aggregated price levels of both sides of the book, best prices first.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Depth {
+    /// Highest price first
+    pub bids: Vec<PriceLevel>,
+    /// Lowest price first
+    pub asks: Vec<PriceLevel>,
+}
+
+/// The best bid and offer (BBO) and what follows from them.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BookTop {
+    /// The highest bid, if any
+    pub bid: Option<PriceLevel>,
+    /// The lowest ask, if any
+    pub ask: Option<PriceLevel>,
+    /// Ask minus bid, 0 if a signer's own orders cross. Needs both sides.
+    pub spread: Option<Price>,
+    /// Halfway between bid and ask, rounded down to a millionth. Needs both sides.
+    pub mid: Option<Price>,
+}
+
+impl BookTop {
+    /// Derives spread and mid from the best levels of each side
+    pub fn new(bid: Option<PriceLevel>, ask: Option<PriceLevel>) -> Self {
+        let (spread, mid) = match (&bid, &ask) {
+            (Some(bid), Some(ask)) => (
+                Some(ask.price.saturating_sub(bid.price)),
+                Some(bid.price.midpoint(ask.price)),
+            ),
+            _ => (None, None),
+        };
+        BookTop { bid, ask, spread, mid }
+    }
+}
+
+impl Depth {
+    /// The best levels of each side
+    pub fn top(&self) -> BookTop {
+        BookTop::new(self.bids.first().cloned(), self.asks.first().cloned())
+    }
+
+    /// Keeps at most `levels` price levels per side
+    pub fn truncated(&self, levels: usize) -> Depth {
+        Depth {
+            bids: self.bids.iter().take(levels).cloned().collect(),
+            asks: self.asks.iter().take(levels).cloned().collect(),
+        }
+    }
+}
+
+/// A resting order as anyone may see it, without its signer.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BookOrder {
+    /// An id only the venue can tie to the order's ordinal, so it says nothing about who placed it.
+    /// It stays the same while the order rests and is new after a restart.
+    pub order_id: u64,
+    /// Buy or sell side of the book
+    pub side: Side,
+    /// Price per unit
+    pub price: Price,
+    /// Units still open
+    pub remaining: Quantity,
+    /// Orders ahead of this one at the same price, 0 is filled next
+    pub queue_position: u64,
+}
+
+/// Every resting order (level 3), best prices first and in queue order within a price.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct L3Book {
+    pub bids: Vec<BookOrder>,
+    pub asks: Vec<BookOrder>,
+}
+
+/// A change to the resting orders. Orders can't be amended, a partial execution is the only
+/// way an order's open amount changes.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BookEvent {
+    /// An order started resting at the back of its price's queue
+    Add {
+        order_id: u64,
+        side: Side,
+        price: Price,
+        amount: Quantity,
+    },
+    /// A resting order traded at its price, or at the price of an auction. It leaves the book when
+    /// nothing is `remaining`.
+    Execute {
+        order_id: u64,
+        amount: Quantity,
+        remaining: Quantity,
+    },
+    /// A resting order was cancelled
+    Delete { order_id: u64 },
+}
+
+/// The [`BookEvent`]s of one command, in the order they happened.
+/// Apply the updates with a `sequence` after the one an [`L3Book`] was read at; if the first
+/// of those has a `previous` after that, updates were missed and the book has to be read again.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct BookUpdate {
+    /// Sequence number of the command
+    pub sequence: u64,
+    /// Sequence number of the previous update
+    pub previous: u64,
+    pub events: Vec<BookEvent>,
+}
+
+/// The single price a call auction executes at and what it leaves over.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Uncross {
+    /// The price every execution happens at
+    pub price: Price,
+    /// Units that execute, the most any price allows
+    pub volume: Quantity,
+    /// Units willing to trade at `price` that don't execute
+    pub surplus: Quantity,
+    /// The side with the surplus, `None` without one
+    pub surplus_side: Option<Side>,
+}
+
+/// The call auction running on the book, if any.
+#[derive(Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Auction {
+    /// Whether orders are collected without matching
+    pub active: bool,
+    /// What the auction would execute if it ended now, `None` if no orders cross
+    pub indicative: Option<Uncross>,
+}
+
+/// The length of a [`Candle`]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum Interval {
+    #[serde(rename = "1s")]
+    OneSecond,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+    #[serde(rename = "1d")]
+    OneDay,
+}
+
+impl Interval {
+    /// Every interval, shortest first
+    pub const ALL: [Interval; 5] = [
+        Interval::OneSecond,
+        Interval::OneMinute,
+        Interval::FiveMinutes,
+        Interval::OneHour,
+        Interval::OneDay,
+    ];
+
+    /// The length in milliseconds
+    pub fn millis(self) -> u64 {
+        match self {
+            Interval::OneSecond => 1_000,
+            Interval::OneMinute => 60_000,
+            Interval::FiveMinutes => 300_000,
+            Interval::OneHour => 3_600_000,
+            Interval::OneDay => 86_400_000,
+        }
+    }
+
+    /// The start of the interval `timestamp` falls into, intervals are aligned to the Unix epoch (UTC)
+    pub fn start_of(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.millis()
+    }
+
+    /// The name used in queries, like `1m`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Interval::OneSecond => "1s",
+            Interval::OneMinute => "1m",
+            Interval::FiveMinutes => "5m",
+            Interval::OneHour => "1h",
+            Interval::OneDay => "1d",
+        }
+    }
+}
+
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Open, high, low, close and volume (OHLCV) of the trades in one [`Interval`].
+/// An interval without trades repeats the previous close with no volume.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Candle {
+    /// When the interval starts, in milliseconds since the Unix epoch
+    pub start: u64,
+    /// Price of the first trade
+    pub open: Price,
+    /// Highest price traded
+    pub high: Price,
+    /// Lowest price traded
+    pub low: Price,
+    /// Price of the last trade
+    pub close: Price,
+    /// Units traded
+    pub volume: Quantity,
+    /// Number of trades
+    pub trades: u64,
+}
+
+impl Candle {
+    /// A candle of a single trade
+    pub fn new(start: u64, price: Price, amount: Quantity) -> Self {
+        Candle {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: amount,
+            trades: 1,
+        }
+    }
+
+    /// A candle without trades, all prices at the previous `close`
+    pub fn flat(start: u64, close: Price) -> Self {
+        Candle {
+            start,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: Quantity::ZERO,
+            trades: 0,
+        }
+    }
+
+    /// Adds a later trade
+    pub fn add(&mut self, price: Price, amount: Quantity) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume = self.volume.saturating_add(amount);
+        self.trades += 1;
+    }
+}
+
+/// Statistics of the trades in the last 24 hours. Prices other than `last` are `None` without trades in that time.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Ticker {
+    /// Price of the latest trade, even if it's older than 24 hours
+    pub last: Option<Price>,
+    /// Price of the first trade in the last 24 hours
+    pub open: Option<Price>,
+    /// Highest price traded
+    pub high: Option<Price>,
+    /// Lowest price traded
+    pub low: Option<Price>,
+    /// Units traded
+    pub volume: Quantity,
+    /// Price times units of all trades
+    pub quote_volume: Amount,
+    /// Volume weighted average price, `quote_volume / volume`
+    pub vwap: Option<Price>,
+    /// Number of trades
+    pub trades: u64,
+    /// Change from `open` to `last` in percent
+    pub change_percent: Option<f64>,
 }
 
 impl PartialOrd for PartialOrder {
@@ -93,19 +442,41 @@ impl PartialOrd for PartialOrder {
 
 /// A receipt issued to the caller for accepting an [`Order`]
 #[derive(Clone, PartialOrd, PartialEq, Eq, Debug , Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Receipt {
     /// Sequence number
     pub ordinal: u64,
 
     /// Matches that happened immediately
     pub matches: Vec<PartialOrder>,
+
+    /// The fees of each match, in the same order
+    #[serde(default)]
+    pub fees: Vec<FillFee>,
+}
+
+/// The fees both sides paid for one match
+#[derive(Clone, PartialOrd, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FillFee {
+    /// Ordinal of the resting order
+    pub maker_ordinal: u64,
+    /// What the resting order's signer paid
+    pub maker_fee: Amount,
+    /// What the resting order's signer was paid instead of a fee
+    pub maker_rebate: Amount,
+    /// What the incoming order's signer paid
+    pub taker_fee: Amount,
 }
 
 impl PartialOrder {
     
     /// Splits one [`PartialOrder`] into two by taking a defined `take` amount
-    pub fn take_from(pos: &mut PartialOrder, take: u64, price: u64) -> PartialOrder {
-        pos.remaining -= take;
+    pub fn take_from(pos: &mut PartialOrder, take: Quantity, price: Price) -> PartialOrder {
+        pos.remaining = pos
+            .remaining
+            .checked_sub(take)
+            .expect("only what remains is taken");
         let mut new = pos.clone();
         new.amount = take;
         new.price = price;
@@ -1,20 +1,133 @@
 use core::error;
-use std::fmt::Debug;
+use crate::core::types::{Amount, Price, Quantity, SessionState};
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Debug};
 use warp::reject::Reject;
 
 /// An application-specific error type
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum ApplicationError {
     /// Account wasn't found
     AccountNotFound(String),
 
     /// Not enough currency in the account (underflow)
-    AccountUnderFunded(String, u64),
+    AccountUnderFunded(String, Amount),
 
     /// Too much currency in the account (overflow)
-    AccountOverFunded(String, u64),
+    AccountOverFunded(String, Amount),
 
-    
+    /// The platform can't accept requests right now (e.g. it's shutting down)
+    Unavailable(String),
+
+    /// No resting order with this ordinal belongs to the signer
+    OrderNotFound(u64),
+
+    /// The idempotency key was already used for a different request
+    IdempotencyKeyReused(String),
+
+    /// The order's price or amount isn't allowed, e.g. it has too many decimals
+    InvalidOrder(String),
+
+    /// The instrument doesn't accept orders
+    InstrumentSuspended(String),
+
+    /// The price isn't a multiple of the tick size (price, tick size)
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    PriceOffTick(Price, Price),
+
+    /// The amount isn't a multiple of the lot size (amount, lot size)
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    QuantityOffLot(Quantity, Quantity),
+
+    /// The amount is below the minimum or above the maximum order size (amount, min, max)
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    QuantityOutOfRange(Quantity, Quantity, Quantity),
+
+    /// The order's value is below the minimum notional (value, min)
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    NotionalTooSmall(Amount, Amount),
+
+    /// The price is too far from the last trade (price, lowest, highest)
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    PriceOutsideBand(Price, Price, Price),
+
+    /// No instrument with this symbol is listed
+    InstrumentNotFound(String),
+
+    /// An instrument is already listed, the platform trades one
+    InstrumentExists(String),
+
+    /// The instrument definition is inconsistent
+    InvalidInstrument(String),
+
+    /// The session doesn't accept the order or cancel right now
+    MarketNotOpen(SessionState),
+
+    /// The account is run by the platform, e.g. the one fees are credited to
+    ReservedAccount(String),
+}
+
+impl fmt::Display for ApplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApplicationError::AccountNotFound(account) => write!(f, "Account '{}' not found", account),
+            ApplicationError::AccountUnderFunded(account, amount) => {
+                write!(f, "Account '{}' can't cover {}", account, amount)
+            }
+            ApplicationError::AccountOverFunded(account, amount) => {
+                write!(f, "Account '{}' can't hold another {}", account, amount)
+            }
+            ApplicationError::Unavailable(reason) => write!(f, "Unavailable: {}", reason),
+            ApplicationError::OrderNotFound(ordinal) => write!(f, "Order {} not found", ordinal),
+            ApplicationError::IdempotencyKeyReused(key) => {
+                write!(f, "Idempotency key '{}' was used for a different request", key)
+            }
+            ApplicationError::InvalidOrder(reason) => write!(f, "Invalid order: {}", reason),
+            ApplicationError::InstrumentSuspended(symbol) => write!(f, "Instrument '{}' is suspended", symbol),
+            ApplicationError::PriceOffTick(price, tick) => {
+                write!(f, "Price {} isn't a multiple of the tick size {}", price, tick)
+            }
+            ApplicationError::QuantityOffLot(amount, lot) => {
+                write!(f, "Amount {} isn't a multiple of the lot size {}", amount, lot)
+            }
+            ApplicationError::QuantityOutOfRange(amount, min, max) => {
+                write!(f, "Amount {} is outside {} to {}", amount, min, max)
+            }
+            ApplicationError::NotionalTooSmall(value, min) => {
+                write!(f, "Order value {} is below the minimum of {}", value, min)
+            }
+            ApplicationError::PriceOutsideBand(price, low, high) => {
+                write!(f, "Price {} is outside the band of {} to {}", price, low, high)
+            }
+            ApplicationError::InstrumentNotFound(symbol) => write!(f, "Instrument '{}' not found", symbol),
+            ApplicationError::InstrumentExists(symbol) => write!(f, "Instrument '{}' is already listed", symbol),
+            ApplicationError::InvalidInstrument(reason) => write!(f, "Invalid instrument: {}", reason),
+            ApplicationError::MarketNotOpen(state) => write!(f, "Not accepted in the {:?} session state", state),
+            ApplicationError::ReservedAccount(account) => write!(f, "Account '{}' is reserved for the platform", account),
+        }
+    }
+}
+
+impl std::error::Error for ApplicationError {}
+
+/// The body of an error response, so clients get the [`ApplicationError`] back
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ErrorResponse {
+    /// What went wrong
+    pub error: ApplicationError,
+    /// A human readable description
+    pub message: String,
+}
+
+impl From<&ApplicationError> for ErrorResponse {
+    fn from(error: &ApplicationError) -> Self {
+        ErrorResponse {
+            error: error.clone(),
+            message: error.to_string(),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -1,13 +1,15 @@
 use std::clone;
 use serde::{Deserialize, Serialize};
 
+use crate::core::types::Amount;
+
 /// A transaction type. Transactions should be able to rebuild a ledger's state
 /// when they are applied in the same sequence to an empty state.
 #[derive(Debug, PartialEq, Eq, Clone , Serialize, Deserialize)]
 pub enum Tx {
     /// Currency was added to the account
-    Deposit { account: String, amount: u64 },
+    Deposit { account: String, amount: Amount },
 
     /// Currency was withdrawn from the account
-    Withdraw { account: String, amount: u64 },
+    Withdraw { account: String, amount: Amount },
 }
@@ -0,0 +1,109 @@
+//! Compares order throughput of a shared `Mutex<TradingPlatform>` with the [`Sequencer`].
+//!
+//! Run with `cargo bench --bench sequencer`.
+use fintech_web::{
+    core::{Amount, Order, Price, Quantity, Side},
+    sequencer::{DEFAULT_QUEUE_CAPACITY, Sequencer},
+    session::{Clock, SystemClock},
+    snapshot::DEFAULT_MAX_STALENESS,
+    trading_platform::TradingPlatform,
+};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const TRADERS: usize = 8;
+const ORDERS_PER_TRADER: usize = 20_000;
+
+fn funded_platform() -> TradingPlatform {
+    let mut platform = TradingPlatform::new();
+    for trader in 0..TRADERS {
+        platform.deposit(&signer(trader), Amount::from_raw(u64::MAX / 2)).unwrap();
+    }
+    platform
+}
+
+fn signer(trader: usize) -> String {
+    format!("TRADER-{}", trader)
+}
+
+/// Alternates sides and walks the price a little so both resting and matching orders occur
+fn order(trader: usize, i: usize) -> Order {
+    Order {
+        price: Price::units(100 + (i % 5) as u64),
+        amount: Quantity::units(1 + (i % 3) as u64),
+        side: if (trader + i).is_multiple_of(2) { Side::Buy } else { Side::Sell },
+        signer: signer(trader),
+    }
+}
+
+/// Runs every trader on its own task and times how long it takes to submit all their orders.
+/// Both variants go through here, so they only differ in how an order is submitted.
+async fn drive<F, Fut>(submit: F) -> Duration
+where
+    F: Fn(Order) -> Fut + Clone + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..TRADERS)
+        .map(|trader| {
+            let submit = submit.clone();
+            tokio::spawn(async move {
+                for i in 0..ORDERS_PER_TRADER {
+                    submit(order(trader, i)).await;
+                    // Handlers interleave like this between requests, otherwise one trader would
+                    // fill the book with orders nobody else gets to match
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    start.elapsed()
+}
+
+async fn bench_mutex() -> Duration {
+    let platform = Arc::new(Mutex::new(funded_platform()));
+    drive(move |order| {
+        let platform = platform.clone();
+        async move {
+            // The sequencer stamps each command with the time as well
+            let now = SystemClock.now_millis();
+            platform.lock().unwrap().order(order, now).unwrap();
+        }
+    })
+    .await
+}
+
+async fn bench_sequencer() -> Duration {
+    let handle = Sequencer::spawn(funded_platform(), DEFAULT_QUEUE_CAPACITY, DEFAULT_MAX_STALENESS);
+    drive(move |order| {
+        let handle = handle.clone();
+        async move {
+            handle.order(order).await.unwrap();
+        }
+    })
+    .await
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let orders = (TRADERS * ORDERS_PER_TRADER) as f64;
+    println!(
+        "{:<10} {:>8} orders in {:>8.2?} = {:>10.0} orders/s",
+        name,
+        orders,
+        elapsed,
+        orders / elapsed.as_secs_f64()
+    );
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    println!("{} traders, {} orders each", TRADERS, ORDERS_PER_TRADER);
+    report("mutex", bench_mutex().await);
+    report("sequencer", bench_sequencer().await);
+}
@@ -1,10 +1,11 @@
-use crate::{errors::ApplicationError, tx::Tx};
-use std::collections::HashMap;
+use crate::{core::Amount, errors::ApplicationError, tx::Tx};
+use im::HashMap;
 
 /// A type for managing accounts and their current currency balance
 #[derive(Debug)]
 pub struct Accounts {
-    accounts: HashMap<String, u64>,
+    /// A persistent map, copies share everything but the accounts changed since
+    accounts: HashMap<String, Amount>,
 }
 
 impl Accounts {
@@ -16,16 +17,21 @@ impl Accounts {
     }
 
     /// Retrieves the balance of an account
-    pub fn balance_of(&self, signer: &str) -> Result<&u64, ApplicationError> {
+    pub fn balance_of(&self, signer: &str) -> Result<&Amount, ApplicationError> {
         self.accounts
             .get(signer)
             .ok_or(ApplicationError::AccountNotFound(signer.to_string()))
     }
 
+    /// Returns a copy of all account balances, cheap to take because it shares structure with the accounts
+    pub fn balances(&self) -> HashMap<String, Amount> {
+        self.accounts.clone()
+    }
+
     /// Either deposits the `amount` provided into the `signer` account or adds the amount to the existing account.
     /// # Errors
     /// Attempted overflow
-    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+    pub fn deposit(&mut self, signer: &str, amount: Amount) -> Result<Tx, ApplicationError> {
         if let Some(account) = self.accounts.get_mut(signer) {
             (*account)
                 .checked_add(amount)
@@ -54,7 +60,7 @@ impl Accounts {
     /// Withdraws the `amount` from the `signer` account.
     /// # Errors
     /// Attempted overflow
-    pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+    pub fn withdraw(&mut self, signer: &str, amount: Amount) -> Result<Tx, ApplicationError> {
         if let Some(account) = self.accounts.get_mut(signer) {
             (*account)
                 .checked_sub(amount)
@@ -84,7 +90,7 @@ impl Accounts {
         &mut self,
         sender: &str,
         recipient: &str,
-        amount: u64,
+        amount: Amount,
     ) -> Result<(Tx, Tx), ApplicationError> {
         if self.accounts.contains_key(sender)  // sender exists
             && self.accounts.contains_key(recipient) // recipient exists
@@ -121,13 +127,13 @@ mod tests {
     #[test]
     fn test_accounts_withdraw_underfunded() {
         let mut accounts = Accounts::new();
-        accounts.deposit("a-key", 0).unwrap();
-        let actual = accounts.withdraw("a-key", 100);
+        accounts.deposit("a-key", Amount::ZERO).unwrap();
+        let actual = accounts.withdraw("a-key", Amount::units(100));
         assert_eq!(
             actual,
             Err(ApplicationError::AccountUnderFunded(
                 "a-key".to_string(),
-                100
+                Amount::units(100)
             ))
         );
     }
@@ -136,14 +142,14 @@ mod tests {
     fn test_accounts_deposit_overfunded() {
         let mut accounts = Accounts::new();
         accounts
-            .deposit("a-key", 1)
+            .deposit("a-key", Amount::units(1))
             .expect("Initial deposit failed");
-        let actual = accounts.deposit("a-key", u64::MAX);
+        let actual = accounts.deposit("a-key", Amount::MAX);
         assert_eq!(
             actual,
             Err(ApplicationError::AccountOverFunded(
                 "a-key".to_string(),
-                u64::MAX
+                Amount::MAX
             ))
         );
     }
@@ -151,7 +157,7 @@ mod tests {
     #[test]
     fn test_accounts_deposit_works() {
         let mut accounts = Accounts::new();
-        let amt = 100;
+        let amt = Amount::units(100);
         let actual = accounts.deposit("a-key", amt);
         assert_eq!(
             actual,
@@ -165,7 +171,7 @@ mod tests {
     #[test]
     fn test_accounts_withdraw_works() {
         let mut accounts = Accounts::new();
-        let amt = 100;
+        let amt = Amount::units(100);
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
         let actual = accounts.withdraw("a-key", amt);
         assert_eq!(
@@ -180,11 +186,11 @@ mod tests {
     #[test]
     fn test_accounts_send_works() {
         let mut accounts = Accounts::new();
-        let amt = 100;
+        let amt = Amount::units(100);
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
 
         // creating the receiver is also required
-        accounts.deposit("b-key", 0).expect("Couldn't deposit");
+        accounts.deposit("b-key", Amount::ZERO).expect("Couldn't deposit");
 
         let (tx1, tx2) = accounts.send("a-key", "b-key", amt).expect("Send failed");
         assert_eq!(
@@ -215,16 +221,16 @@ mod tests {
     #[test]
     fn test_accounts_send_underfunded_fails_and_rolls_back() {
         let mut accounts = Accounts::new();
-        let amt = 100;
+        let amt = Amount::units(100);
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
 
         // creating the receiver is also required
-        accounts.deposit("b-key", 0).expect("Couldn't deposit");
+        accounts.deposit("b-key", Amount::ZERO).expect("Couldn't deposit");
 
-        let actual = accounts.send("a-key", "b-key", amt + 1);
+        let actual = accounts.send("a-key", "b-key", amt.saturating_add(Amount::units(1)));
         assert!(actual.is_err());
-        let expected: HashMap<String, u64> =
-            vec![("a-key".to_string(), amt), ("b-key".to_string(), 0)]
+        let expected: HashMap<String, Amount> =
+            vec![("a-key".to_string(), amt), ("b-key".to_string(), Amount::ZERO)]
                 .into_iter()
                 .collect();
         assert_eq!(accounts.accounts, expected);
@@ -233,18 +239,18 @@ mod tests {
     #[test]
     fn test_accounts_send_overfunded_fails_and_rolls_back() {
         let mut accounts = Accounts::new();
-        let amt = 100;
+        let amt = Amount::units(100);
         accounts.deposit("a-key", amt).expect("Couldn't deposit");
 
         // creating the receiver is also required
         accounts
-            .deposit("b-key", u64::MAX)
+            .deposit("b-key", Amount::MAX)
             .expect("Couldn't deposit");
 
-        let actual = accounts.send("a-key", "b-key", 1);
+        let actual = accounts.send("a-key", "b-key", Amount::units(1));
         assert!(actual.is_err());
-        let expected: HashMap<String, u64> =
-            vec![("a-key".to_string(), amt), ("b-key".to_string(), u64::MAX)]
+        let expected: HashMap<String, Amount> =
+            vec![("a-key".to_string(), amt), ("b-key".to_string(), Amount::MAX)]
                 .into_iter()
                 .collect();
         assert_eq!(accounts.accounts, expected);
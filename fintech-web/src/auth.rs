@@ -0,0 +1,407 @@
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+use warp::{Filter, Rejection, reject::Reject};
+
+/// The header carrying the caller's API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// A scope an endpoint requires from the caller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// Look at balances and the order book
+    Read,
+    /// Place and cancel orders
+    Trade,
+    /// Move funds in and out of accounts and manage them
+    Admin,
+}
+
+/// The role an API key was issued with. Each role includes the permissions of the ones before it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Role {
+    /// Dashboards: balances and the order book only
+    ReadOnly,
+    /// Bots and traders: everything read-only keys can do plus order entry
+    Trader,
+    /// Operators: deposits, withdrawals, transfers and everything else
+    Admin,
+}
+
+impl Role {
+    /// Checks whether this role grants the `permission`
+    pub fn allows(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Read => true,
+            Permission::Trade => *self >= Role::Trader,
+            Permission::Admin => *self == Role::Admin,
+        }
+    }
+
+    /// Parses a role name: `read-only`, `trader` or `admin`
+    pub fn parse(name: &str) -> Option<Role> {
+        match name.trim().to_lowercase().as_str() {
+            "read-only" | "readonly" | "read" => Some(Role::ReadOnly),
+            "trader" | "trade" => Some(Role::Trader),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<String> for Role {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        Role::parse(&name).ok_or_else(|| format!("Unknown role '{}'", name))
+    }
+}
+
+/// No API key was presented or the key is unknown
+#[derive(Debug)]
+pub struct Unauthorized;
+
+impl Reject for Unauthorized {}
+
+/// The API key is valid but its role doesn't grant the required permission
+#[derive(Debug)]
+pub struct Forbidden {
+    pub role: Role,
+    pub permission: Permission,
+}
+
+impl Reject for Forbidden {}
+
+/// The API key may not act for the account named in the request
+#[derive(Debug)]
+pub struct ForeignAccount {
+    pub account: String,
+}
+
+impl Reject for ForeignAccount {}
+
+/// What an API key was issued for: a role and, unless it's an admin key, the accounts it acts for.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Grant {
+    /// Only a role, e.g. `dashboard = "read-only"`. Admin keys act for every account, others for none.
+    Role(Role),
+    /// A role limited to some accounts, e.g. `market-maker = { role = "trader", accounts = ["MM"] }`
+    Scoped { role: Role, accounts: Vec<String> },
+}
+
+impl Grant {
+    /// The role the key was issued with
+    pub fn role(&self) -> Role {
+        match self {
+            Grant::Role(role) | Grant::Scoped { role, .. } => *role,
+        }
+    }
+
+    /// Checks whether the key may place and cancel orders for `account`
+    pub fn acts_for(&self, account: &str) -> bool {
+        match self {
+            Grant::Role(role) => *role == Role::Admin,
+            Grant::Scoped { accounts, .. } => accounts.iter().any(|a| a == account),
+        }
+    }
+}
+
+/// Maps API keys to the [`Grant`] they were issued with.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct ApiKeys {
+    keys: HashMap<String, Grant>,
+    #[serde(skip)]
+    disabled: bool,
+}
+
+impl ApiKeys {
+    /// Creates an empty key store, every caller is rejected until keys are inserted
+    pub fn new() -> Self {
+        ApiKeys {
+            keys: HashMap::new(),
+            disabled: false,
+        }
+    }
+
+    /// A key store that skips permission checks, every caller is an admin
+    pub fn disabled() -> Self {
+        ApiKeys {
+            keys: HashMap::new(),
+            disabled: true,
+        }
+    }
+
+    /// Parses `key=role` pairs separated by commas, optionally followed by the accounts the key acts
+    /// for, e.g. `dash=read-only,bot=trader:ALICE|BOB,ops=admin`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut keys = ApiKeys::new();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, grant) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected 'key=role', got '{}'", pair))?;
+            let (role, accounts) = match grant.split_once(':') {
+                Some((role, accounts)) => (role, Some(accounts)),
+                None => (grant, None),
+            };
+            let role = Role::parse(role).ok_or_else(|| format!("Unknown role '{}'", role))?;
+            match accounts {
+                Some(accounts) => keys.insert_scoped(
+                    key.trim(),
+                    role,
+                    accounts.split('|').map(str::trim).filter(|a| !a.is_empty()).map(String::from).collect(),
+                ),
+                None => keys.insert(key.trim(), role),
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Issues a key with the given role
+    pub fn insert(&mut self, key: &str, role: Role) {
+        self.keys.insert(key.to_string(), Grant::Role(role));
+    }
+
+    /// Issues a key with the given role that only acts for `accounts`
+    pub fn insert_scoped(&mut self, key: &str, role: Role, accounts: Vec<String>) {
+        self.keys.insert(key.to_string(), Grant::Scoped { role, accounts });
+    }
+
+    /// Trader keys issued without any accounts, they couldn't place a single order
+    pub fn without_accounts(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self
+            .keys
+            .iter()
+            .filter(|(_, grant)| *grant == &Grant::Role(Role::Trader))
+            .map(|(key, _)| key.as_str())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Whether any keys were configured
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the presented key if it was issued. Nothing is issued while permission checks are disabled.
+    pub fn authenticate<'a>(&self, key: Option<&'a str>) -> Option<&'a str> {
+        key.filter(|k| !self.disabled && self.keys.contains_key(*k))
+    }
+
+    /// Resolves the role of a presented key. If permission checks are disabled every caller is an admin.
+    pub fn role_of(&self, key: Option<&str>) -> Option<Role> {
+        if self.disabled {
+            return Some(Role::Admin);
+        }
+        key.and_then(|k| self.keys.get(k)).map(Grant::role)
+    }
+
+    /// Whether the presented key may act for `account`. With permission checks disabled every key may.
+    pub fn acts_for(&self, key: Option<&str>, account: &str) -> bool {
+        self.disabled || key.and_then(|k| self.keys.get(k)).is_some_and(|grant| grant.acts_for(account))
+    }
+
+    /// Checks that the presented key may act for `account` and writes an audit entry if it may not.
+    pub fn authorize_account(
+        &self,
+        route: &str,
+        key: Option<&str>,
+        account: &str,
+    ) -> Result<(), Rejection> {
+        if self.disabled {
+            return Ok(());
+        }
+        match key.and_then(|k| self.keys.get(k)) {
+            Some(grant) if grant.acts_for(account) => Ok(()),
+            Some(grant) => {
+                tracing::warn!(
+                    target: "audit",
+                    route,
+                    key = %mask(key),
+                    role = ?grant.role(),
+                    account,
+                    "denied"
+                );
+                Err(warp::reject::custom(ForeignAccount {
+                    account: account.to_string(),
+                }))
+            }
+            None => Err(warp::reject::custom(Unauthorized)),
+        }
+    }
+
+    /// Checks the presented key against the `permission` and writes an audit entry for denied requests.
+    pub fn authorize(
+        &self,
+        route: &str,
+        key: Option<&str>,
+        permission: Permission,
+    ) -> Result<Role, Rejection> {
+        match self.role_of(key) {
+            Some(role) if role.allows(permission) => Ok(role),
+            Some(role) => {
+                tracing::warn!(
+                    target: "audit",
+                    route,
+                    key = %mask(key),
+                    ?role,
+                    required = ?permission,
+                    "denied"
+                );
+                Err(warp::reject::custom(Forbidden { role, permission }))
+            }
+            None => {
+                tracing::warn!(
+                    target: "audit",
+                    route,
+                    key = %mask(key),
+                    required = ?permission,
+                    "unauthenticated"
+                );
+                Err(warp::reject::custom(Unauthorized))
+            }
+        }
+    }
+}
+
+/// Only keep the first few characters of a key for the audit log
+fn mask(key: Option<&str>) -> String {
+    match key {
+        Some(k) => format!("{}***", k.chars().take(4).collect::<String>()),
+        None => "<none>".to_string(),
+    }
+}
+
+/// A filter that only passes if the caller's API key grants the `permission`
+pub fn require(
+    keys: Arc<ApiKeys>,
+    route: &'static str,
+    permission: Permission,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and_then(move |key: Option<String>| {
+            let keys = keys.clone();
+            async move {
+                keys.authorize(route, key.as_deref(), permission)
+                    .map(|_| ())
+            }
+        })
+        .untuple_one()
+}
+
+/// A filter that passes the request `body` on if the caller's API key may act for the account
+/// `account_of` picks from it
+pub fn acting_for<T, F>(
+    keys: Arc<ApiKeys>,
+    route: &'static str,
+    account_of: fn(&T) -> &str,
+    body: F,
+) -> impl Filter<Extract = (T,), Error = Rejection> + Clone
+where
+    T: Send + 'static,
+    F: Filter<Extract = (T,), Error = Rejection> + Clone,
+{
+    body.and(warp::header::optional::<String>(API_KEY_HEADER))
+        .and_then(move |body: T, key: Option<String>| {
+            let keys = keys.clone();
+            async move {
+                keys.authorize_account(route, key.as_deref(), account_of(&body))
+                    .map(|_| body)
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_Role_allows_is_hierarchical() {
+        assert!(Role::ReadOnly.allows(Permission::Read));
+        assert!(!Role::ReadOnly.allows(Permission::Trade));
+        assert!(!Role::ReadOnly.allows(Permission::Admin));
+
+        assert!(Role::Trader.allows(Permission::Read));
+        assert!(Role::Trader.allows(Permission::Trade));
+        assert!(!Role::Trader.allows(Permission::Admin));
+
+        assert!(Role::Admin.allows(Permission::Read));
+        assert!(Role::Admin.allows(Permission::Trade));
+        assert!(Role::Admin.allows(Permission::Admin));
+    }
+
+    #[test]
+    fn test_ApiKeys_parse_works() {
+        let keys = ApiKeys::parse("dash=read-only, bot=trader,ops=admin").unwrap();
+        assert_eq!(keys.role_of(Some("dash")), Some(Role::ReadOnly));
+        assert_eq!(keys.role_of(Some("bot")), Some(Role::Trader));
+        assert_eq!(keys.role_of(Some("ops")), Some(Role::Admin));
+        assert_eq!(keys.role_of(Some("nope")), None);
+        assert_eq!(keys.role_of(None), None);
+    }
+
+    #[test]
+    fn test_ApiKeys_parse_rejects_unknown_roles() {
+        assert!(ApiKeys::parse("dash=superuser").is_err());
+        assert!(ApiKeys::parse("dash").is_err());
+    }
+
+    #[test]
+    fn test_ApiKeys_without_keys_nobody_is_authorized() {
+        let keys = ApiKeys::new();
+        assert_eq!(keys.role_of(None), None);
+        assert_eq!(keys.role_of(Some("ops")), None);
+    }
+
+    #[test]
+    fn test_ApiKeys_disabled_everyone_is_admin() {
+        let keys = ApiKeys::disabled();
+        assert_eq!(keys.role_of(None), Some(Role::Admin));
+    }
+
+    #[test]
+    fn test_ApiKeys_authorize_denies_insufficient_roles() {
+        let mut keys = ApiKeys::new();
+        keys.insert("dash", Role::ReadOnly);
+        keys.insert("bot", Role::Trader);
+
+        assert!(keys.authorize("orderbook", Some("dash"), Permission::Read).is_ok());
+        assert!(keys.authorize("order", Some("dash"), Permission::Trade).is_err());
+        assert!(keys.authorize("order", Some("bot"), Permission::Trade).is_ok());
+        assert!(keys.authorize("withdraw", Some("bot"), Permission::Admin).is_err());
+        assert!(keys.authorize("withdraw", None, Permission::Admin).is_err());
+    }
+
+    #[test]
+    fn test_ApiKeys_parse_reads_accounts() {
+        let keys = ApiKeys::parse("bot=trader:ALICE|BOB,ops=admin").unwrap();
+        assert_eq!(keys.role_of(Some("bot")), Some(Role::Trader));
+        assert!(keys.authorize_account("order", Some("bot"), "ALICE").is_ok());
+        assert!(keys.authorize_account("order", Some("bot"), "BOB").is_ok());
+        assert!(keys.authorize_account("order", Some("bot"), "CAROL").is_err());
+        assert!(keys.authorize_account("order", Some("ops"), "CAROL").is_ok());
+    }
+
+    #[test]
+    fn test_ApiKeys_authorize_account_only_allows_granted_accounts() {
+        let mut keys = ApiKeys::new();
+        keys.insert("bot", Role::Trader);
+        keys.insert_scoped("mm", Role::Trader, vec!["MM".to_string()]);
+        keys.insert_scoped("ops", Role::Admin, vec!["TREASURY".to_string()]);
+
+        assert!(keys.authorize_account("order", Some("bot"), "MM").is_err());
+        assert!(keys.authorize_account("order", Some("mm"), "MM").is_ok());
+        assert!(keys.authorize_account("order", Some("mm"), "FEES").is_err());
+        assert!(keys.authorize_account("order", Some("ops"), "MM").is_err());
+        assert!(keys.authorize_account("order", None, "MM").is_err());
+        assert!(ApiKeys::disabled().authorize_account("order", None, "MM").is_ok());
+        assert_eq!(keys.without_accounts(), vec!["bot"]);
+        assert!(keys.acts_for(Some("mm"), "MM"));
+        assert!(!keys.acts_for(Some("bot"), "MM"));
+    }
+}
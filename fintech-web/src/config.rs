@@ -0,0 +1,563 @@
+use crate::{auth::ApiKeys, rate_limit::RateLimits};
+use fintech_web::{core::Instrument, fees::FeeSchedule, session::Schedule};
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::{
+    fmt,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// Command line flags. Each flag can also be set via its environment variable and overrides the config file.
+#[derive(Parser, Debug, Default)]
+#[command(name = "fintech-web", about = "Fintech trading platform server")]
+pub struct Args {
+    /// Path to a TOML configuration file
+    #[arg(long, short, env = "FINTECH_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Address to listen on, e.g. 127.0.0.1:3030
+    #[arg(long, env = "FINTECH_BIND")]
+    pub bind: Option<SocketAddr>,
+
+    /// Maximum size of a request body in bytes
+    #[arg(long, env = "FINTECH_BODY_LIMIT")]
+    pub body_limit: Option<u64>,
+
+    /// Number of commands that may queue up for the sequencer
+    #[arg(long, env = "FINTECH_QUEUE_CAPACITY")]
+    pub queue_capacity: Option<usize>,
+
+    /// Longest a busy sequencer may defer publishing snapshots to readers, in milliseconds
+    #[arg(long, env = "FINTECH_MAX_STALENESS_MS")]
+    pub max_staleness_ms: Option<u64>,
+
+    /// API keys as `key=role` pairs separated by commas, trader keys followed by their accounts, e.g. `bot=trader:MM|ALICE`
+    #[arg(long, env = "FINTECH_API_KEYS", hide_env_values = true)]
+    pub api_keys: Option<String>,
+
+    /// Run without permission checks, every caller is an admin
+    #[arg(long, env = "FINTECH_AUTH_DISABLED")]
+    pub auth_disabled: bool,
+
+    /// Rate limits as `class=burst/rate` pairs separated by commas
+    #[arg(long, env = "FINTECH_RATE_LIMITS")]
+    pub rate_limits: Option<String>,
+
+    /// Where to keep the write-ahead log
+    #[arg(long, env = "FINTECH_WAL_PATH")]
+    pub wal_path: Option<PathBuf>,
+
+    /// Where to keep snapshots of the platform's state
+    #[arg(long, env = "FINTECH_SNAPSHOT_PATH")]
+    pub snapshot_path: Option<PathBuf>,
+
+    /// Write a snapshot and truncate the WAL after this many commands, 0 only does on shutdown
+    #[arg(long, env = "FINTECH_CHECKPOINT_EVERY")]
+    pub checkpoint_every: Option<u64>,
+
+    /// Log filter, e.g. `info` or `fintech_web=debug`
+    #[arg(long, env = "RUST_LOG")]
+    pub log_level: Option<String>,
+
+    /// Log line format
+    #[arg(long, env = "FINTECH_LOG_FORMAT", value_enum)]
+    pub log_format: Option<LogFormat>,
+
+    /// Write finished spans as OTLP-shaped JSON lines to this file
+    #[arg(long, env = "FINTECH_SPAN_EXPORT_PATH")]
+    pub span_export_path: Option<PathBuf>,
+
+    /// Print the OpenAPI document and exit
+    #[arg(long)]
+    pub print_openapi: bool,
+}
+
+/// Why the configuration couldn't be loaded
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file couldn't be read
+    Read(PathBuf, std::io::Error),
+    /// The config file isn't valid TOML or has unknown/mistyped fields
+    Parse(PathBuf, String),
+    /// A value is out of range or malformed
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "Couldn't read '{}': {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Couldn't parse '{}': {}", path.display(), e),
+            ConfigError::Invalid(e) => write!(f, "Invalid configuration: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The complete server configuration.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub sequencer: SequencerConfig,
+    pub auth: AuthConfig,
+    pub rate_limits: RateLimits,
+    pub persistence: PersistenceConfig,
+    pub logging: LoggingConfig,
+    pub fees: FeeSchedule,
+    pub session: Schedule,
+    /// Listed on start, or updated if the recovered book lists them differently
+    pub instruments: Vec<Instrument>,
+}
+
+/// HTTP listener settings
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Address to listen on
+    pub bind: SocketAddr,
+    /// Maximum size of a request body in bytes
+    pub body_limit: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            bind: SocketAddr::from(([127, 0, 0, 1], 3030)),
+            body_limit: 1024 * 16,
+        }
+    }
+}
+
+/// Sequencer queue and snapshot settings
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SequencerConfig {
+    /// Number of commands that may queue up before callers are held back
+    pub queue_capacity: usize,
+    /// Longest a busy sequencer may defer publishing snapshots, in milliseconds
+    pub max_staleness_ms: u64,
+}
+
+impl Default for SequencerConfig {
+    fn default() -> Self {
+        SequencerConfig {
+            queue_capacity: fintech_web::sequencer::DEFAULT_QUEUE_CAPACITY,
+            max_staleness_ms: fintech_web::snapshot::DEFAULT_MAX_STALENESS.as_millis() as u64,
+        }
+    }
+}
+
+impl SequencerConfig {
+    /// The staleness bound as a [`Duration`]
+    pub fn max_staleness(&self) -> Duration {
+        Duration::from_millis(self.max_staleness_ms)
+    }
+}
+
+/// API keys and their roles
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Maps each key to a role name (`read-only`, `trader` or `admin`), or to a role and the
+    /// accounts the key places orders for, e.g. `{ role = "trader", accounts = ["MM"] }`
+    pub api_keys: ApiKeys,
+    /// Skips permission checks. Has to be set explicitly, without keys the server won't start otherwise.
+    pub disabled: bool,
+}
+
+impl AuthConfig {
+    /// The keys the routes check against
+    pub fn keys(&self) -> ApiKeys {
+        if self.disabled {
+            ApiKeys::disabled()
+        } else {
+            self.api_keys.clone()
+        }
+    }
+}
+
+/// Where state is persisted. Nothing is persisted if the paths are unset.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct PersistenceConfig {
+    /// The write-ahead log
+    pub wal_path: Option<PathBuf>,
+    /// Snapshots of the platform's state
+    pub snapshot_path: Option<PathBuf>,
+    /// Commands after which a snapshot is written and the WAL truncated, 0 only does on shutdown
+    pub checkpoint_every: u64,
+}
+
+impl Default for PersistenceConfig {
+    fn default() -> Self {
+        PersistenceConfig {
+            wal_path: None,
+            snapshot_path: None,
+            checkpoint_every: fintech_web::persistence::DEFAULT_CHECKPOINT_EVERY,
+        }
+    }
+}
+
+/// How log lines are written
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line, including the current span and its parents
+    #[default]
+    Json,
+    /// Human readable lines for local development
+    Text,
+}
+
+/// Log and trace output settings
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// A filter in `RUST_LOG` syntax, e.g. `info` or `fintech_web=debug,warp=warn`
+    pub level: String,
+    /// The format of log lines
+    pub format: LogFormat,
+    /// Where to export finished spans, nothing is exported if unset
+    pub span_export_path: Option<PathBuf>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            format: LogFormat::default(),
+            span_export_path: None,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file (if any), applies flags and environment variables on top and validates the result
+    pub fn load(args: Args) -> Result<Self, ConfigError> {
+        let mut config = match &args.config {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        config.apply(args)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads a TOML file. Missing sections and fields take their default values.
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let content =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+        Config::parse(&content).map_err(|e| match e {
+            ConfigError::Invalid(e) => ConfigError::Parse(path.to_path_buf(), e),
+            other => other,
+        })
+    }
+
+    /// Parses TOML content
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        toml::from_str(content).map_err(|e| ConfigError::Invalid(e.to_string()))
+    }
+
+    /// Overrides values with flags and environment variables
+    fn apply(&mut self, args: Args) -> Result<(), ConfigError> {
+        if let Some(bind) = args.bind {
+            self.server.bind = bind;
+        }
+        if let Some(body_limit) = args.body_limit {
+            self.server.body_limit = body_limit;
+        }
+        if let Some(queue_capacity) = args.queue_capacity {
+            self.sequencer.queue_capacity = queue_capacity;
+        }
+        if let Some(max_staleness_ms) = args.max_staleness_ms {
+            self.sequencer.max_staleness_ms = max_staleness_ms;
+        }
+        if let Some(spec) = args.api_keys {
+            self.auth.api_keys = ApiKeys::parse(&spec)
+                .map_err(|e| ConfigError::Invalid(format!("api keys: {}", e)))?;
+        }
+        if args.auth_disabled {
+            self.auth.disabled = true;
+        }
+        if let Some(spec) = args.rate_limits {
+            self.rate_limits = RateLimits::parse(&spec)
+                .map_err(|e| ConfigError::Invalid(format!("rate limits: {}", e)))?;
+        }
+        if args.wal_path.is_some() {
+            self.persistence.wal_path = args.wal_path;
+        }
+        if args.snapshot_path.is_some() {
+            self.persistence.snapshot_path = args.snapshot_path;
+        }
+        if let Some(every) = args.checkpoint_every {
+            self.persistence.checkpoint_every = every;
+        }
+        if let Some(level) = args.log_level {
+            self.logging.level = level;
+        }
+        if let Some(format) = args.log_format {
+            self.logging.format = format;
+        }
+        if args.span_export_path.is_some() {
+            self.logging.span_export_path = args.span_export_path;
+        }
+        Ok(())
+    }
+
+    /// Checks that all values are usable
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.server.body_limit == 0 {
+            return Err(ConfigError::Invalid("server.body_limit must be positive".to_string()));
+        }
+        if self.sequencer.queue_capacity == 0 {
+            return Err(ConfigError::Invalid(
+                "sequencer.queue_capacity must be positive".to_string(),
+            ));
+        }
+        match (self.auth.disabled, self.auth.api_keys.is_empty()) {
+            (false, true) => {
+                return Err(ConfigError::Invalid(
+                    "auth.api_keys: no keys configured, set auth.disabled = true to run without permission checks"
+                        .to_string(),
+                ));
+            }
+            (true, false) => {
+                return Err(ConfigError::Invalid(
+                    "auth: api_keys are ignored while auth.disabled is set".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        if let Some(key) = self.auth.api_keys.without_accounts().first() {
+            return Err(ConfigError::Invalid(format!(
+                "auth.api_keys.{}: trader keys need the accounts they trade for, e.g. {{ role = \"trader\", accounts = [\"MM\"] }}",
+                key
+            )));
+        }
+        self.rate_limits
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("rate_limits: {}", e)))?;
+        self.fees
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("fees: {}", e)))?;
+        self.session
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("session: {}", e)))?;
+        if self.instruments.len() > 1 {
+            return Err(ConfigError::Invalid(
+                "instruments: there's one book, so only one instrument can be listed".to_string(),
+            ));
+        }
+        for instrument in &self.instruments {
+            instrument
+                .validate()
+                .map_err(|e| ConfigError::Invalid(format!("instruments.{}: {}", instrument.symbol, e)))?;
+        }
+        for (name, path) in [
+            ("persistence.wal_path", &self.persistence.wal_path),
+            ("persistence.snapshot_path", &self.persistence.snapshot_path),
+            ("logging.span_export_path", &self.logging.span_export_path),
+        ] {
+            if let Some(dir) = path.as_ref().and_then(|p| p.parent())
+                && !dir.as_os_str().is_empty()
+                && !dir.is_dir()
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "{}: directory '{}' doesn't exist",
+                    name,
+                    dir.display()
+                )));
+            }
+        }
+        if self.logging.level.trim().is_empty() {
+            return Err(ConfigError::Invalid("logging.level must not be empty".to_string()));
+        }
+        tracing_subscriber::EnvFilter::try_new(&self.logging.level)
+            .map_err(|e| ConfigError::Invalid(format!("logging.level: {}", e)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::{auth::Role, rate_limit::Limit};
+
+    #[test]
+    fn test_Config_parse_empty_uses_defaults() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_Config_parse_works() {
+        let config = Config::parse(
+            r#"
+            [server]
+            bind = "0.0.0.0:8080"
+            body_limit = 4096
+
+            [auth.api_keys]
+            dash = "read-only"
+
+            [rate_limits]
+            trade = { burst = 5, per_second = 1.5 }
+
+            [logging]
+            level = "debug"
+            format = "text"
+
+            [[fees.tiers]]
+            min_volume = 0
+            maker_bps = 10
+            taker_bps = 20
+
+            [[fees.tiers]]
+            min_volume = 1000000
+            maker_bps = -2
+            taker_bps = 5
+
+            [[session.phases]]
+            start = "08:00"
+            state = "PreOpen"
+
+            [[session.phases]]
+            start = "09:00"
+            state = "Continuous"
+
+            [[instruments]]
+            symbol = "BTC-USD"
+            base = "BTC"
+            quote = "USD"
+            tick_size = "0.5"
+            lot_size = "0.001"
+            min_quantity = "0.01"
+            max_quantity = 100
+            precision = { price = 1, quantity = 3 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.server.bind, SocketAddr::from(([0, 0, 0, 0], 8080)));
+        assert_eq!(config.server.body_limit, 4096);
+        assert_eq!(
+            config.rate_limits.trade,
+            Limit {
+                burst: 5,
+                per_second: 1.5
+            }
+        );
+        assert_eq!(config.rate_limits.read, RateLimits::default().read);
+        assert_eq!(config.logging.level, "debug");
+        assert_eq!(config.logging.format, LogFormat::Text);
+        assert_eq!(config.fees.tiers.len(), 2);
+        assert_eq!(config.fees.rates(fintech_web::core::Amount::units(2_000_000)), (-2, 5));
+        assert_eq!(config.session.phases.len(), 2);
+        assert_eq!(config.session.phases[1].start.to_string(), "09:00");
+        assert_eq!(config.instruments.len(), 1);
+        assert_eq!(config.instruments[0].lot_size, "0.001".parse().unwrap());
+        assert_eq!(config.instruments[0].max_quantity, Some(fintech_web::core::Quantity::units(100)));
+        assert_eq!(config.instruments[0].precision.quantity, 3);
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.auth.api_keys.role_of(Some("dash")),
+            Some(Role::ReadOnly)
+        );
+    }
+
+    #[test]
+    fn test_Config_parse_rejects_unknown_fields() {
+        assert!(Config::parse("[server]\nport = 3030").is_err());
+    }
+
+    #[test]
+    fn test_Config_apply_flags_override_file() {
+        let mut config = Config::parse("[server]\nbody_limit = 4096").unwrap();
+        config
+            .apply(Args {
+                bind: Some(SocketAddr::from(([0, 0, 0, 0], 9000))),
+                api_keys: Some("bot=trader:MM".to_string()),
+                rate_limits: Some("read=1/1".to_string()),
+                checkpoint_every: Some(500),
+                ..Args::default()
+            })
+            .unwrap();
+        assert_eq!(config.server.bind, SocketAddr::from(([0, 0, 0, 0], 9000)));
+        assert_eq!(config.server.body_limit, 4096);
+        assert_eq!(config.persistence.checkpoint_every, 500);
+        assert_eq!(
+            config.rate_limits.read,
+            Limit {
+                burst: 1,
+                per_second: 1.0
+            }
+        );
+        assert_eq!(
+            config.auth.api_keys.role_of(Some("bot")),
+            Some(Role::Trader)
+        );
+    }
+
+    #[test]
+    fn test_Config_validate_requires_keys_unless_auth_is_disabled() {
+        assert!(Config::default().validate().is_err());
+        assert!(Config::parse("[auth]\ndisabled = true").unwrap().validate().is_ok());
+        assert!(Config::parse("[auth.api_keys]\ndash = \"read-only\"").unwrap().validate().is_ok());
+        assert!(Config::parse("[auth]\ndisabled = true\n\n[auth.api_keys]\ndash = \"read-only\"")
+            .unwrap()
+            .validate()
+            .is_err());
+
+        let mut config = Config::default();
+        config.apply(Args { auth_disabled: true, ..Args::default() }).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.auth.keys().role_of(None), Some(Role::Admin));
+    }
+
+    #[test]
+    fn test_Config_validate_rejects_bad_values() {
+        let mut config = Config::default();
+        config.server.body_limit = 0;
+        assert!(config.validate().is_err());
+
+        assert!(Config::parse("[auth.api_keys]\ndash = \"root\"").is_err());
+        assert!(Config::parse("[auth.api_keys]\nbot = \"trader\"").unwrap().validate().is_err());
+        assert!(Config::parse("[auth.api_keys]\nbot = { role = \"trader\", accounts = [\"MM\"] }")
+            .unwrap()
+            .validate()
+            .is_ok());
+
+        let mut config = Config::default();
+        config.rate_limits.admin.burst = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.persistence.wal_path = Some(PathBuf::from("/does/not/exist/wal.log"));
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.logging.level = "fintech_web=loud".to_string();
+        assert!(config.validate().is_err());
+
+        assert!(Config::parse("[[fees.tiers]]\nmin_volume = 10\nmaker_bps = 0\ntaker_bps = 5").unwrap().validate().is_err());
+
+        assert!(Config::parse("[[session.phases]]\nstart = \"8:00\"\nstate = \"Closed\"").is_err());
+        assert!(Config::parse("[[session.phases]]\nstart = \"10:00\"\nstate = \"Halted\"").unwrap().validate().is_err());
+
+        let instrument = "[auth]\ndisabled = true\n\n[[instruments]]\nsymbol = \"BTC-USD\"\nbase = \"BTC\"\nquote = \"USD\"\nlot_size = 1\n";
+        // Finer than the default precision allows
+        let off_precision = format!("{}tick_size = \"0.001\"\n", instrument);
+        assert!(Config::parse(&off_precision).unwrap().validate().is_err());
+        let valid = format!("{}tick_size = \"0.01\"\n", instrument);
+        assert!(Config::parse(&valid).unwrap().validate().is_ok());
+        let listed_twice = format!("{}\n{}", valid, valid.replace("[auth]\ndisabled = true\n", ""));
+        assert!(Config::parse(&listed_twice).unwrap().validate().is_err());
+
+        assert!(Config::parse("[auth]\ndisabled = true").unwrap().validate().is_ok());
+    }
+}
@@ -1,5 +1,11 @@
+mod candles;
 mod matching;
+mod tape;
+mod ticker;
 use fintech_common::core::types;
 
-pub use matching::MatchingEngine;
+pub use candles::{CANDLES_KEPT, Candles, MAX_CANDLES};
+pub use matching::{Fill, Level, MatchingEngine, Touched};
+pub use tape::{DEFAULT_TAPE_CAPACITY, TradeTape};
+pub use ticker::{MarketStats, TICKER_WINDOW_MILLIS};
 pub use types::*;
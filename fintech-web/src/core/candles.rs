@@ -0,0 +1,143 @@
+use super::{Candle, Interval, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Candles kept per [`Interval`], a day of minutes or two months of hours
+pub const CANDLES_KEPT: usize = 1_440;
+
+/// The most candles [`Candles::range`] returns at once
+pub const MAX_CANDLES: usize = 1_000;
+
+/// OHLCV candles of every [`Interval`], built from the trades as they happen.
+/// Only intervals with trades are stored, [`Candles::range`] fills the gaps.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Candles {
+    candles: BTreeMap<Interval, VecDeque<Candle>>,
+}
+
+impl Candles {
+    /// Adds a trade to the current candle of every interval, starting new ones as time moves on
+    pub fn record(&mut self, trade: &Trade) {
+        for interval in Interval::ALL {
+            let start = interval.start_of(trade.timestamp);
+            let candles = self.candles.entry(interval).or_default();
+            match candles.back_mut() {
+                // A clock that went back doesn't reopen old candles
+                Some(last) if last.start >= start => last.add(trade.price, trade.amount),
+                _ => {
+                    candles.push_back(Candle::new(start, trade.price, trade.amount));
+                    if candles.len() > CANDLES_KEPT {
+                        candles.pop_front();
+                    }
+                }
+            }
+        }
+    }
+
+    /// The candles of `interval` overlapping `from..to` (milliseconds since the Unix epoch), oldest first.
+    /// Intervals without trades repeat the previous close, there are no candles before the first trade kept.
+    /// Returns the latest [`MAX_CANDLES`] if there are more.
+    pub fn range(&self, interval: Interval, from: u64, to: u64) -> Vec<Candle> {
+        let Some(candles) = self.candles.get(&interval) else {
+            return vec![];
+        };
+        if to <= from {
+            return vec![];
+        }
+        let step = interval.millis();
+        let last = interval.start_of(to - 1);
+        let first = interval
+            .start_of(from)
+            .max(last.saturating_sub(step * (MAX_CANDLES as u64 - 1)));
+
+        let i = candles.partition_point(|c| c.start < first);
+        let mut close = i.checked_sub(1).map(|i| candles[i].close);
+        let mut traded = candles.range(i..).peekable();
+        let mut result = Vec::new();
+        let mut start = first;
+        while start <= last {
+            match traded.next_if(|c| c.start == start) {
+                Some(candle) => {
+                    close = Some(candle.close);
+                    result.push(candle.clone());
+                }
+                None => result.extend(close.map(|close| Candle::flat(start, close))),
+            }
+            start += step;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::core::{Price, Quantity, Side};
+
+    fn trade(timestamp: u64, price: u64, amount: u64) -> Trade {
+        Trade {
+            id: 0,
+            price: Price::units(price),
+            amount: Quantity::units(amount),
+            maker_ordinal: 1,
+            taker_ordinal: 2,
+            aggressor: Side::Buy,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_Candles_range_aggregates_and_fills_gaps() {
+        let mut candles = Candles::default();
+        candles.record(&trade(1_000, 10, 1));
+        candles.record(&trade(1_500, 12, 2));
+        candles.record(&trade(1_900, 9, 1));
+        candles.record(&trade(4_200, 11, 5));
+
+        assert_eq!(
+            candles.range(Interval::OneSecond, 0, 5_000),
+            vec![
+                Candle {
+                    start: 1_000,
+                    open: Price::units(10),
+                    high: Price::units(12),
+                    low: Price::units(9),
+                    close: Price::units(9),
+                    volume: Quantity::units(4),
+                    trades: 3,
+                },
+                Candle::flat(2_000, Price::units(9)),
+                Candle::flat(3_000, Price::units(9)),
+                Candle::new(4_000, Price::units(11), Quantity::units(5)),
+            ]
+        );
+        // A range starting in a gap continues from the close before it, and runs up to `to`
+        assert_eq!(
+            candles.range(Interval::OneSecond, 2_500, 6_000),
+            vec![
+                Candle::flat(2_000, Price::units(9)),
+                Candle::flat(3_000, Price::units(9)),
+                Candle::new(4_000, Price::units(11), Quantity::units(5)),
+                Candle::flat(5_000, Price::units(11)),
+            ]
+        );
+        assert_eq!(candles.range(Interval::OneMinute, 0, 60_000).len(), 1);
+        assert_eq!(candles.range(Interval::OneMinute, 0, 60_000)[0].volume, Quantity::units(9));
+        assert!(candles.range(Interval::OneSecond, 5_000, 5_000).is_empty());
+    }
+
+    #[test]
+    fn test_Candles_range_returns_the_latest_candles() {
+        let mut candles = Candles::default();
+        candles.record(&trade(0, 10, 1));
+        candles.record(&trade(2_000_000, 11, 1));
+
+        let range = candles.range(Interval::OneSecond, 0, 2_001_000);
+        assert_eq!(range.len(), MAX_CANDLES);
+        assert_eq!(range.last().unwrap(), &Candle::new(2_000_000, Price::units(11), Quantity::units(1)));
+        assert_eq!(range[0], Candle::flat(1_001_000, Price::units(10)));
+    }
+}
@@ -1,24 +1,72 @@
-use std::{collections::{BTreeMap, BinaryHeap}, vec};
+use std::{cmp::Reverse, collections::{BTreeMap, BTreeSet, BinaryHeap}, hash::{BuildHasher, RandomState}, vec};
 
 use crate::{
-    core::{Order, Receipt, Side},
+    core::{BookEvent, BookOrder, BookTop, Candles, Depth, L3Book, Order, Price, PriceLevel, Quantity, Receipt, Side, Trade, TradeTape, Uncross},
     errors::ApplicationError,
 };
 
 use super::PartialOrder;
 
+/// One execution of an auction, both sides at the auction's price
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Fill {
+    /// The side that was in the book first
+    pub maker: PartialOrder,
+    /// The side that arrived later
+    pub taker: PartialOrder,
+}
+
+/// The prices of each side whose resting orders changed
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Touched {
+    pub bids: BTreeSet<Price>,
+    pub asks: BTreeSet<Price>,
+}
+
+impl Touched {
+    /// Notes that the orders at `price` on the `side` changed
+    pub fn insert(&mut self, side: &Side, price: Price) {
+        match side {
+            Side::Buy => self.bids.insert(price),
+            Side::Sell => self.asks.insert(price),
+        };
+    }
+}
+
+/// The orders resting at one price, in the order they fill
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Level {
+    /// Their open units and how many there are
+    pub summary: PriceLevel,
+    /// With their signers
+    pub orders: Vec<PartialOrder>,
+    /// As anyone may see them
+    pub queue: Vec<BookOrder>,
+}
+
 #[derive(Default, Debug)]
 pub struct MatchingEngine {
     /// The last sequence number
     pub ordinal: u64,
 
     /// The "Bid" or "Buy" side of the order book. Ordered by ordinal number.
-    pub bids: BTreeMap<u64, BinaryHeap<PartialOrder>>,
+    pub bids: BTreeMap<Price, BinaryHeap<PartialOrder>>,
     /// The "Ask" or "Sell" side of the order book. Ordered by ordinal number.
-    pub asks: BTreeMap<u64, BinaryHeap<PartialOrder>>,
+    pub asks: BTreeMap<Price, BinaryHeap<PartialOrder>>,
+
+    /// The most recent trades
+    pub trades: TradeTape,
+    /// OHLCV candles of the trades
+    pub candles: Candles,
 
-    /// Previous matches for record keeping
-    pub history: Vec<Receipt>,
+    /// Changes to the resting orders since [`MatchingEngine::take_events`] was last called
+    events: Vec<BookEvent>,
+    /// Whether orders are collected for a call auction instead of matched
+    auction: bool,
+    /// The secret key resting orders' public ids are derived from, so they don't give away ordinals
+    public_ids: RandomState,
+    /// Price levels changed since [`MatchingEngine::take_touched`] was last called
+    touched: Touched,
 }
 
 impl MatchingEngine {
@@ -28,39 +76,89 @@ impl MatchingEngine {
             ordinal: 0,
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
-            history: Vec::new(),
+            trades: TradeTape::default(),
+            candles: Candles::default(),
+            events: Vec::new(),
+            auction: false,
+            public_ids: RandomState::new(),
+            touched: Touched::default(),
         }
     }
 
+    /// The id the order with this `ordinal` is published under in the L3 book and its events.
+    /// Only the engine can map it back, ordinals are known to whoever placed the order.
+    pub fn public_id(&self, ordinal: u64) -> u64 {
+        self.public_ids.hash_one(ordinal)
+    }
+
+    /// Starts collecting orders for a call auction, they rest without matching until [`MatchingEngine::uncross`]
+    pub fn start_auction(&mut self) {
+        self.auction = true;
+    }
+
+    /// Whether a call auction is running
+    pub fn in_auction(&self) -> bool {
+        self.auction
+    }
+
     /// Processes an [`Order`] and returns a [`Receipt`]
     /// This includes matching the order to whatever is in the current books and adding the remainder (if any) to the book for future matching.
-    pub fn process(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
+    /// Its trades happen at `timestamp`, in milliseconds since the Unix epoch.
+    pub fn process(&mut self, order: Order, timestamp: u64) -> Result<Receipt, ApplicationError> {
         // Increment the ordinal number for this order
         self.ordinal += 1;
         let ordinal = self.ordinal;
 
+        // During an auction every order rests, they execute when it ends
+        if self.auction {
+            let amount = order.amount;
+            let partial = order.into_partial_order(ordinal, amount);
+            self.events.push(BookEvent::Add {
+                order_id: self.public_id(ordinal),
+                side: partial.side.clone(),
+                price: partial.price,
+                amount,
+            });
+            self.touched.insert(&partial.side, partial.price);
+            let book = match partial.side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            book.entry(partial.price).or_default().push(partial);
+            return Ok(Receipt {
+                ordinal,
+                matches: vec![],
+                fees: vec![],
+            });
+        }
+
         let original_amount = order.amount;
         let original_price = order.price;
+        let side = order.side.clone();
         let mut partial = order.into_partial_order(ordinal, original_amount);
+        // Price and amount of the rest of the order if it goes on the book
+        let mut rested = None;
 
         // Orders are matched to the opposite side
         let receipt = match &partial.side {
             Side::Buy => {
                 // Implement this side of the matching!
                 // Fetch all orders in the expected price range from this side of the orderbook
-                let orderbook_entry = self.asks.range_mut(0..=partial.price);
+                let orderbook_entry = self.asks.range_mut(Price::ZERO..=partial.price);
                 let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
-                let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
+                let matched_amount: Quantity = receipt.matches.iter().map(|m| m.amount).sum();
 
                 // If the matched price is higher than the original price, we need to update the price
                 // and add it to the bids side of the book
                 if matched_amount < original_amount {
-                   partial.amount = original_amount - matched_amount;
+                   partial.amount = original_amount.saturating_sub(matched_amount);
+                   partial.remaining = partial.amount;
                     let price = if partial.price < original_price {
                         original_price
                     } else {
                         partial.price
                     };
+                    rested = Some((price, partial.remaining));
                     let bids = self.bids.entry(price).or_insert(vec![].into());
                     bids.push(partial);
                 }
@@ -71,15 +169,17 @@ impl MatchingEngine {
             }
             Side::Sell => {
                 // Fetch all orders in the expected price range from this side of the orderbook
-                let orderbook_entry = self.bids.range_mut(partial.price..=u64::MAX);
+                let orderbook_entry = self.bids.range_mut(partial.price..=Price::MAX);
 
                 let receipt = MatchingEngine::match_order(&partial, orderbook_entry, ordinal)?;
-                let matched_amount: u64 = receipt.matches.iter().map(|m| m.amount).sum();
+                let matched_amount: Quantity = receipt.matches.iter().map(|m| m.amount).sum();
 
                 // The order wasn't fully matched
                 if matched_amount < original_amount {
-                    partial.amount = original_amount - matched_amount;
+                    partial.amount = original_amount.saturating_sub(matched_amount);
+                    partial.remaining = partial.amount;
                     let price = partial.price;
+                    rested = Some((price, partial.remaining));
                     let asks = self.asks.entry(price).or_insert(vec![].into());
                     asks.push(partial);
                 }
@@ -90,12 +190,331 @@ impl MatchingEngine {
         // Cleanup: Remove price entries without orders from the orderbook
         self.asks.retain(|_, orders| !orders.is_empty());
         self.bids.retain(|_, orders| !orders.is_empty());
+        for m in &receipt.matches {
+            self.touched.insert(&m.side, m.price);
+        }
+        if let Some((price, _)) = rested {
+            self.touched.insert(&side, price);
+        }
 
-        // Keep a log of matches
-        self.history.push(receipt.clone());
+        // Makers trade before the rest of the order goes on the book
+        let public_ids = &self.public_ids;
+        self.events.extend(receipt.matches.iter().map(|m| BookEvent::Execute {
+            order_id: public_ids.hash_one(m.ordinal),
+            amount: m.amount,
+            remaining: m.remaining,
+        }));
+        if let Some((price, amount)) = rested {
+            self.events.push(BookEvent::Add {
+                order_id: self.public_id(ordinal),
+                side: side.clone(),
+                price,
+                amount,
+            });
+        }
+
+        // Every match is a trade the taker started
+        for m in &receipt.matches {
+            let trade = Trade {
+                id: 0,
+                price: m.price,
+                amount: m.amount,
+                maker_ordinal: m.ordinal,
+                taker_ordinal: ordinal,
+                aggressor: side.clone(),
+                timestamp,
+            };
+            self.candles.record(&trade);
+            self.trades.record(trade);
+        }
         Ok(receipt)
     }
 
+    /// The matches [`MatchingEngine::process`] would make for `order` right now, without changing the book
+    pub fn preview(&self, order: &Order) -> Result<Vec<PartialOrder>, ApplicationError> {
+        if self.auction {
+            return Ok(vec![]);
+        }
+        let partial = order.clone().into_partial_order(self.ordinal + 1, order.amount);
+        // Walked in the same order as `process` does
+        let levels = match partial.side {
+            Side::Buy => self.asks.range(Price::ZERO..=partial.price),
+            Side::Sell => self.bids.range(partial.price..=Price::MAX),
+        };
+        // Only copies the levels the order can reach
+        let mut book = BTreeMap::new();
+        let mut open = partial.amount;
+        for (price, orders) in levels {
+            if open.is_zero() {
+                break;
+            }
+            let others: Quantity = orders.iter().filter(|o| o.signer != partial.signer).map(|o| o.remaining).sum();
+            open = open.saturating_sub(others);
+            book.insert(*price, orders.clone());
+        }
+        MatchingEngine::match_order(&partial, book.iter_mut(), partial.ordinal).map(|receipt| receipt.matches)
+    }
+
+    /// The price an auction would execute at if it ended now, `None` if no orders cross.
+    /// Among the resting prices, the one executing the most units wins. Ties go to the price leaving the
+    /// smallest surplus, then to the highest price if the surplus is all on the buy side or the lowest if it's
+    /// all on the sell side, and then to the price closest to the `reference` (the middle of the tied prices
+    /// without one), the lower one if two are equally close.
+    /// Orders of the same signer don't execute against each other, so a little less may execute than indicated.
+    pub fn indicative(&self, reference: Option<Price>) -> Option<Uncross> {
+        let mut prices: Vec<Price> = self.bids.keys().chain(self.asks.keys()).copied().collect();
+        prices.sort();
+        prices.dedup();
+        let open = |orders: &BinaryHeap<PartialOrder>| orders.iter().map(|o| o.remaining).sum::<Quantity>();
+        let candidates: Vec<Uncross> = prices
+            .into_iter()
+            .filter_map(|price| {
+                let demand: Quantity = self.bids.range(price..).map(|(_, orders)| open(orders)).sum();
+                let supply: Quantity = self.asks.range(..=price).map(|(_, orders)| open(orders)).sum();
+                let volume = demand.min(supply);
+                let (surplus, surplus_side) = match demand.cmp(&supply) {
+                    std::cmp::Ordering::Greater => (demand.saturating_sub(supply), Some(Side::Buy)),
+                    std::cmp::Ordering::Less => (supply.saturating_sub(demand), Some(Side::Sell)),
+                    std::cmp::Ordering::Equal => (Quantity::ZERO, None),
+                };
+                (!volume.is_zero()).then_some(Uncross { price, volume, surplus, surplus_side })
+            })
+            .collect();
+
+        // Maximum volume, then minimum surplus
+        let best = candidates.iter().map(|c| (c.volume, Reverse(c.surplus))).max()?;
+        let tied: Vec<Uncross> = candidates
+            .into_iter()
+            .filter(|c| (c.volume, Reverse(c.surplus)) == best)
+            .collect();
+        // Market pressure, `tied` is ordered by price
+        if tied.iter().all(|c| c.surplus_side == Some(Side::Buy)) {
+            return tied.last().cloned();
+        }
+        if tied.iter().all(|c| c.surplus_side == Some(Side::Sell)) {
+            return tied.first().cloned();
+        }
+        let (low, high) = (tied.first()?.price, tied.last()?.price);
+        let reference = reference.unwrap_or_else(|| low.midpoint(high));
+        let distance = |price: Price| price.max(reference).saturating_sub(price.min(reference));
+        // The first of the closest is the lowest
+        tied.into_iter().min_by_key(|c| distance(c.price))
+    }
+
+    /// The fills [`MatchingEngine::uncross`] would execute right now, without changing the book
+    pub fn preview_uncross(&self, reference: Option<Price>) -> Vec<Fill> {
+        let mut dry_run = MatchingEngine {
+            bids: self.bids.clone(),
+            asks: self.asks.clone(),
+            ..MatchingEngine::new()
+        };
+        dry_run.uncross(reference, 0).1
+    }
+
+    /// Ends the auction: every order that can execute at the [`MatchingEngine::indicative`] price does, in price-time
+    /// priority. The order that was in the book first is the maker of each fill. What's left rests in the book in
+    /// its old place and matching continues as usual. The trades happen at `timestamp`.
+    pub fn uncross(&mut self, reference: Option<Price>, timestamp: u64) -> (Option<Uncross>, Vec<Fill>) {
+        self.auction = false;
+        let Some(uncross) = self.indicative(reference) else {
+            return (None, vec![]);
+        };
+        let price = uncross.price;
+        let queue = |book: &mut BTreeMap<Price, BinaryHeap<PartialOrder>>, levels: Vec<Price>| {
+            levels
+                .into_iter()
+                .filter_map(|level| book.remove(&level))
+                .flat_map(|orders| {
+                    let mut orders = orders.into_vec();
+                    orders.sort_by_key(|o| o.ordinal);
+                    orders
+                })
+                .collect::<Vec<_>>()
+        };
+        // Best prices first
+        let bid_levels: Vec<Price> = self.bids.range(price..).rev().map(|(level, _)| *level).collect();
+        self.touched.bids.extend(&bid_levels);
+        let mut bids = queue(&mut self.bids, bid_levels);
+        let ask_levels: Vec<Price> = self.asks.range(..=price).map(|(level, _)| *level).collect();
+        self.touched.asks.extend(&ask_levels);
+        let mut asks = queue(&mut self.asks, ask_levels);
+
+        let mut fills = vec![];
+        let mut executed = Quantity::ZERO;
+        let mut first_open = 0;
+        for bid in bids.iter_mut() {
+            while asks.get(first_open).is_some_and(|ask| ask.remaining.is_zero()) {
+                first_open += 1;
+            }
+            for ask in asks[first_open..].iter_mut() {
+                if bid.remaining.is_zero() || executed >= uncross.volume {
+                    break;
+                }
+                // Self-matches are skipped like in continuous trading
+                if ask.remaining.is_zero() || ask.signer == bid.signer {
+                    continue;
+                }
+                let take = bid.remaining.min(ask.remaining).min(uncross.volume.saturating_sub(executed));
+                executed = executed.saturating_add(take);
+                let bid_fill = PartialOrder::take_from(bid, take, price);
+                let ask_fill = PartialOrder::take_from(ask, take, price);
+                let (maker, taker) = if bid_fill.ordinal < ask_fill.ordinal {
+                    (bid_fill, ask_fill)
+                } else {
+                    (ask_fill, bid_fill)
+                };
+                fills.push(Fill { maker, taker });
+            }
+        }
+
+        for fill in &fills {
+            let public_ids = &self.public_ids;
+            self.events.extend([&fill.maker, &fill.taker].map(|o| BookEvent::Execute {
+                order_id: public_ids.hash_one(o.ordinal),
+                amount: o.amount,
+                remaining: o.remaining,
+            }));
+            let trade = Trade {
+                id: 0,
+                price,
+                amount: fill.maker.amount,
+                maker_ordinal: fill.maker.ordinal,
+                taker_ordinal: fill.taker.ordinal,
+                aggressor: fill.taker.side.clone(),
+                timestamp,
+            };
+            self.candles.record(&trade);
+            self.trades.record(trade);
+        }
+        for order in bids.into_iter().chain(asks).filter(|o| !o.remaining.is_zero()) {
+            let book = match order.side {
+                Side::Buy => &mut self.bids,
+                Side::Sell => &mut self.asks,
+            };
+            book.entry(order.price).or_default().push(order);
+        }
+        (Some(uncross), fills)
+    }
+
+    /// Removes the resting order with this `ordinal` if it belongs to the `signer` and returns what was left of it
+    pub fn cancel(&mut self, ordinal: u64, signer: &str) -> Result<PartialOrder, ApplicationError> {
+        for book in [&mut self.bids, &mut self.asks] {
+            let level = book.iter_mut().find_map(|(price, orders)| {
+                orders
+                    .iter()
+                    .find(|o| o.ordinal == ordinal)
+                    .map(|order| (*price, order.clone()))
+            });
+            if let Some((price, order)) = level {
+                // Other signers' orders are "not found" too, so ordinals can't be probed
+                if order.signer != signer {
+                    break;
+                }
+                if let Some(orders) = book.get_mut(&price) {
+                    orders.retain(|o| o.ordinal != ordinal);
+                    if orders.is_empty() {
+                        book.remove(&price);
+                    }
+                }
+                self.events.push(BookEvent::Delete { order_id: self.public_ids.hash_one(ordinal) });
+                self.touched.insert(&order.side, price);
+                return Ok(order);
+            }
+        }
+        Err(ApplicationError::OrderNotFound(ordinal))
+    }
+
+    /// The highest bid
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.bids.iter().next_back().map(MatchingEngine::level)
+    }
+
+    /// The lowest ask
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.asks.iter().next().map(MatchingEngine::level)
+    }
+
+    /// The best bid and offer with spread and mid price
+    pub fn top(&self) -> BookTop {
+        BookTop::new(self.best_bid(), self.best_ask())
+    }
+
+    /// Up to `levels` aggregated price levels per side, best prices first
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            bids: self.bids.iter().rev().take(levels).map(MatchingEngine::level).collect(),
+            asks: self.asks.iter().take(levels).map(MatchingEngine::level).collect(),
+        }
+    }
+
+    /// Every resting order without its signer, best prices first and in queue order within a price
+    pub fn orders(&self) -> L3Book {
+        L3Book {
+            bids: self.bids.iter().rev().flat_map(|level| self.queue(level)).collect(),
+            asks: self.asks.iter().flat_map(|level| self.queue(level)).collect(),
+        }
+    }
+
+    /// Hands out the changes to the resting orders since the last call
+    pub fn take_events(&mut self) -> Vec<BookEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Hands out the price levels whose orders changed since the last call
+    pub fn take_touched(&mut self) -> Touched {
+        std::mem::take(&mut self.touched)
+    }
+
+    /// Every price level that has orders resting
+    pub fn levels(&self) -> Touched {
+        Touched {
+            bids: self.bids.keys().copied().collect(),
+            asks: self.asks.keys().copied().collect(),
+        }
+    }
+
+    /// The orders resting at `price` on the `side`, if there are any
+    pub fn level_at(&self, side: &Side, price: Price) -> Option<Level> {
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        let (price, orders) = book.get_key_value(&price)?;
+        let mut queued: Vec<PartialOrder> = orders.iter().cloned().collect();
+        queued.sort_by_key(|o| o.ordinal);
+        Some(Level {
+            summary: MatchingEngine::level((price, orders)),
+            orders: queued,
+            queue: self.queue((price, orders)),
+        })
+    }
+
+    /// The orders at one price in the order they fill
+    fn queue(&self, (price, orders): (&Price, &BinaryHeap<PartialOrder>)) -> Vec<BookOrder> {
+        let mut queue: Vec<&PartialOrder> = orders.iter().collect();
+        queue.sort_by_key(|o| o.ordinal);
+        queue
+            .into_iter()
+            .enumerate()
+            .map(|(position, o)| BookOrder {
+                order_id: self.public_id(o.ordinal),
+                side: o.side.clone(),
+                price: *price,
+                remaining: o.remaining,
+                queue_position: position as u64,
+            })
+            .collect()
+    }
+
+    /// Sums up the open amounts at one price
+    fn level((price, orders): (&Price, &BinaryHeap<PartialOrder>)) -> PriceLevel {
+        PriceLevel {
+            price: *price,
+            amount: orders.iter().map(|o| o.remaining).sum(),
+            orders: orders.len() as u64,
+        }
+    }
+
     /// Matches an order to the provided order book side.
     /// # Parameters
     /// - `order`: the order to match to the book
@@ -107,13 +526,13 @@ impl MatchingEngine {
         ordinal: u64,
     ) -> Result<Receipt, ApplicationError>
     where
-        T: Iterator<Item = (&'a u64, &'a mut BinaryHeap<PartialOrder>)>,
+        T: Iterator<Item = (&'a Price, &'a mut BinaryHeap<PartialOrder>)>,
     {
         let mut remaining_amount = order.amount;
         let mut matches = vec![];
 
         // Each matching position's amount is subtraced
-        'outer: while remaining_amount > 0 {
+        'outer: while !remaining_amount.is_zero() {
             // The iterator contains all orderbook_entry of a price point
             match orderbook_entry.next() {
                 Some((price, orderbook_entry)) => {
@@ -135,7 +554,8 @@ impl MatchingEngine {
                                 matches.push(
                                     PartialOrder::take_from(&mut position, remaining_amount, *price),
                                 );
-                                if position.remaining > 0 { 
+                                remaining_amount = Quantity::ZERO;
+                                if !position.remaining.is_zero() { 
                                     // If there is still a remaining amount, put it back into the orderbook entry
                                     orderbook_entry.push(position);
                                 }
@@ -144,9 +564,10 @@ impl MatchingEngine {
                             }
 
                             None => { 
-                                  remaining_amount -= position.remaining;
-                                position.remaining = 0;
-                                matches.push(position);
+                                // Take everything that's left, a match's amount is what traded
+                                let take = position.remaining;
+                                remaining_amount = remaining_amount.saturating_sub(take);
+                                matches.push(PartialOrder::take_from(&mut position, take, *price));
                             }
 
 
@@ -161,7 +582,12 @@ impl MatchingEngine {
                 None => break 'outer,
             }
         }
-        Ok(Receipt { ordinal, matches })
+        Ok(Receipt {
+            ordinal,
+            matches,
+            // Fees are charged at settlement
+            fees: vec![],
+        })
     }
 }
 
@@ -172,6 +598,9 @@ mod tests {
 
     use super::*;
 
+    /// When the orders in these tests arrive, in milliseconds since the Unix epoch
+    const NOW: u64 = 1_700_000_000_000;
+
     #[test]
     fn test_MatchingEngine_process_partially_match_order() {
         // Immplement me
@@ -179,30 +608,30 @@ mod tests {
 
         let alice_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let bob_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![PartialOrder {
-                price: 10,
-                amount: 1,
-                remaining: 1,   // 1 unit remains unfilled          
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                remaining: Quantity::units(1),   // 1 unit remains unfilled          
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
                 ordinal: 1
@@ -217,30 +646,30 @@ mod tests {
 
         let alice_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let bob_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![PartialOrder {
-                price: 10,
-                amount: 2,
-                remaining: 0,
+                price: Price::units(10),
+                amount: Quantity::units(2),
+                remaining: Quantity::ZERO,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
                 ordinal: 1
@@ -258,50 +687,50 @@ mod tests {
 
         let alice_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let charlie_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(charlie_receipt.matches, vec![]);
         assert_eq!(charlie_receipt.ordinal, 2);
 
         let bob_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![
                 PartialOrder {
-                    price: 10,
-                    amount: 1,
-                    remaining: 0,
+                    price: Price::units(10),
+                    amount: Quantity::units(1),
+                    remaining: Quantity::ZERO,
                     side: Side::Sell,
                     signer: "ALICE".to_string(),
                     ordinal: 1
                 },
                 PartialOrder {
-                    price: 10,
-                    amount: 1,
-                    remaining: 0,
+                    price: Price::units(10),
+                    amount: Quantity::units(1),
+                    remaining: Quantity::ZERO,
                     side: Side::Sell,
                     signer: "CHARLIE".to_string(),
                     ordinal: 2
@@ -319,41 +748,41 @@ mod tests {
 
         let alice_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let charlie_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(charlie_receipt.matches, vec![]);
         assert_eq!(charlie_receipt.ordinal, 2);
 
         let alice_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             alice_receipt.matches,
             vec![PartialOrder {
-                price: 10,
-                amount: 1,
-                remaining: 0,
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                remaining: Quantity::ZERO,
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
                 ordinal: 2
@@ -370,22 +799,22 @@ mod tests {
 
         let alice_receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let bob_receipt = matching_engine
             .process(Order {
-                price: 11,
-                amount: 2,
+                price: Price::units(11),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(bob_receipt.matches, vec![]);
@@ -398,33 +827,398 @@ mod tests {
         assert_eq!(matching_engine.ordinal, 0);
         let receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Buy,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(receipt.ordinal, matching_engine.ordinal);
 
         let receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(receipt.ordinal, matching_engine.ordinal);
 
         let receipt = matching_engine
             .process(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Buy,
                 signer: "CHARLIE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(receipt.ordinal, matching_engine.ordinal);
         assert_eq!(matching_engine.ordinal, 3);
     }
+
+    #[test]
+    fn test_MatchingEngine_process_partial_fills_across_levels() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, amount) in [(10, 2), (11, 5)] {
+            matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(amount),
+                    side: Side::Sell,
+                    signer: "ALICE".to_string(),
+                }, NOW)
+                .unwrap();
+        }
+
+        // Fills the cheaper level and part of the next, and stops there
+        let receipt = matching_engine
+            .process(Order {
+                price: Price::units(11),
+                amount: Quantity::units(3),
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            }, NOW)
+            .unwrap();
+        let fills: Vec<_> = receipt.matches.iter().map(|m| (m.ordinal, m.price, m.amount, m.remaining)).collect();
+        assert_eq!(fills, vec![(1, Price::units(10), Quantity::units(2), Quantity::units(0)), (2, Price::units(11), Quantity::units(1), Quantity::units(4))]);
+        assert_eq!(matching_engine.asks[&Price::units(11)].peek().unwrap().remaining, Quantity::units(4));
+
+        // A partially filled maker reports what traded, the rest of the taker rests with its open amount
+        let receipt = matching_engine
+            .process(Order {
+                price: Price::units(11),
+                amount: Quantity::units(5),
+                side: Side::Buy,
+                signer: "CHARLIE".to_string(),
+            }, NOW)
+            .unwrap();
+        let fills: Vec<_> = receipt.matches.iter().map(|m| (m.ordinal, m.amount, m.remaining)).collect();
+        assert_eq!(fills, vec![(2, Quantity::units(4), Quantity::units(0))]);
+        assert!(matching_engine.asks.is_empty());
+        let rest = matching_engine.bids[&Price::units(11)].peek().unwrap();
+        assert_eq!((rest.ordinal, rest.amount, rest.remaining), (4, Quantity::units(1), Quantity::units(1)));
+    }
+
+    #[test]
+    fn test_MatchingEngine_preview_matches_like_process() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, amount, signer) in [(10, 2, "ALICE"), (10, 1, "BOB"), (11, 5, "ALICE"), (12, 1, "CHARLIE")] {
+            matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(amount),
+                    side: Side::Sell,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        }
+        let order = Order {
+            price: Price::units(11),
+            amount: Quantity::units(4),
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+        };
+
+        // BOB's own order is skipped
+        let preview = matching_engine.preview(&order).unwrap();
+        assert_eq!(preview.iter().map(|m| (m.ordinal, m.amount)).collect::<Vec<_>>(), vec![
+            (1, Quantity::units(2)),
+            (3, Quantity::units(2)),
+        ]);
+        assert_eq!(matching_engine.ordinal, 4);
+        assert_eq!(matching_engine.depth(10).asks[0].amount, Quantity::units(3));
+        assert!(matching_engine.take_events().iter().all(|e| matches!(e, BookEvent::Add { .. })));
+
+        assert_eq!(matching_engine.process(order, NOW).unwrap().matches, preview);
+    }
+
+    #[test]
+    fn test_MatchingEngine_cancel_removes_own_resting_order() {
+        let mut matching_engine = MatchingEngine::new();
+        for (side, price) in [(Side::Sell, 10), (Side::Buy, 8), (Side::Buy, 8)] {
+            matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(2),
+                    side,
+                    signer: "ALICE".to_string(),
+                }, NOW)
+                .unwrap();
+        }
+
+        assert_eq!(
+            matching_engine.cancel(1, "BOB"),
+            Err(ApplicationError::OrderNotFound(1))
+        );
+        let cancelled = matching_engine.cancel(1, "ALICE").unwrap();
+        assert_eq!(cancelled.ordinal, 1);
+        assert!(matching_engine.asks.is_empty());
+
+        assert_eq!(matching_engine.cancel(2, "ALICE").unwrap().ordinal, 2);
+        assert_eq!(matching_engine.bids.get(&Price::units(8)).map(|o| o.len()), Some(1));
+        assert_eq!(
+            matching_engine.cancel(2, "ALICE"),
+            Err(ApplicationError::OrderNotFound(2))
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_depth_aggregates_levels_best_first() {
+        let mut matching_engine = MatchingEngine::new();
+        assert_eq!(matching_engine.top(), BookTop::default());
+        for (side, price, amount, signer) in [
+            (Side::Buy, 8, 2, "ALICE"),
+            (Side::Buy, 9, 1, "ALICE"),
+            (Side::Buy, 9, 4, "BOB"),
+            (Side::Sell, 12, 3, "ALICE"),
+            (Side::Sell, 14, 1, "BOB"),
+            // Partially fills ALICE's ask, the level shows what's open
+            (Side::Buy, 12, 1, "BOB"),
+        ] {
+            matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(amount),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        }
+
+        let level = |price, amount, orders| PriceLevel {
+            price: Price::units(price),
+            amount: Quantity::units(amount),
+            orders,
+        };
+        assert_eq!(
+            matching_engine.depth(usize::MAX),
+            Depth {
+                bids: vec![level(9, 5, 2), level(8, 2, 1)],
+                asks: vec![level(12, 2, 1), level(14, 1, 1)],
+            }
+        );
+        assert_eq!(matching_engine.depth(1), matching_engine.depth(usize::MAX).truncated(1));
+        assert_eq!(
+            matching_engine.top(),
+            BookTop {
+                bid: Some(level(9, 5, 2)),
+                ask: Some(level(12, 2, 1)),
+                spread: Some(Price::units(3)),
+                mid: Some("10.5".parse().unwrap()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_MatchingEngine_take_events_replays_into_the_l3_book() {
+        let mut matching_engine = MatchingEngine::new();
+        for (side, price, amount, signer) in [
+            (Side::Sell, 10, 2, "ALICE"),
+            (Side::Sell, 10, 3, "BOB"),
+            (Side::Buy, 8, 1, "ALICE"),
+            // Fills ALICE's ask, part of BOB's, and rests at 10
+            (Side::Buy, 10, 6, "CHARLIE"),
+        ] {
+            matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(amount),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        }
+        matching_engine.cancel(3, "ALICE").unwrap();
+
+        let ids: Vec<u64> = (0..5).map(|ordinal| matching_engine.public_id(ordinal)).collect();
+        assert_eq!(
+            matching_engine.take_events(),
+            vec![
+                BookEvent::Add { order_id: ids[1], side: Side::Sell, price: Price::units(10), amount: Quantity::units(2) },
+                BookEvent::Add { order_id: ids[2], side: Side::Sell, price: Price::units(10), amount: Quantity::units(3) },
+                BookEvent::Add { order_id: ids[3], side: Side::Buy, price: Price::units(8), amount: Quantity::units(1) },
+                BookEvent::Execute { order_id: ids[1], amount: Quantity::units(2), remaining: Quantity::ZERO },
+                BookEvent::Execute { order_id: ids[2], amount: Quantity::units(3), remaining: Quantity::ZERO },
+                BookEvent::Add { order_id: ids[4], side: Side::Buy, price: Price::units(10), amount: Quantity::units(1) },
+                BookEvent::Delete { order_id: ids[3] },
+            ]
+        );
+        assert!(matching_engine.take_events().is_empty());
+        assert_eq!(
+            matching_engine.orders(),
+            L3Book {
+                bids: vec![BookOrder {
+                    order_id: ids[4],
+                    side: Side::Buy,
+                    price: Price::units(10),
+                    remaining: Quantity::units(1),
+                    queue_position: 0,
+                }],
+                asks: vec![],
+            }
+        );
+        // No signers in sight
+        assert!(!format!("{:?}", matching_engine.orders()).contains("CHARLIE"));
+    }
+
+    #[test]
+    fn test_MatchingEngine_public_id_hides_the_ordinal() {
+        let matching_engine = MatchingEngine::new();
+        let ids: std::collections::HashSet<u64> = (1..=1000).map(|ordinal| matching_engine.public_id(ordinal)).collect();
+        assert_eq!(ids.len(), 1000);
+        assert!((1..=1000).all(|ordinal| !ids.contains(&ordinal)));
+        // Another engine, e.g. after a restart, keys them differently
+        assert_ne!(MatchingEngine::new().public_id(1), matching_engine.public_id(1));
+    }
+
+    #[test]
+    fn test_MatchingEngine_orders_in_queue_order() {
+        let mut matching_engine = MatchingEngine::new();
+        for (price, signer) in [(9, "ALICE"), (10, "BOB"), (10, "ALICE"), (9, "BOB")] {
+            matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(1),
+                    side: Side::Buy,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        }
+        let ids: Vec<u64> = (0..5).map(|ordinal| matching_engine.public_id(ordinal)).collect();
+        let queue: Vec<_> = matching_engine
+            .orders()
+            .bids
+            .iter()
+            .map(|o| (o.price, o.order_id, o.queue_position))
+            .collect();
+        assert_eq!(queue, vec![(Price::units(10), ids[2], 0), (Price::units(10), ids[3], 1), (Price::units(9), ids[1], 0), (Price::units(9), ids[4], 1)]);
+    }
+
+    #[test]
+    fn test_MatchingEngine_indicative_tie_breaks() {
+        let engine = |orders: &[(Side, u64, u64)]| {
+            let mut matching_engine = MatchingEngine::new();
+            matching_engine.start_auction();
+            for (i, (side, price, amount)) in orders.iter().enumerate() {
+                matching_engine
+                    .process(Order {
+                        price: Price::units(*price),
+                        amount: Quantity::units(*amount),
+                        side: side.clone(),
+                        signer: format!("SIGNER{}", i),
+                    }, NOW)
+                    .unwrap();
+            }
+            matching_engine
+        };
+        let uncross = |price, volume, surplus, surplus_side| Uncross {
+            price: Price::units(price),
+            volume: Quantity::units(volume),
+            surplus: Quantity::units(surplus),
+            surplus_side,
+        };
+
+        // The most volume
+        let matching_engine = engine(&[(Side::Buy, 10, 3), (Side::Buy, 9, 2), (Side::Sell, 8, 2), (Side::Sell, 9, 2), (Side::Sell, 11, 1)]);
+        assert_eq!(matching_engine.indicative(None), Some(uncross(9, 4, 1, Some(Side::Buy))));
+        // Then the surplus decides which way the price goes
+        let matching_engine = engine(&[(Side::Buy, 12, 4), (Side::Sell, 10, 3)]);
+        assert_eq!(matching_engine.indicative(None), Some(uncross(12, 3, 1, Some(Side::Buy))));
+        let matching_engine = engine(&[(Side::Buy, 12, 3), (Side::Sell, 10, 4)]);
+        assert_eq!(matching_engine.indicative(None), Some(uncross(10, 3, 1, Some(Side::Sell))));
+        // Then the reference price, or the middle without one
+        let matching_engine = engine(&[(Side::Buy, 12, 3), (Side::Sell, 10, 3)]);
+        assert_eq!(matching_engine.indicative(Some("11.5".parse().unwrap())), Some(uncross(12, 3, 0, None)));
+        assert_eq!(matching_engine.indicative(Some(Price::units(5))), Some(uncross(10, 3, 0, None)));
+        assert_eq!(matching_engine.indicative(None), Some(uncross(10, 3, 0, None)));
+        // Nothing crosses
+        let matching_engine = engine(&[(Side::Buy, 9, 3), (Side::Sell, 10, 3)]);
+        assert_eq!(matching_engine.indicative(None), None);
+    }
+
+    #[test]
+    fn test_MatchingEngine_uncross_executes_at_one_price() {
+        let mut matching_engine = MatchingEngine::new();
+        matching_engine.start_auction();
+        for (side, price, amount, signer) in [
+            (Side::Sell, 9, 2, "ALICE"),
+            (Side::Buy, 10, 3, "BOB"),
+            (Side::Sell, 10, 2, "CHARLIE"),
+            (Side::Buy, 9, 1, "ALICE"),
+            (Side::Sell, 11, 1, "BOB"),
+        ] {
+            let receipt = matching_engine
+                .process(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(amount),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+            // Crossing orders rest until the auction ends
+            assert_eq!(receipt.matches, vec![]);
+        }
+        assert_eq!(matching_engine.take_events().len(), 5);
+
+        let (uncross, fills) = matching_engine.uncross(None, NOW);
+        assert_eq!(
+            uncross,
+            Some(Uncross {
+                price: Price::units(10),
+                volume: Quantity::units(3),
+                surplus: Quantity::units(1),
+                surplus_side: Some(Side::Sell),
+            })
+        );
+        assert!(!matching_engine.in_auction());
+        // Both sides trade at the auction's price, the earlier order is the maker
+        let fills: Vec<_> = fills
+            .iter()
+            .map(|f| (f.maker.ordinal, f.taker.ordinal, f.maker.price, f.taker.price, f.maker.amount))
+            .collect();
+        assert_eq!(
+            fills,
+            vec![
+                (1, 2, Price::units(10), Price::units(10), Quantity::units(2)),
+                (2, 3, Price::units(10), Price::units(10), Quantity::units(1)),
+            ]
+        );
+        let trades: Vec<_> = matching_engine
+            .trades
+            .since(0, usize::MAX)
+            .into_iter()
+            .map(|t| (t.price, t.amount, t.maker_ordinal, t.taker_ordinal, t.aggressor))
+            .collect();
+        assert_eq!(
+            trades,
+            vec![
+                (Price::units(10), Quantity::units(2), 1, 2, Side::Buy),
+                (Price::units(10), Quantity::units(1), 2, 3, Side::Sell),
+            ]
+        );
+        let ids: Vec<u64> = (0..5).map(|ordinal| matching_engine.public_id(ordinal)).collect();
+        assert_eq!(
+            matching_engine.take_events(),
+            vec![
+                BookEvent::Execute { order_id: ids[1], amount: Quantity::units(2), remaining: Quantity::ZERO },
+                BookEvent::Execute { order_id: ids[2], amount: Quantity::units(2), remaining: Quantity::units(1) },
+                BookEvent::Execute { order_id: ids[2], amount: Quantity::units(1), remaining: Quantity::ZERO },
+                BookEvent::Execute { order_id: ids[3], amount: Quantity::units(1), remaining: Quantity::units(1) },
+            ]
+        );
+        // The rest stays in the book and matching continues
+        let book = matching_engine.orders();
+        let rest: Vec<_> = book.asks.iter().chain(&book.bids).map(|o| (o.order_id, o.remaining)).collect();
+        assert_eq!(rest, vec![(ids[3], Quantity::units(1)), (matching_engine.public_id(5), Quantity::units(1)), (ids[4], Quantity::units(1))]);
+        let receipt = matching_engine
+            .process(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Buy,
+                signer: "DAVE".to_string(),
+            }, NOW)
+            .unwrap();
+        assert_eq!(receipt.matches.iter().map(|m| m.ordinal).collect::<Vec<_>>(), vec![3]);
+    }
 }
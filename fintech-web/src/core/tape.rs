@@ -0,0 +1,108 @@
+use super::Trade;
+use std::collections::VecDeque;
+
+/// Default number of trades kept on the [`TradeTape`]
+pub const DEFAULT_TAPE_CAPACITY: usize = 10_000;
+
+/// The most recent trades, oldest first. Once full, the oldest trades drop off.
+#[derive(Debug)]
+pub struct TradeTape {
+    trades: VecDeque<Trade>,
+    capacity: usize,
+    /// The id of the latest trade, 0 before the first
+    last_id: u64,
+}
+
+impl Default for TradeTape {
+    fn default() -> Self {
+        TradeTape::new(DEFAULT_TAPE_CAPACITY)
+    }
+}
+
+impl TradeTape {
+    /// Creates an empty tape keeping up to `capacity` trades
+    pub fn new(capacity: usize) -> Self {
+        TradeTape {
+            trades: VecDeque::new(),
+            capacity,
+            last_id: 0,
+        }
+    }
+
+    /// Continues the tape from earlier `trades`, oldest first, so ids keep counting up
+    pub fn restore(trades: Vec<Trade>) -> Self {
+        let mut tape = TradeTape {
+            last_id: trades.last().map(|t| t.id).unwrap_or_default(),
+            trades: trades.into(),
+            capacity: DEFAULT_TAPE_CAPACITY,
+        };
+        tape.trim();
+        tape
+    }
+
+    /// Appends a trade with the next id
+    pub fn record(&mut self, mut trade: Trade) {
+        self.last_id += 1;
+        trade.id = self.last_id;
+        self.trades.push_back(trade);
+        self.trim();
+    }
+
+    /// The id of the latest trade, 0 if there were none
+    pub fn last_id(&self) -> u64 {
+        self.last_id
+    }
+
+    /// Up to `limit` trades after the one with the id `since`, oldest first.
+    /// If the trade after `since` dropped off already, the result starts with the oldest trade kept.
+    pub fn since(&self, since: u64, limit: usize) -> Vec<Trade> {
+        let start = self.trades.partition_point(|t| t.id <= since);
+        self.trades.range(start..).take(limit).cloned().collect()
+    }
+
+    fn trim(&mut self) {
+        while self.trades.len() > self.capacity {
+            self.trades.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::core::{Price, Quantity, Side};
+
+    fn trade(price: u64) -> Trade {
+        Trade {
+            id: 0,
+            price: Price::units(price),
+            amount: Quantity::units(1),
+            maker_ordinal: 1,
+            taker_ordinal: 2,
+            aggressor: Side::Buy,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_TradeTape_since_pages_through_the_kept_trades() {
+        let mut tape = TradeTape::new(3);
+        for price in 1..=5 {
+            tape.record(trade(price));
+        }
+        assert_eq!(tape.last_id(), 5);
+
+        let ids = |trades: Vec<Trade>| trades.iter().map(|t| t.id).collect::<Vec<_>>();
+        // 1 and 2 dropped off
+        assert_eq!(ids(tape.since(0, 10)), vec![3, 4, 5]);
+        assert_eq!(ids(tape.since(3, 1)), vec![4]);
+        assert_eq!(ids(tape.since(5, 10)), Vec::<u64>::new());
+
+        let restored = TradeTape::restore(tape.since(0, 10));
+        assert_eq!(restored.last_id(), 5);
+        assert_eq!(ids(restored.since(4, 10)), vec![5]);
+    }
+}
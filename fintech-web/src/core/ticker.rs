@@ -0,0 +1,154 @@
+use super::{Amount, Interval, Price, Quantity, Ticker, Trade};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The time the [`Ticker`] covers, in milliseconds
+pub const TICKER_WINDOW_MILLIS: u64 = 24 * 60 * 60 * 1_000;
+
+/// The trades of one minute, as far as the ticker needs them
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Minute {
+    start: u64,
+    open: Price,
+    high: Price,
+    low: Price,
+    volume: Quantity,
+    quote_volume: Amount,
+    trades: u64,
+}
+
+/// Rolling 24 hour statistics, updated with every trade. Trades are kept per minute,
+/// so the window moves on a minute at a time.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarketStats {
+    /// Minutes with trades, oldest first
+    minutes: VecDeque<Minute>,
+    /// Price of the latest trade
+    last: Option<Price>,
+}
+
+impl MarketStats {
+    /// Adds a trade and drops the minutes that fell out of the window
+    pub fn record(&mut self, trade: &Trade) {
+        let start = Interval::OneMinute.start_of(trade.timestamp);
+        let quote_volume = trade.price.checked_mul(trade.amount).unwrap_or(Amount::MAX);
+        match self.minutes.back_mut() {
+            // A clock that went back doesn't reopen old minutes
+            Some(last) if last.start >= start => {
+                last.high = last.high.max(trade.price);
+                last.low = last.low.min(trade.price);
+                last.volume = last.volume.saturating_add(trade.amount);
+                last.quote_volume = last.quote_volume.saturating_add(quote_volume);
+                last.trades += 1;
+            }
+            _ => self.minutes.push_back(Minute {
+                start,
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                volume: trade.amount,
+                quote_volume,
+                trades: 1,
+            }),
+        }
+        self.last = Some(trade.price);
+        while self
+            .minutes
+            .front()
+            .is_some_and(|m| m.start + TICKER_WINDOW_MILLIS <= start)
+        {
+            self.minutes.pop_front();
+        }
+    }
+
+    /// The price of the latest trade
+    pub fn last_price(&self) -> Option<Price> {
+        self.last
+    }
+
+    /// The statistics of the 24 hours up to `now` (milliseconds since the Unix epoch)
+    pub fn ticker(&self, now: u64) -> Ticker {
+        let current = Interval::OneMinute.start_of(now);
+        let mut ticker = Ticker {
+            last: self.last,
+            ..Ticker::default()
+        };
+        for minute in self.minutes.iter().filter(|m| m.start + TICKER_WINDOW_MILLIS > current) {
+            ticker.open.get_or_insert(minute.open);
+            ticker.high = Some(ticker.high.map_or(minute.high, |high| high.max(minute.high)));
+            ticker.low = Some(ticker.low.map_or(minute.low, |low| low.min(minute.low)));
+            ticker.volume = ticker.volume.saturating_add(minute.volume);
+            ticker.quote_volume = ticker.quote_volume.saturating_add(minute.quote_volume);
+            ticker.trades += minute.trades;
+        }
+        ticker.vwap = ticker.quote_volume.checked_div(ticker.volume);
+        if let (Some(open), Some(last)) = (ticker.open, ticker.last)
+            && !open.is_zero()
+        {
+            ticker.change_percent = Some((last.to_f64() - open.to_f64()) / open.to_f64() * 100.0);
+        }
+        ticker
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::core::Side;
+
+    const MINUTE: u64 = 60_000;
+
+    fn trade(timestamp: u64, price: u64, amount: u64) -> Trade {
+        Trade {
+            id: 0,
+            price: Price::units(price),
+            amount: Quantity::units(amount),
+            maker_ordinal: 1,
+            taker_ordinal: 2,
+            aggressor: Side::Buy,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn test_MarketStats_ticker_covers_the_last_24_hours() {
+        let mut stats = MarketStats::default();
+        assert_eq!(stats.ticker(0), Ticker::default());
+
+        stats.record(&trade(0, 100, 1));
+        stats.record(&trade(10 * MINUTE, 80, 2));
+        stats.record(&trade(10 * MINUTE + 1, 120, 1));
+        stats.record(&trade(20 * MINUTE, 110, 1));
+
+        assert_eq!(
+            stats.ticker(30 * MINUTE),
+            Ticker {
+                last: Some(Price::units(110)),
+                open: Some(Price::units(100)),
+                high: Some(Price::units(120)),
+                low: Some(Price::units(80)),
+                volume: Quantity::units(5),
+                quote_volume: Amount::units(100 + 160 + 120 + 110),
+                vwap: Some(Price::units(98)),
+                trades: 4,
+                change_percent: Some(10.0),
+            }
+        );
+
+        // The first minute dropped out of the window
+        let ticker = stats.ticker(TICKER_WINDOW_MILLIS + 5 * MINUTE);
+        assert_eq!(ticker.open, Some(Price::units(80)));
+        assert_eq!(ticker.trades, 3);
+        assert_eq!(ticker.change_percent, Some(37.5));
+
+        // Without recent trades only the last price is left
+        let ticker = stats.ticker(2 * TICKER_WINDOW_MILLIS);
+        assert_eq!(ticker.last, Some(Price::units(110)));
+        assert_eq!(ticker.open, None);
+        assert_eq!(ticker.volume, Quantity::ZERO);
+        assert_eq!(ticker.vwap, None);
+    }
+}
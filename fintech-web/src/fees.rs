@@ -0,0 +1,193 @@
+use crate::core::Amount;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// The account fees are credited to and maker rebates are paid from
+pub const FEE_ACCOUNT: &str = "FEES";
+
+/// Days of trading volume that decide an account's tier
+pub const VOLUME_WINDOW_DAYS: u64 = 30;
+
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1_000;
+
+/// Rates for accounts that traded at least `min_volume` (price times units) in the last 30 days
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeTier {
+    pub min_volume: Amount,
+    /// Basis points of a fill's value the resting order pays, a negative rate is a rebate
+    pub maker_bps: i64,
+    /// Basis points of a fill's value the incoming order pays
+    pub taker_bps: u64,
+}
+
+/// Maker and taker rates by volume tier. Without tiers trading is free.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeeSchedule {
+    /// Ordered by `min_volume`, the first one starting at 0
+    pub tiers: Vec<FeeTier>,
+}
+
+impl FeeSchedule {
+    /// Checks that the tiers are ordered and every fill's taker fee covers the maker rebate, whatever the tiers of both sides
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(first) = self.tiers.first()
+            && !first.min_volume.is_zero()
+        {
+            return Err("the first tier must start at min_volume 0".to_string());
+        }
+        if self.tiers.windows(2).any(|w| w[0].min_volume >= w[1].min_volume) {
+            return Err("tiers must be ordered by increasing min_volume".to_string());
+        }
+        if let Some(tier) = self
+            .tiers
+            .iter()
+            .find(|tier| tier.taker_bps > 10_000 || tier.maker_bps.unsigned_abs() > 10_000)
+        {
+            return Err(format!("tier {}: rates can't exceed 10000 bps", tier.min_volume));
+        }
+        let max_rebate = self.tiers.iter().map(|tier| (-tier.maker_bps).max(0) as u64).max().unwrap_or(0);
+        let min_taker = self.tiers.iter().map(|tier| tier.taker_bps).min().unwrap_or(0);
+        if max_rebate > min_taker {
+            return Err(format!(
+                "the largest maker rebate ({} bps) can't exceed the lowest taker fee ({} bps)",
+                max_rebate, min_taker
+            ));
+        }
+        Ok(())
+    }
+
+    /// The highest taker rate of any tier
+    pub fn max_taker_bps(&self) -> u64 {
+        self.tiers.iter().map(|tier| tier.taker_bps).max().unwrap_or(0)
+    }
+
+    /// The rates of an account with this 30 day `volume`, `(maker_bps, taker_bps)`
+    pub fn rates(&self, volume: Amount) -> (i64, u64) {
+        self.tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_volume <= volume)
+            .map_or((0, 0), |tier| (tier.maker_bps, tier.taker_bps))
+    }
+}
+
+/// The fee on `value` at `bps`, rounded up to a millionth
+pub fn fee(value: Amount, bps: u64) -> Amount {
+    Amount::from_raw((value.raw() as u128 * bps as u128).div_ceil(10_000).min(u64::MAX as u128) as u64)
+}
+
+/// The rebate on `value` at `bps`, rounded down to a millionth
+pub fn rebate(value: Amount, bps: u64) -> Amount {
+    Amount::from_raw((value.raw() as u128 * bps as u128 / 10_000) as u64)
+}
+
+/// Value traded per account and day, for the volume tiers
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Volumes {
+    /// Days since the Unix epoch with their volume, oldest first
+    accounts: HashMap<String, VecDeque<(u64, Amount)>>,
+}
+
+impl Volumes {
+    /// Adds `value` traded at `timestamp` (milliseconds since the Unix epoch) and forgets days out of the window
+    pub fn record(&mut self, account: &str, value: Amount, timestamp: u64) {
+        let today = timestamp / DAY_MILLIS;
+        let days = self.accounts.entry(account.to_string()).or_default();
+        match days.back_mut() {
+            Some((day, volume)) if *day >= today => *volume = volume.saturating_add(value),
+            _ => days.push_back((today, value)),
+        }
+        while days.front().is_some_and(|(day, _)| day + VOLUME_WINDOW_DAYS <= today) {
+            days.pop_front();
+        }
+    }
+
+    /// The value the account traded in the 30 days up to `timestamp`, including that day
+    pub fn thirty_day(&self, account: &str, timestamp: u64) -> Amount {
+        let today = timestamp / DAY_MILLIS;
+        self.accounts.get(account).map_or(Amount::ZERO, |days| {
+            days.iter()
+                .filter(|(day, _)| day + VOLUME_WINDOW_DAYS > today)
+                .map(|(_, volume)| *volume)
+                .sum()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    fn schedule() -> FeeSchedule {
+        FeeSchedule {
+            tiers: vec![
+                FeeTier {
+                    min_volume: Amount::ZERO,
+                    maker_bps: 10,
+                    taker_bps: 20,
+                },
+                FeeTier {
+                    min_volume: Amount::units(1_000),
+                    maker_bps: -5,
+                    taker_bps: 10,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_FeeSchedule_rates_picks_the_tier() {
+        let schedule = schedule();
+        assert!(schedule.validate().is_ok());
+        assert_eq!(schedule.rates(Amount::ZERO), (10, 20));
+        assert_eq!(schedule.rates(Amount::units(999)), (10, 20));
+        assert_eq!(schedule.rates(Amount::units(5_000)), (-5, 10));
+        assert_eq!(FeeSchedule::default().rates(Amount::units(5_000)), (0, 0));
+    }
+
+    #[test]
+    fn test_FeeSchedule_validate_rejects_bad_tiers() {
+        let mut unordered = schedule();
+        unordered.tiers.reverse();
+        assert!(unordered.validate().is_err());
+
+        let mut generous = schedule();
+        generous.tiers[1].maker_bps = -11;
+        assert!(generous.validate().is_err());
+
+        let mut late = schedule();
+        late.tiers.remove(0);
+        assert!(late.validate().is_err());
+    }
+
+    #[test]
+    fn test_fee_rounds_up_and_rebate_down() {
+        let raw = Amount::from_raw;
+        assert_eq!(fee(raw(101), 100), raw(2));
+        assert_eq!(fee(raw(100), 100), raw(1));
+        assert_eq!(fee(Amount::ZERO, 100), Amount::ZERO);
+        assert_eq!(rebate(raw(199), 100), raw(1));
+        // Fractions of a unit are kept
+        assert_eq!(fee(Amount::units(1), 25), "0.0025".parse().unwrap());
+    }
+
+    #[test]
+    fn test_Volumes_thirty_day_forgets_old_days() {
+        let mut volumes = Volumes::default();
+        volumes.record("ALICE", Amount::units(100), 0);
+        volumes.record("ALICE", Amount::units(50), DAY_MILLIS / 2);
+        volumes.record("ALICE", Amount::units(10), 10 * DAY_MILLIS);
+
+        assert_eq!(volumes.thirty_day("ALICE", 10 * DAY_MILLIS), Amount::units(160));
+        assert_eq!(volumes.thirty_day("ALICE", 30 * DAY_MILLIS), Amount::units(10));
+        assert_eq!(volumes.thirty_day("BOB", 0), Amount::ZERO);
+
+        volumes.record("ALICE", Amount::units(1), 40 * DAY_MILLIS);
+        assert_eq!(volumes.thirty_day("ALICE", 40 * DAY_MILLIS), Amount::units(1));
+    }
+}
@@ -0,0 +1,10 @@
+pub mod accounting;
+pub mod core;
+pub mod fees;
+pub mod metrics;
+pub mod persistence;
+pub mod sequencer;
+pub mod session;
+pub mod snapshot;
+pub mod trading_platform;
+pub use fintech_common::{errors, tx};
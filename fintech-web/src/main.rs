@@ -1,190 +1,1426 @@
-mod accounting;
-mod core;
-use  fintech_common::{errors , tx};
-mod trading_platform;
-use warp::Filter;
+mod auth;
+mod config;
+mod openapi;
+mod rate_limit;
+mod telemetry;
+use clap::Parser;
+use fintech_web::{metrics, persistence, sequencer, session, trading_platform};
+use std::time::Duration;
+use warp::{Filter, Reply};
+
+/// How long the sequencer gets to apply queued commands and write its checkpoint on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
 
 
 #[tokio::main]
 async fn main() {
-   
-    pretty_env_logger::init();
-    log::info!("Starting Fintech Trading Platform Server");
-
-    let trading_platform = std::sync::Arc::new(std::sync::Mutex::new(trading_platform::TradingPlatform::new()));
-    log::info!("Trading platform initialized");
-
-    let routes = filters::deposit(trading_platform.clone())
-        .or(filters::withdraw(trading_platform.clone()))
-        .or(filters::send(trading_platform.clone()))
-        .or(filters::order(trading_platform.clone()))
-        .or(filters::orderbook(trading_platform.clone()))
-        .or(filters::balance(trading_platform.clone()));
-
-    log::info!("Routes configured");
-    println!("Starting server on http://127.0.0.1:3030");
-    log::info!("Server starting on http://127.0.0.1:3030");
-    
+    let args = config::Args::parse();
+    if args.print_openapi {
+        println!("{}", *openapi::SPEC);
+        return;
+    }
+    let config = match config::Config::load(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = telemetry::init(&config.logging) {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    tracing::info!("Starting Fintech Trading Platform Server");
+
+    let (sequencer, trading_platform) = sequencer::Sequencer::new(
+        trading_platform::TradingPlatform::new().with_fee_schedule(config.fees.clone()),
+        config.sequencer.queue_capacity,
+        config.sequencer.max_staleness(),
+    );
+    let persistence = persistence::Persistence {
+        wal_path: config.persistence.wal_path.clone(),
+        snapshot_path: config.persistence.snapshot_path.clone(),
+        checkpoint_every: config.persistence.checkpoint_every,
+    };
+    if !persistence.is_enabled() {
+        tracing::warn!("No persistence configured, state is lost on shutdown");
+    }
+    // Recovery runs on the sequencer's task, /readyz reports when it's done
+    let sequencer_task = tokio::spawn(sequencer.with_persistence(persistence).run());
+    tracing::info!("Trading platform initialized");
+
+    // Through the sequencer, so the WAL has them once recovery is done
+    for instrument in config.instruments.clone() {
+        let symbol = instrument.symbol.clone();
+        match trading_platform.list_instrument(instrument).await {
+            Ok(_) => tracing::info!(%symbol, "Listed the configured instrument"),
+            Err(e) => {
+                tracing::error!(error = %e, %symbol, "Couldn't list the configured instrument");
+                std::process::exit(2);
+            }
+        }
+    }
+
+    // Scheduled transitions go through the sequencer like any other command
+    let scheduler_task = (!config.session.phases.is_empty()).then(|| {
+        let scheduler = session::Scheduler::new(config.session.clone(), std::sync::Arc::new(session::SystemClock));
+        tokio::spawn(scheduler.run(trading_platform.clone(), session::DEFAULT_TICK))
+    });
+
+    if config.auth.disabled {
+        tracing::warn!("Authentication is disabled, every caller is an admin");
+    }
+
+    let keys = std::sync::Arc::new(config.auth.keys());
+    let ctx = filters::Context {
+        tp: trading_platform,
+        keys: keys.clone(),
+        limiter: std::sync::Arc::new(rate_limit::RateLimiter::new(config.rate_limits, keys)),
+        body_limit: config.server.body_limit,
+    };
+
+    // Each group is boxed, a single chain of every route overflows the compiler's type depth limit
+    let accounts = filters::deposit(ctx.clone())
+        .or(filters::withdraw(ctx.clone()))
+        .or(filters::send(ctx.clone()))
+        .or(filters::balance(ctx.clone()))
+        .map(Reply::into_response)
+        .boxed();
+    let trading = filters::order(ctx.clone())
+        .or(filters::cancel(ctx.clone()))
+        .or(filters::orderbook(ctx.clone()))
+        .or(filters::book_top(ctx.clone()))
+        .or(filters::book_depth(ctx.clone()))
+        .or(filters::book_orders(ctx.clone()))
+        .map(Reply::into_response)
+        .boxed();
+    let market_data = filters::trade_history(ctx.clone())
+        .or(filters::candles(ctx.clone()))
+        .or(filters::ticker(ctx.clone()))
+        .or(filters::trades(ctx.clone()))
+        .or(filters::book_updates(ctx.clone()))
+        .map(Reply::into_response)
+        .boxed();
+    let venue = filters::instruments(ctx.clone())
+        .or(filters::create_instrument(ctx.clone()))
+        .or(filters::update_instrument(ctx.clone()))
+        .or(filters::session(ctx.clone()))
+        .or(filters::set_session(ctx.clone()))
+        .or(filters::halt(ctx.clone()))
+        .or(filters::resume(ctx.clone()))
+        .or(filters::auction(ctx.clone()))
+        .map(Reply::into_response)
+        .boxed();
+    let operations = filters::metrics()
+        .or(filters::healthz(ctx.tp.clone()))
+        .or(filters::readyz(ctx.tp.clone()))
+        .or(filters::openapi())
+        .or(filters::docs())
+        .map(Reply::into_response)
+        .boxed();
+
+    let api = accounts
+        .or(trading)
+        .or(market_data)
+        .or(venue)
+        .or(operations)
+        .recover(handlers::rejection);
+
+    let routes = filters::request_id()
+        .and(api)
+        .map(|id: String, reply| warp::reply::with_header(reply, telemetry::REQUEST_ID_HEADER, id))
+        .with(warp::trace(|info| {
+            tracing::info_span!(
+                "request",
+                method = %info.method(),
+                route = filters::route_label(info.path()),
+                request_id = tracing::field::Empty,
+            )
+        }))
+        .with(warp::log::custom(|info| {
+            metrics::METRICS.observe_request(filters::route_label(info.path()), info.status().as_u16(), info.elapsed())
+        }));
+
+    tracing::info!("Routes configured");
+    println!("Starting server on http://{}", config.server.bind);
+    tracing::info!(bind = %config.server.bind, "Server starting");
+
+    let shutdown = {
+        let tp = ctx.tp.clone();
+        async move {
+            shutdown_signal().await;
+            tracing::info!("Shutting down, draining in-flight requests");
+            tp.mark_unready();
+        }
+    };
+    // Stops accepting connections on shutdown and waits for in-flight requests
     warp::serve(routes)
-        .run(([127, 0, 0, 1], 3030))
+        .bind(config.server.bind)
+        .await
+        .graceful(shutdown)
+        .run()
         .await;
+
+    // With the last handle gone, the sequencer applies what's queued, syncs the WAL and writes a checkpoint
+    if let Some(task) = scheduler_task {
+        task.abort();
+    }
+    drop(ctx);
+    match tokio::time::timeout(SHUTDOWN_TIMEOUT, sequencer_task).await {
+        Ok(Ok(_)) => tracing::info!("Shutdown complete"),
+        Ok(Err(e)) => tracing::error!(error = %e, "Sequencer failed during shutdown"),
+        Err(_) => tracing::error!(timeout = ?SHUTDOWN_TIMEOUT, "Sequencer didn't stop in time"),
+    }
+}
+
+/// Resolves on SIGINT (Ctrl+C) or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Couldn't listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Couldn't listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = interrupt => {},
+        _ = terminate => {},
+    }
 }
 
 
 mod filters {
-    use fintech_common::core::types::{AccountBalanceRequest, AccountUpdateRequest, SendRequest, Order};
+    use fintech_common::core::types::{AccountBalanceRequest, AccountUpdateRequest, CancelRequest, HaltRequest, Instrument, SendRequest, SessionRequest, Order};
+    use crate::auth::{self, ApiKeys, Permission};
+    use crate::rate_limit::{self, RateLimiter, Usage};
+    use fintech_web::sequencer::SequencerHandle;
+    use std::sync::Arc;
     use warp::Filter;
+
+    /// Everything the routes need, shared by all of them
+    #[derive(Clone)]
+    pub struct Context {
+        pub tp: SequencerHandle,
+        pub keys: Arc<ApiKeys>,
+        pub limiter: Arc<RateLimiter>,
+        pub body_limit: u64,
+    }
  
-    pub fn deposit(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    pub fn deposit(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
        warp::path!("deposit")
             .and(warp::post())
-            .and(json_body::<AccountUpdateRequest>())
-            .and(with_trading_platform(tp))
-            .and_then(|req: AccountUpdateRequest, tp| crate::handlers::deposit(tp, req))
+            .and(auth::require(ctx.keys, "deposit", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<AccountUpdateRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: AccountUpdateRequest, tp| rate_limit::decorate(usage, crate::handlers::deposit(tp, key, req)))
     }
 
-    pub fn withdraw(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    pub fn withdraw(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
        warp::path!("withdraw")
             .and(warp::post())
-            .and(json_body::<AccountUpdateRequest>())
-            .and(with_trading_platform(tp))
-            .and_then(|req: AccountUpdateRequest, tp| crate::handlers::withdraw(tp, req))
+            .and(auth::require(ctx.keys, "withdraw", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<AccountUpdateRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: AccountUpdateRequest, tp| rate_limit::decorate(usage, crate::handlers::withdraw(tp, key, req)))
     }
 
-    pub fn send(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    pub fn send(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
        warp::path!("send")
             .and(warp::post())
-            .and(json_body::<SendRequest>())
-            .and(with_trading_platform(tp))
-            .and_then(|req: SendRequest, tp| crate::handlers::send(tp, req))
+            .and(auth::require(ctx.keys, "send", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<SendRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: SendRequest, tp| rate_limit::decorate(usage, crate::handlers::send(tp, key, req)))
     }
 
-    pub fn order(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    pub fn order(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
        warp::path!("order")
             .and(warp::post())
-            .and(json_body::<Order>())
-            .and(with_trading_platform(tp))
-            .and_then(|req: Order, tp| crate::handlers::order(tp, req))
+            .and(auth::require(ctx.keys.clone(), "order", Permission::Trade))
+            .and(rate_limit::check(ctx.limiter, Permission::Trade))
+            .and(idempotency_key())
+            .and(auth::acting_for(ctx.keys, "order", |req: &Order| &req.signer, json_body::<Order>(ctx.body_limit)))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: Order, tp| rate_limit::decorate(usage, crate::handlers::order(tp, key, req)))
+    }
+
+    pub fn cancel(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("cancel")
+            .and(warp::post())
+            .and(auth::require(ctx.keys.clone(), "cancel", Permission::Trade))
+            .and(rate_limit::check(ctx.limiter, Permission::Trade))
+            .and(idempotency_key())
+            .and(auth::acting_for(ctx.keys, "cancel", |req: &CancelRequest| &req.signer, json_body::<CancelRequest>(ctx.body_limit)))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: CancelRequest, tp| rate_limit::decorate(usage, crate::handlers::cancel(tp, key, req)))
     }
 
-    pub fn orderbook(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    pub fn orderbook(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
        warp::path!("orderbook")
             .and(warp::get())
-            .and(with_trading_platform(tp))
-            .and_then(|tp| crate::handlers::orderbook(tp))
+            .and(auth::require(ctx.keys.clone(), "orderbook", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(warp::header::optional::<String>(auth::API_KEY_HEADER))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(move |usage: Usage, key: Option<String>, tp| rate_limit::decorate(usage, crate::handlers::orderbook(tp, ctx.keys.clone(), key)))
     }
 
-    pub fn balance(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    pub fn book_top(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("book" / "top")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "book/top", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, tp| rate_limit::decorate(usage, crate::handlers::book_top(tp)))
+    }
+
+    pub fn book_depth(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("book" / "depth")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "book/depth", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(warp::query::<crate::handlers::DepthQuery>())
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, query, tp| rate_limit::decorate(usage, crate::handlers::book_depth(tp, query)))
+    }
+
+    pub fn book_orders(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("book" / "orders")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "book/orders", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, tp| rate_limit::decorate(usage, crate::handlers::book_orders(tp)))
+    }
+
+    pub fn balance(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
        warp::path!("balance")
             .and(warp::post())
-            .and(json_body::<AccountBalanceRequest>())
+            .and(auth::require(ctx.keys, "balance", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(json_body::<AccountBalanceRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, req: AccountBalanceRequest, tp| rate_limit::decorate(usage, crate::handlers::balance(tp, req)))
+    }
+
+    pub fn trade_history(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("trades")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "trades", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(warp::query::<crate::handlers::TradesQuery>())
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, query, tp| rate_limit::decorate(usage, crate::handlers::trade_history(tp, query)))
+    }
+
+    pub fn candles(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("candles")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "candles", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(warp::query::<crate::handlers::CandlesQuery>())
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, query, tp| rate_limit::decorate(usage, crate::handlers::candles(tp, query)))
+    }
+
+    pub fn ticker(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("ticker")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "ticker", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, tp| rate_limit::decorate(usage, crate::handlers::ticker(tp)))
+    }
+
+    /// Every trade as it happens, over a WebSocket
+    pub fn trades(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("ws" / "trades")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "ws/trades", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(warp::ws())
+            .and(with_trading_platform(ctx.tp))
+            .map(|usage: Usage, ws: warp::ws::Ws, tp: SequencerHandle| {
+                let trades = tp.subscribe_trades();
+                usage.apply(ws.on_upgrade(move |socket| crate::handlers::stream(socket, "trades", trades)))
+            })
+    }
+
+    /// Every change to the resting orders, over a WebSocket
+    pub fn book_updates(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("ws" / "book")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "ws/book", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(warp::ws())
+            .and(with_trading_platform(ctx.tp))
+            .map(|usage: Usage, ws: warp::ws::Ws, tp: SequencerHandle| {
+                let updates = tp.subscribe_book();
+                usage.apply(ws.on_upgrade(move |socket| crate::handlers::stream(socket, "book", updates)))
+            })
+    }
+
+    /// Prometheus metrics. Not authenticated so scrapers don't need a key.
+    pub fn metrics() -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("metrics")
+            .and(warp::get())
+            .and_then(crate::handlers::metrics)
+    }
+
+    /// Liveness: the process serves requests and the sequencer is running. Not authenticated, for probes.
+    pub fn healthz(tp: SequencerHandle) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("healthz")
+            .and(warp::get())
             .and(with_trading_platform(tp))
-            .and_then(|req: AccountBalanceRequest, tp| crate::handlers::balance(tp, req))
+            .and_then(crate::handlers::healthz)
+    }
+
+    /// Readiness: state is recovered and the server isn't shutting down. Not authenticated, for probes.
+    pub fn readyz(tp: SequencerHandle) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("readyz")
+            .and(warp::get())
+            .and(with_trading_platform(tp))
+            .and_then(crate::handlers::readyz)
+    }
+
+    /// The OpenAPI document. Not authenticated, it's public knowledge.
+    pub fn openapi() -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("openapi.json")
+            .and(warp::get())
+            .map(|| warp::reply::with_header(crate::openapi::SPEC.as_str(), "content-type", "application/json"))
+    }
+
+    /// A page to browse the OpenAPI document
+    pub fn docs() -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("docs")
+            .and(warp::get())
+            .map(|| warp::reply::html(crate::openapi::DOCS_PAGE))
+    }
+
+    /// Takes the caller's `x-request-id` or makes one up, and records it on the request span
+    pub fn request_id() -> impl warp::Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>(crate::telemetry::REQUEST_ID_HEADER).map(|id: Option<String>| {
+            let id = id
+                .filter(|id| crate::telemetry::is_valid_request_id(id))
+                .unwrap_or_else(crate::telemetry::new_request_id);
+            tracing::Span::current().record("request_id", id.as_str());
+            id
+        })
+    }
+
+    pub fn instruments(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("instruments")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "instruments", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, tp| rate_limit::decorate(usage, crate::handlers::instruments(tp)))
+    }
+
+    pub fn create_instrument(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("instruments")
+            .and(warp::post())
+            .and(auth::require(ctx.keys, "instruments", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<Instrument>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: Instrument, tp| rate_limit::decorate(usage, crate::handlers::create_instrument(tp, key, req)))
+    }
+
+    pub fn update_instrument(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("instruments" / String)
+            .and(warp::put())
+            .and(auth::require(ctx.keys, "instruments/{symbol}", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<Instrument>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|symbol: String, usage: Usage, key: Option<String>, req: Instrument, tp| rate_limit::decorate(usage, crate::handlers::update_instrument(tp, key, decode_segment(&symbol), req)))
+    }
+
+    pub fn session(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("session")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "session", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, tp| rate_limit::decorate(usage, crate::handlers::session(tp)))
+    }
+
+    pub fn set_session(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("session")
+            .and(warp::put())
+            .and(auth::require(ctx.keys, "session", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<SessionRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: SessionRequest, tp| rate_limit::decorate(usage, crate::handlers::set_session(tp, key, req)))
+    }
+
+    pub fn halt(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("session" / "halt")
+            .and(warp::post())
+            .and(auth::require(ctx.keys, "session/halt", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<HaltRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: HaltRequest, tp| rate_limit::decorate(usage, crate::handlers::halt(tp, key, req)))
+    }
+
+    pub fn resume(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("session" / "resume")
+            .and(warp::post())
+            .and(auth::require(ctx.keys, "session/resume", Permission::Admin))
+            .and(rate_limit::check(ctx.limiter, Permission::Admin))
+            .and(idempotency_key())
+            .and(json_body::<HaltRequest>(ctx.body_limit))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, key: Option<String>, req: HaltRequest, tp| rate_limit::decorate(usage, crate::handlers::resume(tp, key, req)))
+    }
+
+    pub fn auction(ctx: Context) -> impl warp::Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+       warp::path!("auction")
+            .and(warp::get())
+            .and(auth::require(ctx.keys, "auction", Permission::Read))
+            .and(rate_limit::check(ctx.limiter, Permission::Read))
+            .and(with_trading_platform(ctx.tp))
+            .and_then(|usage: Usage, tp| rate_limit::decorate(usage, crate::handlers::auction(tp)))
     }
 
-    fn with_trading_platform(tp: std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>) -> impl warp::Filter<Extract = (std::sync::Arc<std::sync::Mutex<crate::trading_platform::TradingPlatform>>,), Error = std::convert::Infallible> + Clone {
+    /// Maps a request path to one of the known routes, so arbitrary paths don't create new metric series
+    pub fn route_label(path: &str) -> &'static str {
+        match path.trim_matches('/') {
+            "deposit" => "deposit",
+            "withdraw" => "withdraw",
+            "send" => "send",
+            "order" => "order",
+            "cancel" => "cancel",
+            "orderbook" => "orderbook",
+            "book/top" => "book_top",
+            "book/depth" => "book_depth",
+            "book/orders" => "book_orders",
+            "balance" => "balance",
+            "trades" => "trade_history",
+            "candles" => "candles",
+            "ticker" => "ticker",
+            "instruments" => "instruments",
+            p if p.starts_with("instruments/") => "instrument",
+            "session" => "session",
+            "session/halt" => "halt",
+            "session/resume" => "resume",
+            "auction" => "auction",
+            "ws/trades" => "trades",
+            "ws/book" => "book_updates",
+            "metrics" => "metrics",
+            "healthz" => "healthz",
+            "readyz" => "readyz",
+            "openapi.json" => "openapi",
+            "docs" => "docs",
+            _ => "unmatched",
+        }
+    }
+
+    fn with_trading_platform(tp: SequencerHandle) -> impl warp::Filter<Extract = (SequencerHandle,), Error = std::convert::Infallible> + Clone {
         warp::any().map(move || tp.clone())
     }
 
 
-    fn json_body<T: serde::de::DeserializeOwned + Send>() -> impl warp::Filter<Extract = (T,), Error = warp::Rejection> + Clone {
-         warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    /// The caller's `idempotency-key`, so retried writes are applied once
+    fn idempotency_key() -> impl warp::Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("idempotency-key")
+    }
+
+    /// Path parameters arrive as sent, e.g. `BTC%2FUSD` for `BTC/USD`
+    fn decode_segment(segment: &str) -> String {
+        percent_encoding::percent_decode_str(segment).decode_utf8_lossy().into_owned()
+    }
+
+    fn json_body<T: serde::de::DeserializeOwned + Send>(limit: u64) -> impl warp::Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+         warp::body::content_length_limit(limit).and(warp::body::json())
     }
     
 }
 
 mod handlers {
     use std::convert::Infallible;
-    use fintech_common::core::types::{AccountBalanceRequest, AccountUpdateRequest, Order, SendRequest};
-    use crate::trading_platform::TradingPlatform;
-    use std::sync::{Arc, Mutex};
-    use log::{info, error};
+    use std::time::SystemTime;
+    use fintech_common::core::types::{AccountBalanceRequest, AccountUpdateRequest, Amount, Auction, BookTop, CancelRequest, Candle, Depth, HaltRequest, Instrument, Interval, L3Book, Order, PartialOrder, Receipt, SendRequest, SessionRequest, Ticker, Trade, TradingSession};
+    use crate::auth::{ApiKeys, ForeignAccount, Forbidden, Unauthorized};
+    use std::sync::Arc;
+    use crate::rate_limit::RateLimited;
+    use fintech_web::{errors::{ApplicationError, ErrorResponse}, metrics::METRICS, sequencer::{self, Command, Response, SequencerHandle}};
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::sync::broadcast::{self, error::RecvError};
+    use tracing::{Span, debug, error, field::{self, Empty}, info, instrument, warn};
+    use warp::Reply;
+    use warp::http::StatusCode;
+    use warp::ws::{Message, WebSocket};
 
 
-    pub async fn deposit(tp : Arc<Mutex<TradingPlatform>> , req: AccountUpdateRequest ) -> Result<impl warp::Reply ,Infallible> {
-        info!("Deposit request for account: {}, amount: {}", req.account, req.amount);
-        let mut platform = tp.lock().unwrap();
-        match platform.deposit(&req.account, req.amount) {
+    #[utoipa::path(
+        post,
+        path = "/deposit",
+        tag = "accounts",
+        summary = "Deposit funds into an account (admin)",
+        request_body = AccountUpdateRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "`\"Deposit successful\"`", body = String),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission, or the account is reserved for the platform"),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 422, description = "The account can't hold the amount", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(account = %req.account, amount = %req.amount))]
+    pub async fn deposit(tp : SequencerHandle , idempotency_key: Option<String>, req: AccountUpdateRequest ) -> Result<warp::reply::Response ,Infallible> {
+        let command = Command::Deposit { account: req.account, amount: req.amount };
+        match tp.submit(command, idempotency_key).await {
             Ok(_) => {
-                info!("Deposit successful for account: {}, amount: {}", req.account, req.amount);
-                Ok(warp::reply::json(&"Deposit successful"))
+                info!("Deposit successful");
+                Ok(warp::reply::json(&"Deposit successful").into_response())
             },
             Err(e) => {
-                error!("Deposit failed for account: {}, amount: {}, error: {:?}", req.account, req.amount, e);
-                Ok(warp::reply::json(&format!("Error: {:?}", e)))
+                error!(error = ?e, "Deposit failed");
+                Ok(error_reply(&e))
             },
         }
     }
 
 
-    pub async fn withdraw(tp : Arc<Mutex<TradingPlatform>> , req: AccountUpdateRequest ) -> Result<impl warp::Reply ,Infallible> {
-        info!("Withdraw request for account: {}, amount: {}", req.account, req.amount);
-        let mut platform = tp.lock().unwrap();
-        match platform.withdraw(&req.account, req.amount) {
+    #[utoipa::path(
+        post,
+        path = "/withdraw",
+        tag = "accounts",
+        summary = "Withdraw funds from an account (admin)",
+        request_body = AccountUpdateRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "`\"Withdrawal successful\"`", body = String),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission, or the account is reserved for the platform"),
+            (status = 404, description = "The account doesn't exist", body = ErrorResponse),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 422, description = "The account can't cover the amount", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(account = %req.account, amount = %req.amount))]
+    pub async fn withdraw(tp : SequencerHandle , idempotency_key: Option<String>, req: AccountUpdateRequest ) -> Result<warp::reply::Response ,Infallible> {
+        let command = Command::Withdraw { account: req.account, amount: req.amount };
+        match tp.submit(command, idempotency_key).await {
             Ok(_) => {
-                info!("Withdrawal successful for account: {}, amount: {}", req.account, req.amount);
-                Ok(warp::reply::json(&"Withdrawal successful"))
+                info!("Withdrawal successful");
+                Ok(warp::reply::json(&"Withdrawal successful").into_response())
             },
             Err(e) => {
-                error!("Withdrawal failed for account: {}, amount: {}, error: {:?}", req.account, req.amount, e);
-                Ok(warp::reply::json(&format!("Error: {:?}", e)))
+                error!(error = ?e, "Withdrawal failed");
+                Ok(error_reply(&e))
             },
         }
     }
 
-    pub async fn send(tp : Arc<Mutex<TradingPlatform>> , req: SendRequest ) -> Result<impl warp::Reply ,Infallible> {
-        info!("Transfer request from: {} to: {}, amount: {}", req.sender, req.recipient, req.amount);
-        let mut platform = tp.lock().unwrap();
-        match platform.send(&req.sender, &req.recipient, req.amount) {
+    #[utoipa::path(
+        post,
+        path = "/send",
+        tag = "accounts",
+        summary = "Transfer funds between accounts (admin)",
+        request_body = SendRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "`\"Transfer successful\"`", body = String),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission, or the account is reserved for the platform"),
+            (status = 404, description = "The sender doesn't exist", body = ErrorResponse),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 422, description = "The sender can't cover the amount or the recipient can't hold it", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(account = %req.sender, recipient = %req.recipient, amount = %req.amount))]
+    pub async fn send(tp : SequencerHandle , idempotency_key: Option<String>, req: SendRequest ) -> Result<warp::reply::Response ,Infallible> {
+        let command = Command::Send { sender: req.sender, recipient: req.recipient, amount: req.amount };
+        match tp.submit(command, idempotency_key).await {
             Ok(_) => {
-                info!("Transfer successful from: {} to: {}, amount: {}", req.sender, req.recipient, req.amount);
-                Ok(warp::reply::json(&"Transfer successful"))
+                info!("Transfer successful");
+                Ok(warp::reply::json(&"Transfer successful").into_response())
             },
             Err(e) => {
-                error!("Transfer failed from: {} to: {}, amount: {}, error: {:?}", req.sender, req.recipient, req.amount, e);
-                Ok(warp::reply::json(&format!("Error: {:?}", e)))
+                error!(error = ?e, "Transfer failed");
+                Ok(error_reply(&e))
             },
         }
     }
 
-    pub async fn order(tp : Arc<Mutex<TradingPlatform>> , req:Order ) -> Result<impl warp::Reply ,Infallible> {
-        info!("Order request - signer: {}, side: {:?}, price: {}, amount: {}", req.signer, req.side, req.price, req.amount);
-        let mut platform = tp.lock().unwrap();
-        match platform.order(req) {
+    #[utoipa::path(
+        post,
+        path = "/order",
+        tag = "trading",
+        summary = "Place a limit order (trader)",
+        request_body = Order,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "The receipt with immediate matches", body = Receipt),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission, the key can't act for the signer, or the account is reserved for the platform"),
+            (status = 404, description = "The signer's account doesn't exist", body = ErrorResponse),
+            (status = 409, description = "The session doesn't accept orders, or the idempotency key was used for a different request", body = ErrorResponse),
+            (status = 422, description = "The signer can't cover the order, or it breaks the instrument's rules or has too many decimals", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(
+        skip_all,
+        fields(account = %req.signer, side = ?req.side, price = %req.price, amount = %req.amount, ordinal = Empty, matches = Empty)
+    )]
+    pub async fn order(tp : SequencerHandle , idempotency_key: Option<String>, req:Order ) -> Result<warp::reply::Response ,Infallible> {
+        let result = match tp.submit(Command::Order(req), idempotency_key).await {
+            Ok(Response::Receipt(receipt)) => Ok(receipt),
+            Ok(other) => Err(sequencer::unexpected(other)),
+            Err(e) => Err(e),
+        };
+        match result {
             Ok(receipt) => {
-                info!("Order processed successfully - ordinal: {}, matches: {}", receipt.ordinal, receipt.matches.len());
-                Ok(warp::reply::json(&receipt))
+                // The makers' ordinals identify the matches
+                let matches: Vec<u64> = receipt.matches.iter().map(|m| m.ordinal).collect();
+                let span = Span::current();
+                span.record("ordinal", receipt.ordinal);
+                span.record("matches", field::debug(&matches));
+                info!("Order processed successfully");
+                Ok(warp::reply::json(&receipt).into_response())
+            },
+            Err(e) => {
+                error!(error = ?e, "Order processing failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/cancel",
+        tag = "trading",
+        summary = "Cancel a resting order (trader)",
+        request_body = CancelRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "The part of the order that was still resting", body = PartialOrder),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission, or the key can't act for the signer"),
+            (status = 404, description = "The signer has no resting order with the ordinal", body = ErrorResponse),
+            (status = 409, description = "The session doesn't accept cancels, or the idempotency key was used for a different request", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(account = %req.signer, ordinal = req.ordinal))]
+    pub async fn cancel(tp : SequencerHandle , idempotency_key: Option<String>, req: CancelRequest) -> Result<warp::reply::Response, Infallible> {
+        let command = Command::Cancel { signer: req.signer, ordinal: req.ordinal };
+        match tp.submit(command, idempotency_key).await {
+            Ok(Response::Cancelled(order)) => {
+                info!(remaining = %order.remaining, "Order cancelled");
+                Ok(warp::reply::json(&order).into_response())
             },
+            Ok(other) => Ok(error_reply(&sequencer::unexpected(other))),
             Err(e) => {
-                error!("Order processing failed, error: {:?}", e);
-                Ok(warp::reply::json(&format!("Error processing order: {:?}", e)))
+                error!(error = ?e, "Cancellation failed");
+                Ok(error_reply(&e))
             },
         }
     }
 
 
     //getter function for orderbook
-    pub async fn orderbook(tp : Arc<Mutex<TradingPlatform>>) -> Result<impl warp::Reply, Infallible> {
-        info!("Orderbook request received");
-        let platform = tp.lock().unwrap();
-        let orderbook = platform.orderbook();
-        info!("Returning orderbook with {} orders", orderbook.len());
-        Ok(warp::reply::json(&orderbook))
+    #[utoipa::path(
+        get,
+        path = "/orderbook",
+        tag = "trading",
+        summary = "The resting orders, oldest first (read-only)",
+        description = "Signers are only shown on the orders of accounts the API key acts for, the others' are empty. Admin keys see every signer.",
+        responses(
+            (status = 200, description = "All resting orders", body = [PartialOrder], headers(("x-snapshot-sequence" = u64, description = "The snapshot the data was read from"))),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(sequence = Empty))]
+    pub async fn orderbook(tp : SequencerHandle, keys: Arc<ApiKeys>, key: Option<String>) -> Result<impl warp::Reply, Infallible> {
+        let snapshot = tp.snapshot();
+        Span::current().record("sequence", snapshot.sequence);
+        let mut orderbook = snapshot.orderbook();
+        debug!(orders = orderbook.len(), "Returning orderbook");
+        // Other accounts' signers would tell who is behind the orders in the anonymous L3 book
+        for order in orderbook.iter_mut() {
+            if !keys.acts_for(key.as_deref(), &order.signer) {
+                order.signer.clear();
+            }
+        }
+        Ok(with_sequence(warp::reply::json(&orderbook), snapshot.sequence))
+    }
+
+
+    /// Price levels per side if `GET /book/depth` doesn't ask for a number
+    const DEFAULT_DEPTH_LEVELS: usize = 10;
+
+    /// The query of `GET /book/depth`
+    #[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct DepthQuery {
+        /// Price levels per side, 10 if not given
+        levels: Option<usize>,
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/book/top",
+        tag = "trading",
+        summary = "Best bid and offer with spread and mid price (read-only)",
+        responses(
+            (status = 200, description = "The best level of each side, signers aren't shown", body = BookTop, headers(("x-snapshot-sequence" = u64, description = "The snapshot the data was read from"))),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(sequence = Empty))]
+    pub async fn book_top(tp : SequencerHandle) -> Result<impl warp::Reply, Infallible> {
+        let snapshot = tp.snapshot();
+        Span::current().record("sequence", snapshot.sequence);
+        Ok(with_sequence(warp::reply::json(&snapshot.top()), snapshot.sequence))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/book/depth",
+        tag = "trading",
+        summary = "Aggregated price levels per side, best first (read-only)",
+        params(DepthQuery),
+        responses(
+            (status = 200, description = "Total open amount and order count per price, signers aren't shown", body = Depth, headers(("x-snapshot-sequence" = u64, description = "The snapshot the data was read from"))),
+            (status = 400, description = "`levels` isn't a number"),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(levels = query.levels, sequence = Empty))]
+    pub async fn book_depth(tp : SequencerHandle, query: DepthQuery) -> Result<impl warp::Reply, Infallible> {
+        let snapshot = tp.snapshot();
+        Span::current().record("sequence", snapshot.sequence);
+        let depth = snapshot.depth(query.levels.unwrap_or(DEFAULT_DEPTH_LEVELS));
+        debug!(bids = depth.bids.len(), asks = depth.asks.len(), "Returning depth");
+        Ok(with_sequence(warp::reply::json(&depth), snapshot.sequence))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/book/orders",
+        tag = "trading",
+        summary = "Every resting order with its queue position, without signers (read-only)",
+        description = "Level 3 view of the book. Keep a replica with the `BookUpdate`s from `GET /ws/book`: \
+            subscribe first, then apply the updates with a `sequence` after `x-snapshot-sequence`.",
+        responses(
+            (status = 200, description = "Best prices first, in queue order within a price", body = L3Book, headers(("x-snapshot-sequence" = u64, description = "The snapshot the data was read from"))),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(sequence = Empty))]
+    pub async fn book_orders(tp : SequencerHandle) -> Result<impl warp::Reply, Infallible> {
+        let snapshot = tp.snapshot();
+        Span::current().record("sequence", snapshot.sequence);
+        Ok(with_sequence(warp::reply::json(&snapshot.orders()), snapshot.sequence))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/balance",
+        tag = "accounts",
+        summary = "An account's balance (read-only)",
+        request_body = AccountBalanceRequest,
+        responses(
+            (status = 200, description = "The balance", body = Amount, headers(("x-snapshot-sequence" = u64, description = "The snapshot the data was read from"))),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 404, description = "The account doesn't exist", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(account = %req.account, sequence = Empty))]
+    pub async fn balance(tp : SequencerHandle , req : AccountBalanceRequest) -> Result<impl warp::Reply, Infallible> {
+        let snapshot = tp.snapshot();
+        Span::current().record("sequence", snapshot.sequence);
+        match snapshot.balances.get(&req.account) {
+            Some(balance) => {
+                debug!(%balance, "Balance retrieved");
+                Ok(with_sequence(warp::reply::json(balance).into_response(), snapshot.sequence))
+            },
+            None => {
+                let e = ApplicationError::AccountNotFound(req.account.clone());
+                error!(error = ?e, "Balance retrieval failed");
+                Ok(with_sequence(error_reply(&e), snapshot.sequence))
+            },
+        }
+    }
+
+    /// Trades per page if `GET /trades` doesn't ask for a number
+    const DEFAULT_TRADES_LIMIT: usize = 100;
+    /// The most trades `GET /trades` returns at once
+    const MAX_TRADES_LIMIT: usize = 1000;
+
+    /// The query of `GET /trades`
+    #[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct TradesQuery {
+        /// Only trades with a higher id, 0 if not given
+        since: Option<u64>,
+        /// Trades to return, 100 if not given and at most 1000
+        limit: Option<usize>,
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/trades",
+        tag = "trading",
+        summary = "Recent trades, oldest first (read-only)",
+        description = "Pages through the trade tape, which keeps the latest trades only. \
+            Pass the `id` of the last trade received as `since` to get the next page; \
+            `GET /ws/trades` streams the trades after that.",
+        params(TradesQuery),
+        responses(
+            (status = 200, description = "Trades with an id after `since`", body = [Trade]),
+            (status = 400, description = "`since` or `limit` isn't a number"),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(since = query.since, limit = query.limit))]
+    pub async fn trade_history(tp : SequencerHandle, query: TradesQuery) -> Result<warp::reply::Response, Infallible> {
+        let limit = query.limit.unwrap_or(DEFAULT_TRADES_LIMIT).min(MAX_TRADES_LIMIT);
+        match tp.trades(query.since.unwrap_or(0), limit).await {
+            Ok(trades) => {
+                debug!(trades = trades.len(), "Returning trades");
+                Ok(warp::reply::json(&trades).into_response())
+            },
+            Err(e) => {
+                error!(error = ?e, "Trade history failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    /// The query of `GET /candles`
+    #[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+    #[into_params(parameter_in = Query)]
+    pub struct CandlesQuery {
+        /// Length of the candles
+        interval: Interval,
+        /// Milliseconds since the Unix epoch, the candle containing it is the first. From the first trade kept if not given.
+        from: Option<u64>,
+        /// Milliseconds since the Unix epoch, candles start before it. Now if not given, never later than now.
+        to: Option<u64>,
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/candles",
+        tag = "trading",
+        summary = "OHLCV candles of the trades, oldest first (read-only)",
+        description = "Intervals without trades repeat the previous close with no volume. \
+            Returns the latest 1000 candles of the range if there are more. \
+            The server keeps 1440 candles with trades per interval.",
+        params(CandlesQuery),
+        responses(
+            (status = 200, description = "The candles overlapping `from..to`", body = [Candle]),
+            (status = 400, description = "`interval` is missing or unknown, or `from` or `to` isn't a number"),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(interval = %query.interval, from = query.from, to = query.to))]
+    pub async fn candles(tp : SequencerHandle, query: CandlesQuery) -> Result<warp::reply::Response, Infallible> {
+        // `to` is exclusive, one past now includes the candle in progress
+        let now = now_millis() + 1;
+        let to = query.to.map_or(now, |to| to.min(now));
+        match tp.candles(query.interval, query.from.unwrap_or(0), to).await {
+            Ok(candles) => {
+                debug!(candles = candles.len(), "Returning candles");
+                Ok(warp::reply::json(&candles).into_response())
+            },
+            Err(e) => {
+                error!(error = ?e, "Candles failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/ticker",
+        tag = "trading",
+        summary = "Statistics of the trades in the last 24 hours (read-only)",
+        description = "The window moves on a minute at a time.",
+        responses(
+            (status = 200, description = "Last price, open, high, low, volumes, VWAP, trade count and change", body = Ticker),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all)]
+    pub async fn ticker(tp : SequencerHandle) -> Result<warp::reply::Response, Infallible> {
+        match tp.ticker(now_millis()).await {
+            Ok(ticker) => {
+                debug!(last = ?ticker.last, trades = ticker.trades, "Returning ticker");
+                Ok(warp::reply::json(&ticker).into_response())
+            },
+            Err(e) => {
+                error!(error = ?e, "Ticker failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/instruments",
+        tag = "instruments",
+        summary = "The listed instruments (read-only)",
+        description = "The venue runs a single book, so there's at most one.",
+        responses(
+            (status = 200, description = "Symbol, assets, tick and lot size, order limits, price band and status", body = [Instrument]),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all)]
+    pub async fn instruments(tp : SequencerHandle) -> Result<warp::reply::Response, Infallible> {
+        match tp.instruments().await {
+            Ok(instruments) => {
+                debug!(count = instruments.len(), "Returning instruments");
+                Ok(warp::reply::json(&instruments).into_response())
+            },
+            Err(e) => {
+                error!(error = ?e, "Instruments failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/instruments",
+        tag = "instruments",
+        summary = "List the instrument traded on the book (admin)",
+        description = "From then on every order is checked against it. Without an instrument only the configured decimals are.",
+        request_body = Instrument,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "The listed instrument", body = Instrument),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 409, description = "An instrument is already listed, or the idempotency key was used for a different request", body = ErrorResponse),
+            (status = 422, description = "The definition is inconsistent", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(symbol = %req.symbol))]
+    pub async fn create_instrument(tp : SequencerHandle, idempotency_key: Option<String>, req: Instrument) -> Result<warp::reply::Response, Infallible> {
+        match tp.submit(Command::CreateInstrument(req), idempotency_key).await {
+            Ok(Response::Instrument(instrument)) => {
+                info!("Instrument listed");
+                Ok(warp::reply::json(&instrument).into_response())
+            },
+            Ok(other) => Ok(error_reply(&sequencer::unexpected(other))),
+            Err(e) => {
+                error!(error = ?e, "Listing the instrument failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    #[utoipa::path(
+        put,
+        path = "/instruments/{symbol}",
+        tag = "instruments",
+        summary = "Change an instrument's definition (admin)",
+        description = "Resting orders stay as they are, new orders are checked against the new definition. The symbol can't change.",
+        request_body = Instrument,
+        params(
+            ("symbol" = String, Path, description = "The instrument's symbol"),
+            ("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once"),
+        ),
+        responses(
+            (status = 200, description = "The changed instrument", body = Instrument),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 404, description = "No instrument is listed with the symbol", body = ErrorResponse),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 422, description = "The definition is inconsistent", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(symbol = %symbol))]
+    pub async fn update_instrument(tp : SequencerHandle, idempotency_key: Option<String>, symbol: String, req: Instrument) -> Result<warp::reply::Response, Infallible> {
+        match tp.submit(Command::UpdateInstrument { symbol, instrument: req }, idempotency_key).await {
+            Ok(Response::Instrument(instrument)) => {
+                info!(status = ?instrument.status, "Instrument changed");
+                Ok(warp::reply::json(&instrument).into_response())
+            },
+            Ok(other) => Ok(error_reply(&sequencer::unexpected(other))),
+            Err(e) => {
+                error!(error = ?e, "Changing the instrument failed");
+                Ok(error_reply(&e))
+            },
+        }
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/session",
+        tag = "session",
+        summary = "The trading session (read-only)",
+        description = "New orders are only accepted in `Continuous` and `Auction`, where they're collected without matching \
+            until the book moves on and the auction executes at a single price. Resting orders can be cancelled in every state but `Closed`, \
+            and not at all while the whole venue is halted.",
+        responses(
+            (status = 200, description = "The book's state, the state a resume returns to and whether the venue is halted", body = TradingSession),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all)]
+    pub async fn session(tp : SequencerHandle) -> Result<warp::reply::Response, Infallible> {
+        match tp.session().await {
+            Ok(session) => {
+                debug!(state = ?session.state, venue_halted = session.venue_halted, "Returning session");
+                Ok(warp::reply::json(&session).into_response())
+            },
+            Err(e) => {
+                error!(error = ?e, "Session failed");
+                Ok(error_reply(&e))
+            },
+        }
     }
 
+    #[utoipa::path(
+        put,
+        path = "/session",
+        tag = "session",
+        summary = "Move the book to another session state (admin)",
+        description = "A configured schedule moves it on again when its next phase starts. While halted, this sets the state a resume returns to.",
+        request_body = SessionRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "The session", body = TradingSession),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(state = ?req.state))]
+    pub async fn set_session(tp : SequencerHandle, idempotency_key: Option<String>, req: SessionRequest) -> Result<warp::reply::Response, Infallible> {
+        Ok(session_reply(tp.submit(Command::SetSession { state: req.state }, idempotency_key).await, "Session state set"))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/session/halt",
+        tag = "session",
+        summary = "Halt an instrument or the whole venue (admin)",
+        description = "A halted instrument only accepts cancels. A halted venue accepts neither orders nor cancels.",
+        request_body = HaltRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "The session", body = TradingSession),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 404, description = "No instrument is listed with the symbol", body = ErrorResponse),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(symbol = ?req.symbol))]
+    pub async fn halt(tp : SequencerHandle, idempotency_key: Option<String>, req: HaltRequest) -> Result<warp::reply::Response, Infallible> {
+        Ok(session_reply(tp.submit(Command::Halt { symbol: req.symbol }, idempotency_key).await, "Halted"))
+    }
+
+    #[utoipa::path(
+        post,
+        path = "/session/resume",
+        tag = "session",
+        summary = "Lift the halt of an instrument or the whole venue (admin)",
+        description = "The instrument returns to the state it was halted in, or the one the schedule moved on to since. \
+            Lifting the venue's halt leaves an instrument's halt in place.",
+        request_body = HaltRequest,
+        params(("idempotency-key" = Option<String>, Header, description = "Retries with the same key are applied once")),
+        responses(
+            (status = 200, description = "The session", body = TradingSession),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 404, description = "No instrument is listed with the symbol", body = ErrorResponse),
+            (status = 409, description = "The idempotency key was used for a different request", body = ErrorResponse),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+            (status = 503, description = "The sequencer isn't running", body = ErrorResponse),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(symbol = ?req.symbol))]
+    pub async fn resume(tp : SequencerHandle, idempotency_key: Option<String>, req: HaltRequest) -> Result<warp::reply::Response, Infallible> {
+        Ok(session_reply(tp.submit(Command::Resume { symbol: req.symbol }, idempotency_key).await, "Resumed"))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/auction",
+        tag = "session",
+        summary = "The call auction and its indicative price (read-only)",
+        description = "While the book is in `Auction`, the indicative price is what the auction would execute at if it ended now: \
+            the price executing the most units, then leaving the smallest surplus, then the highest if the surplus is all \
+            on the buy side or the lowest if it's all on the sell side, then the one closest to the last trade.",
+        responses(
+            (status = 200, description = "Whether an auction is running and its indicative price, volume and surplus", body = Auction, headers(("x-snapshot-sequence" = u64, description = "The snapshot the data was read from"))),
+            (status = 401, description = "Missing or unknown API key"),
+            (status = 403, description = "The key's role lacks the permission"),
+            (status = 429, description = "Rate limit exceeded, see `Retry-After`"),
+        ),
+        security(("api_key" = []))
+    )]
+    #[instrument(skip_all, fields(sequence = Empty))]
+    pub async fn auction(tp : SequencerHandle) -> Result<impl warp::Reply, Infallible> {
+        let snapshot = tp.snapshot();
+        Span::current().record("sequence", snapshot.sequence);
+        Ok(with_sequence(warp::reply::json(&snapshot.auction), snapshot.sequence))
+    }
 
-    pub async fn balance(tp : Arc<Mutex<TradingPlatform>> , req : AccountBalanceRequest) -> Result<impl warp::Reply, Infallible> {
-        info!("Balance request for account: {}", req.account);
-        let mut  platform = tp.lock().unwrap();
-        match platform.balance_of(&req.account) {
-            Ok(balance) => {
-                info!("Balance retrieved for account: {}, balance: {}", req.account, balance);
-                Ok(warp::reply::json(&balance))
+    /// Replies with the session a session command left behind
+    fn session_reply(result: Result<Response, ApplicationError>, done: &str) -> warp::reply::Response {
+        match result {
+            Ok(Response::Session(session)) => {
+                info!(state = ?session.state, venue_halted = session.venue_halted, "{}", done);
+                warp::reply::json(&session).into_response()
             },
-           Err(e) => {
-                error!("Balance retrieval failed for account: {}, error: {:?}", req.account, e);
-                Ok(warp::reply::json(&format!("Error: {:?}", e)))
+            Ok(other) => error_reply(&sequencer::unexpected(other)),
+            Err(e) => {
+                error!(error = ?e, "Session change failed");
+                error_reply(&e)
             },
         }
     }
-}
\ No newline at end of file
+
+    /// Milliseconds since the Unix epoch, like trade timestamps
+    fn now_millis() -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    /// Streams every message of a `feed` as JSON text until the client goes away.
+    /// A client too slow to keep up is disconnected rather than sent a feed with gaps.
+    pub async fn stream<T: serde::Serialize + Clone>(socket: WebSocket, feed: &'static str, mut messages: broadcast::Receiver<T>) {
+        let (mut sink, mut stream) = socket.split();
+        info!(feed, "Subscriber connected");
+        loop {
+            tokio::select! {
+                message = messages.recv() => match message {
+                    Ok(message) => {
+                        let text = serde_json::to_string(&message).expect("feed messages serialize");
+                        if sink.send(Message::text(text)).await.is_err() {
+                            break;
+                        }
+                    },
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(feed, skipped, "Subscriber lagged, disconnecting");
+                        let _ = sink.send(Message::close_with(1008u16, "lagged")).await;
+                        break;
+                    },
+                    Err(RecvError::Closed) => {
+                        let _ = sink.send(Message::close()).await;
+                        break;
+                    },
+                },
+                message = stream.next() => match message {
+                    Some(Ok(message)) if message.is_close() => break,
+                    Some(Ok(_)) => {},
+                    Some(Err(_)) | None => break,
+                },
+            }
+        }
+        info!(feed, "Subscriber disconnected");
+    }
+
+    /// The status code for an error and its [`ErrorResponse`] body
+    fn error_reply(e: &ApplicationError) -> warp::reply::Response {
+        let code = match e {
+            ApplicationError::AccountNotFound(_)
+            | ApplicationError::OrderNotFound(_)
+            | ApplicationError::InstrumentNotFound(_) => StatusCode::NOT_FOUND,
+            ApplicationError::AccountUnderFunded(_, _)
+            | ApplicationError::AccountOverFunded(_, _)
+            | ApplicationError::InvalidOrder(_)
+            | ApplicationError::InstrumentSuspended(_)
+            | ApplicationError::PriceOffTick(_, _)
+            | ApplicationError::QuantityOffLot(_, _)
+            | ApplicationError::QuantityOutOfRange(_, _, _)
+            | ApplicationError::NotionalTooSmall(_, _)
+            | ApplicationError::PriceOutsideBand(_, _, _)
+            | ApplicationError::InvalidInstrument(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApplicationError::IdempotencyKeyReused(_)
+            | ApplicationError::InstrumentExists(_)
+            | ApplicationError::MarketNotOpen(_) => StatusCode::CONFLICT,
+            ApplicationError::ReservedAccount(_) => StatusCode::FORBIDDEN,
+            ApplicationError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        warp::reply::with_status(warp::reply::json(&ErrorResponse::from(e)), code).into_response()
+    }
+
+    /// Tells readers which snapshot they were served
+    fn with_sequence(reply: impl warp::Reply, sequence: u64) -> impl warp::Reply {
+        warp::reply::with_header(reply, "x-snapshot-sequence", sequence.to_string())
+    }
+
+    pub async fn metrics() -> Result<impl warp::Reply, Infallible> {
+        Ok(warp::reply::with_header(
+            METRICS.render(),
+            "content-type",
+            "text/plain; version=0.0.4",
+        ))
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/healthz",
+        tag = "operations",
+        summary = "Liveness",
+        responses(
+            (status = 200, description = "The server and sequencer run", body = String),
+            (status = 503, description = "The sequencer stopped", body = String),
+        )
+    )]
+    pub async fn healthz(tp: SequencerHandle) -> Result<impl warp::Reply, Infallible> {
+        Ok(if tp.is_running() {
+            warp::reply::with_status(warp::reply::json(&"ok"), StatusCode::OK)
+        } else {
+            warp::reply::with_status(warp::reply::json(&"sequencer stopped"), StatusCode::SERVICE_UNAVAILABLE)
+        })
+    }
+
+    #[utoipa::path(
+        get,
+        path = "/readyz",
+        tag = "operations",
+        summary = "Readiness",
+        responses(
+            (status = 200, description = "State is recovered and commands are applied", body = String),
+            (status = 503, description = "Still replaying the WAL, or shutting down", body = String),
+        )
+    )]
+    pub async fn readyz(tp: SequencerHandle) -> Result<impl warp::Reply, Infallible> {
+        Ok(if tp.is_ready() {
+            warp::reply::with_status(warp::reply::json(&"ready"), StatusCode::OK)
+        } else {
+            warp::reply::with_status(warp::reply::json(&"not ready"), StatusCode::SERVICE_UNAVAILABLE)
+        })
+    }
+
+    /// Turns rejections into status codes, most importantly denied permissions into 403 and exhausted rate limits into 429
+    pub async fn rejection(err: warp::Rejection) -> Result<warp::reply::Response, Infallible> {
+        let (code, message) = if err.is_not_found() {
+            (StatusCode::NOT_FOUND, "Not found".to_string())
+        } else if err.find::<Unauthorized>().is_some() {
+            (StatusCode::UNAUTHORIZED, "Missing or unknown API key".to_string())
+        } else if let Some(Forbidden { role, permission }) = err.find::<Forbidden>() {
+            (StatusCode::FORBIDDEN, format!("Role {:?} lacks {:?} permission", role, permission))
+        } else if let Some(ForeignAccount { account }) = err.find::<ForeignAccount>() {
+            (StatusCode::FORBIDDEN, format!("The API key can't act for account '{}'", account))
+        } else if let Some(limited) = err.find::<RateLimited>() {
+            let mut response = warp::reply::with_status(
+                warp::reply::json(&"Rate limit exceeded"),
+                StatusCode::TOO_MANY_REQUESTS,
+            )
+            .into_response();
+            let headers = response.headers_mut();
+            headers.insert("retry-after", limited.retry_after_secs().into());
+            headers.insert("x-ratelimit-limit", limited.limit.into());
+            headers.insert("x-ratelimit-remaining", 0.into());
+            return Ok(response);
+        } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+            (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed".to_string())
+        } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+            (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large".to_string())
+        } else if let Some(e) = err.find::<warp::filters::body::BodyDeserializeError>() {
+            (StatusCode::BAD_REQUEST, format!("Invalid body: {}", e))
+        } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+            (StatusCode::BAD_REQUEST, format!("{}", e))
+        } else {
+            error!(rejection = ?err, "Unhandled rejection");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string())
+        };
+        Ok(warp::reply::with_status(warp::reply::json(&message), code).into_response())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::{core::{Receipt, Trade}, errors::ApplicationError};
+use prometheus::{
+    Counter, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use std::{sync::LazyLock, time::Duration};
+
+/// Latency buckets from 10µs to ~10s
+const LATENCY_BUCKETS: &[f64] = &[
+    0.00001, 0.000025, 0.00005, 0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05,
+    0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// All metrics of the trading server, registered with their own [`Registry`].
+pub struct Metrics {
+    registry: Registry,
+    /// HTTP requests by route and status code
+    pub http_requests: IntCounterVec,
+    /// HTTP request latency by route
+    pub http_latency: HistogramVec,
+    /// Orders by outcome: `accepted` or the rejection reason
+    pub orders: IntCounterVec,
+    /// Number of fills
+    pub matches: IntCounter,
+    /// Units traded
+    pub matched_volume: Counter,
+    /// Units traded times their price
+    pub matched_notional: Counter,
+    /// Resting orders per side of the book
+    pub book_depth: IntGaugeVec,
+    /// Time commands spend waiting for the sequencer
+    pub queue_wait: HistogramVec,
+    /// Commands waiting for the sequencer
+    pub queue_depth: IntGauge,
+    /// Time the sequencer spends applying a command
+    pub command_latency: HistogramVec,
+    /// Time it takes to append a command to the write-ahead log
+    pub wal_append: Histogram,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("fintech".to_string()), None)
+            .expect("valid metrics prefix");
+        let metrics = Metrics {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests by route and status"),
+                &["route", "status"],
+            )
+            .unwrap(),
+            http_latency: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route")
+                    .buckets(LATENCY_BUCKETS.to_vec()),
+                &["route"],
+            )
+            .unwrap(),
+            orders: IntCounterVec::new(
+                Opts::new("orders_total", "Orders by outcome (accepted or rejection reason)"),
+                &["outcome"],
+            )
+            .unwrap(),
+            matches: IntCounter::new("matches_total", "Number of fills").unwrap(),
+            matched_volume: Counter::new("matched_volume_total", "Units traded").unwrap(),
+            matched_notional: Counter::new(
+                "matched_notional_total",
+                "Units traded times their price",
+            )
+            .unwrap(),
+            book_depth: IntGaugeVec::new(
+                Opts::new("book_depth", "Resting orders per side of the book"),
+                &["side"],
+            )
+            .unwrap(),
+            queue_wait: HistogramVec::new(
+                HistogramOpts::new(
+                    "sequencer_queue_wait_seconds",
+                    "Time commands wait for the sequencer",
+                )
+                .buckets(LATENCY_BUCKETS.to_vec()),
+                &["command"],
+            )
+            .unwrap(),
+            queue_depth: IntGauge::new(
+                "sequencer_queue_depth",
+                "Commands waiting for the sequencer",
+            )
+            .unwrap(),
+            command_latency: HistogramVec::new(
+                HistogramOpts::new(
+                    "sequencer_command_duration_seconds",
+                    "Time the sequencer spends applying a command",
+                )
+                .buckets(LATENCY_BUCKETS.to_vec()),
+                &["command"],
+            )
+            .unwrap(),
+            wal_append: Histogram::with_opts(
+                HistogramOpts::new(
+                    "wal_append_duration_seconds",
+                    "Time it takes to append a command to the write-ahead log",
+                )
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            )
+            .unwrap(),
+            registry,
+        };
+        metrics.register_all();
+        metrics
+    }
+
+    fn register_all(&self) {
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(self.http_requests.clone()),
+            Box::new(self.http_latency.clone()),
+            Box::new(self.orders.clone()),
+            Box::new(self.matches.clone()),
+            Box::new(self.matched_volume.clone()),
+            Box::new(self.matched_notional.clone()),
+            Box::new(self.book_depth.clone()),
+            Box::new(self.queue_wait.clone()),
+            Box::new(self.queue_depth.clone()),
+            Box::new(self.command_latency.clone()),
+            Box::new(self.wal_append.clone()),
+        ];
+        for collector in collectors {
+            self.registry
+                .register(collector)
+                .expect("metric names are unique");
+        }
+    }
+
+    /// Records a finished HTTP request
+    pub fn observe_request(&self, route: &str, status: u16, elapsed: Duration) {
+        self.http_requests
+            .with_label_values(&[route, &status.to_string()])
+            .inc();
+        self.http_latency
+            .with_label_values(&[route])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Records the outcome of an order, including its fills
+    pub fn observe_order(&self, result: Result<&Receipt, &ApplicationError>) {
+        match result {
+            Ok(receipt) => {
+                self.orders.with_label_values(&["accepted"]).inc();
+                self.matches.inc_by(receipt.matches.len() as u64);
+                for m in &receipt.matches {
+                    self.matched_volume.inc_by(m.amount.to_f64());
+                    self.matched_notional.inc_by(m.price.checked_mul(m.amount).map_or(f64::MAX, |value| value.to_f64()));
+                }
+            }
+            Err(e) => self.orders.with_label_values(&[reason(e)]).inc(),
+        }
+    }
+
+    /// Records trades that happened without an order, like the fills of an auction
+    pub fn observe_trades(&self, trades: &[Trade]) {
+        self.matches.inc_by(trades.len() as u64);
+        for trade in trades {
+            self.matched_volume.inc_by(trade.amount.to_f64());
+            self.matched_notional.inc_by(trade.price.checked_mul(trade.amount).map_or(f64::MAX, |value| value.to_f64()));
+        }
+    }
+
+    /// Records the number of resting orders per side
+    pub fn observe_book(&self, bids: usize, asks: usize) {
+        self.book_depth.with_label_values(&["bid"]).set(bids as i64);
+        self.book_depth.with_label_values(&["ask"]).set(asks as i64);
+    }
+
+    /// Renders all metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding doesn't fail");
+        String::from_utf8(buffer).expect("text encoding is utf-8")
+    }
+}
+
+/// A low-cardinality label for a rejection
+fn reason(e: &ApplicationError) -> &'static str {
+    match e {
+        ApplicationError::AccountNotFound(_) => "account_not_found",
+        ApplicationError::AccountUnderFunded(_, _) => "account_underfunded",
+        ApplicationError::AccountOverFunded(_, _) => "account_overfunded",
+        ApplicationError::Unavailable(_) => "unavailable",
+        ApplicationError::OrderNotFound(_) => "order_not_found",
+        ApplicationError::IdempotencyKeyReused(_) => "idempotency_key_reused",
+        ApplicationError::InvalidOrder(_) => "invalid_order",
+        ApplicationError::InstrumentSuspended(_) => "instrument_suspended",
+        ApplicationError::PriceOffTick(_, _) => "price_off_tick",
+        ApplicationError::QuantityOffLot(_, _) => "quantity_off_lot",
+        ApplicationError::QuantityOutOfRange(_, _, _) => "quantity_out_of_range",
+        ApplicationError::NotionalTooSmall(_, _) => "notional_too_small",
+        ApplicationError::PriceOutsideBand(_, _, _) => "price_outside_band",
+        ApplicationError::InstrumentNotFound(_) => "instrument_not_found",
+        ApplicationError::InstrumentExists(_) => "instrument_exists",
+        ApplicationError::InvalidInstrument(_) => "invalid_instrument",
+        ApplicationError::MarketNotOpen(_) => "market_not_open",
+        ApplicationError::ReservedAccount(_) => "reserved_account",
+    }
+}
+
+/// The process-wide metrics
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::core::{PartialOrder, Quantity, Side};
+
+    #[test]
+    fn test_Metrics_observe_order_counts_outcomes_and_volume() {
+        let metrics = Metrics::new();
+        metrics.observe_order(Ok(&Receipt {
+            ordinal: 2,
+            matches: vec![PartialOrder {
+                price: "10.5".parse().unwrap(),
+                amount: Quantity::units(3),
+                remaining: Quantity::ZERO,
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+                ordinal: 1,
+            }],
+            fees: vec![],
+        }));
+        metrics.observe_order(Err(&ApplicationError::AccountNotFound("BOB".to_string())));
+
+        assert_eq!(metrics.orders.with_label_values(&["accepted"]).get(), 1);
+        assert_eq!(
+            metrics.orders.with_label_values(&["account_not_found"]).get(),
+            1
+        );
+        assert_eq!(metrics.matches.get(), 1);
+        assert_eq!(metrics.matched_volume.get(), 3.0);
+        assert_eq!(metrics.matched_notional.get(), 31.5);
+    }
+
+    #[test]
+    fn test_Metrics_render_uses_prometheus_text_format() {
+        let metrics = Metrics::new();
+        metrics.observe_request("order", 200, Duration::from_millis(3));
+        metrics.observe_book(2, 1);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE fintech_http_requests_total counter"));
+        assert!(text.contains(r#"fintech_http_requests_total{route="order",status="200"} 1"#));
+        assert!(text.contains(r#"fintech_book_depth{side="bid"} 2"#));
+        assert!(text.contains("fintech_http_request_duration_seconds_bucket"));
+    }
+}
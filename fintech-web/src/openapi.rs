@@ -0,0 +1,196 @@
+use crate::auth::API_KEY_HEADER;
+use fintech_common::{
+    core::types::{
+        AccountBalanceRequest, AccountUpdateRequest, Auction, BookEvent, BookOrder, BookTop, BookUpdate, Candle, CancelRequest, Depth, FillFee, HaltRequest,
+        Instrument, InstrumentStatus, Interval, L3Book, Order, PartialOrder, PriceLevel, Receipt, SendRequest, SessionRequest, SessionState, Side, Ticker, Trade, TradingSession, Uncross,
+    },
+    errors::{ApplicationError, ErrorResponse},
+};
+use std::sync::LazyLock;
+use utoipa::{
+    Modify, OpenApi,
+    openapi::security::{ApiKey, ApiKeyValue, SecurityScheme},
+};
+
+/// The HTTP API's contract, generated from the shared types in `fintech-common` and the handlers.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Fintech Trading Platform",
+        description = "Accounts, transfers and a limit order book. \
+            Requests need an API key in the `x-api-key` header unless the server runs without keys. \
+            Every response carries an `x-request-id` header, send one to correlate your own logs. \
+            Writes accept an `idempotency-key` header: a retry with the same key returns the first outcome. \
+            Failed requests return an `ErrorResponse`. \
+            `GET /ws/trades` upgrades to a WebSocket that sends every `Trade` as a JSON text message, \
+            `GET /ws/book` to one that sends a `BookUpdate` for every command that changed the resting orders."
+    ),
+    paths(
+        crate::handlers::deposit,
+        crate::handlers::withdraw,
+        crate::handlers::send,
+        crate::handlers::order,
+        crate::handlers::cancel,
+        crate::handlers::orderbook,
+        crate::handlers::book_top,
+        crate::handlers::book_depth,
+        crate::handlers::book_orders,
+        crate::handlers::balance,
+        crate::handlers::trade_history,
+        crate::handlers::candles,
+        crate::handlers::ticker,
+        crate::handlers::instruments,
+        crate::handlers::create_instrument,
+        crate::handlers::update_instrument,
+        crate::handlers::session,
+        crate::handlers::set_session,
+        crate::handlers::halt,
+        crate::handlers::resume,
+        crate::handlers::auction,
+        crate::handlers::healthz,
+        crate::handlers::readyz,
+    ),
+    components(schemas(
+        AccountUpdateRequest,
+        AccountBalanceRequest,
+        SendRequest,
+        Order,
+        Side,
+        PartialOrder,
+        Receipt,
+        FillFee,
+        CancelRequest,
+        Trade,
+        Interval,
+        Candle,
+        Ticker,
+        Instrument,
+        InstrumentStatus,
+        SessionState,
+        TradingSession,
+        SessionRequest,
+        HaltRequest,
+        Uncross,
+        Auction,
+        PriceLevel,
+        Depth,
+        BookTop,
+        BookOrder,
+        L3Book,
+        BookEvent,
+        BookUpdate,
+        ErrorResponse,
+        ApplicationError
+    )),
+    modifiers(&ApiKeyAuth),
+    tags(
+        (name = "accounts", description = "Deposits, withdrawals, transfers and balances"),
+        (name = "trading", description = "Orders and the order book"),
+        (name = "instruments", description = "Reference data of the traded instrument and the order rules that come with it"),
+        (name = "session", description = "Session states of the trading day, halts and call auctions"),
+        (name = "operations", description = "Probes for orchestrators and load balancers")
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the `x-api-key` header as security scheme
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi
+            .components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+            );
+    }
+}
+
+/// The rendered document, it doesn't change while the server runs
+pub static SPEC: LazyLock<String> = LazyLock::new(|| {
+    let mut spec = ApiDoc::openapi();
+    // The crate has no license field, don't publish an empty one
+    spec.info.license = None;
+    spec.to_pretty_json().expect("the OpenAPI document serializes")
+});
+
+/// A Swagger UI page for browsing and trying out `/openapi.json`
+pub const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Fintech Trading Platform API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+
+    #[test]
+    fn test_ApiDoc_covers_all_routes() {
+        let spec = ApiDoc::openapi();
+        for path in [
+            "/deposit",
+            "/withdraw",
+            "/send",
+            "/order",
+            "/cancel",
+            "/orderbook",
+            "/book/top",
+            "/book/depth",
+            "/book/orders",
+            "/balance",
+            "/trades",
+            "/candles",
+            "/ticker",
+            "/instruments",
+            "/instruments/{symbol}",
+            "/session",
+            "/session/halt",
+            "/session/resume",
+            "/auction",
+            "/healthz",
+            "/readyz",
+        ] {
+            assert!(spec.paths.paths.contains_key(path), "{} is undocumented", path);
+        }
+        let schemas = &spec.components.as_ref().unwrap().schemas;
+        assert!(schemas.contains_key("Order"));
+        assert!(schemas.contains_key("Receipt"));
+        assert!(schemas.contains_key("ErrorResponse"));
+        assert!(schemas.contains_key("Trade"));
+        assert!(schemas.contains_key("Depth"));
+        assert!(schemas.contains_key("BookTop"));
+        assert!(schemas.contains_key("L3Book"));
+        assert!(schemas.contains_key("BookUpdate"));
+    }
+
+    /// Integrators and our own tools use the checked-in copy, regenerate it with
+    /// `cargo run --bin fintech-web -- --print-openapi > openapi.json`
+    #[test]
+    fn test_checked_in_openapi_json_is_up_to_date() {
+        let checked_in = include_str!("../openapi.json");
+        assert_eq!(
+            checked_in.trim_end(),
+            SPEC.trim_end(),
+            "openapi.json is outdated, regenerate it with `cargo run -- --print-openapi > openapi.json`"
+        );
+    }
+}
@@ -0,0 +1,513 @@
+use crate::{
+    core::{Amount, Candles, Instrument, MarketStats, PartialOrder, Trade, TradingSession},
+    fees::Volumes,
+    sequencer::Command,
+    trading_platform::TradingPlatform,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// Default number of commands after which a checkpoint is written and the WAL truncated
+pub const DEFAULT_CHECKPOINT_EVERY: u64 = 10_000;
+
+/// One line of the write-ahead log
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    /// The sequence number the command was applied with
+    pub sequence: u64,
+    pub command: Command,
+    /// The key the caller submitted the command with, so retries after a restart are still recognized
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// When the command was applied, in milliseconds since the Unix epoch. A replay applies it at the same time,
+    /// so trades get the same fee tiers, candles and statistics. Missing from entries written before it was kept.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+}
+
+/// An [`Entry`] to write, without copying the command
+#[derive(Serialize)]
+struct EntryRef<'a> {
+    sequence: u64,
+    command: &'a Command,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idempotency_key: Option<&'a str>,
+    timestamp: u64,
+}
+
+/// A line of the write-ahead log marking the entry with this sequence number, the one before it, as poisoned:
+/// applying it panicked, so it's skipped on replay
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Poisoned {
+    poisoned: u64,
+}
+
+/// An append-only log of state-changing commands, one JSON object per line.
+/// Entries are handed to the OS before the command is applied, so they survive the process
+/// crashing; [`Wal::sync`] also gets them to disk.
+#[derive(Debug)]
+pub struct Wal {
+    writer: BufWriter<File>,
+}
+
+impl Wal {
+    /// Opens the log at `path` for appending, creating it if needed
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Wal {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Reads all entries at `path`, without the poisoned ones. A missing file is an empty log.
+    /// A torn last line (the process died mid-write) is skipped, any other malformed line is an error.
+    pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let lines = BufReader::new(file).lines().collect::<io::Result<Vec<_>>>()?;
+        let last = lines.len().saturating_sub(1);
+        let mut entries = Vec::with_capacity(lines.len());
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = match serde_json::from_str(line) {
+                Ok(entry) => {
+                    entries.push(entry);
+                    continue;
+                }
+                Err(e) => e,
+            };
+            match serde_json::from_str(line) {
+                Ok(Poisoned { poisoned }) => {
+                    tracing::warn!(path = %path.display(), sequence = poisoned, "Skipping poisoned WAL entry");
+                    // Only the entry before the marker, the sequence number is handed out again after a restart
+                    entries.retain(|e: &Entry| e.sequence != poisoned);
+                }
+                Err(_) if i == last => {
+                    tracing::warn!(path = %path.display(), error = %error, "Skipping torn last WAL entry");
+                }
+                Err(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{}:{}: {}", path.display(), i + 1, error),
+                    ));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Appends an entry for a command applied at `timestamp` and hands it to the OS
+    pub fn append(&mut self, sequence: u64, command: &Command, idempotency_key: Option<&str>, timestamp: u64) -> io::Result<()> {
+        serde_json::to_writer(
+            &mut self.writer,
+            &EntryRef {
+                sequence,
+                command,
+                idempotency_key,
+                timestamp,
+            },
+        )?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+
+    /// Marks the last entry, the one with this `sequence` number, as poisoned and waits for the disk
+    pub fn poison(&mut self, sequence: u64) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, &Poisoned { poisoned: sequence })?;
+        self.writer.write_all(b"\n")?;
+        self.sync()
+    }
+
+    /// Flushes buffered entries and waits for the disk
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()
+    }
+
+    /// Drops all entries, once a [`Checkpoint`] covers them
+    pub fn truncate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().set_len(0)?;
+        self.writer.get_ref().sync_data()
+    }
+}
+
+/// The complete state of the platform after `sequence` commands, written periodically and on shutdown
+/// so the next start only replays the WAL from there. The transaction log isn't kept, the trade tape, candles, statistics, trading volumes, the instrument, the session and whether an auction is running are.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Sequence number of the last command included
+    pub sequence: u64,
+    /// The last order ordinal handed out
+    pub ordinal: u64,
+    /// All account balances
+    pub balances: HashMap<String, Amount>,
+    /// The resting orders
+    pub orderbook: Vec<PartialOrder>,
+    /// The trades still on the tape, oldest first. Missing from checkpoints written before there was a tape.
+    #[serde(default)]
+    pub trades: Vec<Trade>,
+    /// The candles of the trades. Missing from checkpoints written before there were candles.
+    #[serde(default)]
+    pub candles: Candles,
+    /// The rolling 24 hour statistics. Missing from checkpoints written before there were statistics.
+    #[serde(default)]
+    pub stats: MarketStats,
+    /// Recent trading volume per account, for the fee tiers. Missing from checkpoints written before there were fees.
+    #[serde(default)]
+    pub volumes: Volumes,
+    /// The listed instrument. Missing from checkpoints written before there were instruments.
+    #[serde(default)]
+    pub instrument: Option<Instrument>,
+    /// The trading session. Missing from checkpoints written before there were sessions.
+    #[serde(default)]
+    pub session: TradingSession,
+    /// Whether a call auction is running. Missing from checkpoints written before there were auctions.
+    #[serde(default)]
+    pub auction: bool,
+}
+
+impl Checkpoint {
+    /// Captures the state of the `platform`
+    pub fn capture(platform: &TradingPlatform, sequence: u64) -> Self {
+        Checkpoint {
+            sequence,
+            ordinal: platform.ordinal(),
+            balances: platform.accounts.balances().into_iter().collect(),
+            orderbook: platform.orderbook(),
+            trades: platform.trades_since(0, usize::MAX),
+            candles: platform.all_candles().clone(),
+            stats: platform.market_stats().clone(),
+            volumes: platform.volumes().clone(),
+            instrument: platform.instrument().cloned(),
+            session: platform.session().clone(),
+            auction: platform.auction().active,
+        }
+    }
+
+    /// Reads a checkpoint, `None` if there is none yet. Fails if its instrument isn't valid.
+    pub fn read(path: &Path) -> io::Result<Option<Self>> {
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e));
+                let checkpoint: Checkpoint = serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+                // The definition may predate checks that were added since
+                if let Some(instrument) = &checkpoint.instrument {
+                    instrument.validate().map_err(|e| invalid(format!("instrument {}: {}", instrument.symbol, e)))?;
+                }
+                Ok(Some(checkpoint))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the checkpoint to a temporary file and moves it in place, so a crash never leaves half a checkpoint
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, self)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Rebuilds the platform from the checkpoint
+    pub fn restore(self) -> TradingPlatform {
+        TradingPlatform::restore(
+            self.ordinal,
+            self.balances,
+            self.orderbook,
+            self.trades,
+            self.candles,
+            self.stats,
+            self.volumes,
+            self.instrument,
+            self.session,
+            self.auction,
+        )
+    }
+}
+
+/// Where the sequencer keeps its state. Either path may be unset.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Persistence {
+    pub wal_path: Option<PathBuf>,
+    pub snapshot_path: Option<PathBuf>,
+    /// Write a checkpoint and truncate the WAL after this many commands, 0 only does on shutdown
+    pub checkpoint_every: u64,
+}
+
+impl Persistence {
+    /// Whether anything is persisted at all
+    pub fn is_enabled(&self) -> bool {
+        self.wal_path.is_some() || self.snapshot_path.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::core::{Order, Precision, Price, Quantity, Side};
+
+    /// When the commands in these tests are applied
+    const NOW: u64 = 1_700_000_000_000;
+
+    /// A fresh path in the temp directory, removed when dropped
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!(
+                "fintech-{}-{}-{}",
+                std::process::id(),
+                uuid::Uuid::new_v4().simple(),
+                name
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(self.0.with_extension("tmp"));
+        }
+    }
+
+    fn deposit(account: &str, amount: u64) -> Command {
+        Command::Deposit {
+            account: account.to_string(),
+            amount: Amount::units(amount),
+        }
+    }
+
+    #[test]
+    fn test_Wal_append_and_read_roundtrip() {
+        let path = TempPath::new("wal.jsonl");
+        let mut wal = Wal::open(&path.0).unwrap();
+        wal.append(1, &deposit("ALICE", 100), None, NOW).unwrap();
+        wal.append(
+            2,
+            &Command::Order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            }),
+            Some("order-1"),
+            NOW,
+        )
+        .unwrap();
+
+        let entries = Wal::read(&path.0).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(
+            entries[0],
+            Entry {
+                sequence: 1,
+                command: deposit("ALICE", 100),
+                idempotency_key: None,
+                timestamp: Some(NOW),
+            }
+        );
+        assert_eq!(entries[1].sequence, 2);
+        assert_eq!(entries[1].idempotency_key.as_deref(), Some("order-1"));
+        assert!(matches!(entries[1].command, Command::Order(_)));
+
+        wal.truncate().unwrap();
+        assert!(Wal::read(&path.0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_Wal_read_accepts_whole_numbers_written_before_decimals() {
+        let path = TempPath::new("wal.jsonl");
+        fs::write(
+            &path.0,
+            concat!(
+                r#"{"sequence":1,"command":{"type":"deposit","account":"ALICE","amount":100}}"#,
+                "\n",
+                r#"{"sequence":2,"command":{"type":"order","price":"10.25","amount":"0.5","side":"Sell","signer":"ALICE"}}"#,
+                "\n",
+            ),
+        )
+        .unwrap();
+
+        let entries = Wal::read(&path.0).unwrap();
+        assert_eq!(entries[0].command, deposit("ALICE", 100));
+        assert_eq!(entries[0].timestamp, None);
+        assert_eq!(
+            entries[1].command,
+            Command::Order(Order {
+                price: "10.25".parse().unwrap(),
+                amount: "0.5".parse().unwrap(),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+        );
+        // Written back as decimal strings
+        let line = serde_json::to_string(&entries[0]).unwrap();
+        assert!(line.contains(r#""amount":"100""#));
+    }
+
+    #[test]
+    fn test_Wal_read_skips_torn_last_entry() {
+        let path = TempPath::new("wal.jsonl");
+        let mut wal = Wal::open(&path.0).unwrap();
+        wal.append(1, &deposit("ALICE", 100), None, NOW).unwrap();
+        drop(wal);
+        let mut file = OpenOptions::new().append(true).open(&path.0).unwrap();
+        file.write_all(br#"{"sequence":2,"comm"#).unwrap();
+
+        assert_eq!(Wal::read(&path.0).unwrap().len(), 1);
+        assert!(Wal::read(&TempPath::new("missing.jsonl").0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_Wal_read_skips_poisoned_entries() {
+        let path = TempPath::new("wal.jsonl");
+        let mut wal = Wal::open(&path.0).unwrap();
+        wal.append(1, &deposit("ALICE", 100), None, NOW).unwrap();
+        wal.append(2, &deposit("BOB", 100), None, NOW).unwrap();
+        wal.poison(2).unwrap();
+        // After a restart the sequence number is handed out again
+        wal.append(2, &deposit("CHARLIE", 100), None, NOW).unwrap();
+        drop(wal);
+
+        let entries = Wal::read(&path.0).unwrap();
+        assert_eq!(
+            entries.iter().map(|e| (e.sequence, e.command.clone())).collect::<Vec<_>>(),
+            vec![(1, deposit("ALICE", 100)), (2, deposit("CHARLIE", 100))]
+        );
+    }
+
+    #[test]
+    fn test_Wal_read_rejects_corrupt_entries() {
+        let path = TempPath::new("wal.jsonl");
+        fs::write(&path.0, "garbage\n{\"sequence\":1,\"command\":{\"type\":\"orderbook\"}}\n").unwrap();
+        assert_eq!(
+            Wal::read(&path.0).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn test_Checkpoint_restore_continues_where_it_left_off() {
+        let mut platform = TradingPlatform::new();
+        platform.deposit("ALICE", Amount::units(100)).unwrap();
+        platform.deposit("BOB", Amount::units(100)).unwrap();
+        for (side, signer, price) in [(Side::Sell, "ALICE", 10), (Side::Buy, "BOB", 8)] {
+            platform
+                .order(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(2),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        }
+
+        let path = TempPath::new("checkpoint.json");
+        Checkpoint::capture(&platform, 4).write(&path.0).unwrap();
+        let checkpoint = Checkpoint::read(&path.0).unwrap().unwrap();
+        assert_eq!(checkpoint.sequence, 4);
+        assert_eq!(checkpoint.ordinal, 2);
+
+        let mut restored = checkpoint.restore();
+        assert_eq!(restored.orderbook(), platform.orderbook());
+        assert_eq!(restored.accounts.balances(), platform.accounts.balances());
+
+        // The resting ask still matches and ordinals keep counting
+        let receipt = restored
+            .order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            }, NOW)
+            .unwrap();
+        assert_eq!(receipt.ordinal, 3);
+        assert_eq!(receipt.matches[0].ordinal, 1);
+        assert_eq!(restored.balance_of("ALICE"), Ok(&Amount::units(110)));
+
+        assert_eq!(Checkpoint::read(&TempPath::new("missing.json").0).unwrap(), None);
+    }
+
+    #[test]
+    fn test_Checkpoint_keeps_the_trade_tape_candles_and_stats() {
+        let mut platform = TradingPlatform::new();
+        platform.deposit("ALICE", Amount::units(100)).unwrap();
+        platform.deposit("BOB", Amount::units(100)).unwrap();
+        let trade = |platform: &mut TradingPlatform, side| {
+            let signer = if side == Side::Buy { "BOB" } else { "ALICE" };
+            platform
+                .order(Order {
+                    price: Price::units(10),
+                    amount: Quantity::units(1),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        };
+        trade(&mut platform, Side::Sell);
+        trade(&mut platform, Side::Buy);
+
+        let path = TempPath::new("checkpoint.json");
+        Checkpoint::capture(&platform, 4).write(&path.0).unwrap();
+        let mut restored = Checkpoint::read(&path.0).unwrap().unwrap().restore();
+        assert_eq!(restored.trades_since(0, 10), platform.trades_since(0, 10));
+        assert_eq!(restored.all_candles(), platform.all_candles());
+        assert_eq!(restored.market_stats(), platform.market_stats());
+
+        trade(&mut restored, Side::Sell);
+        trade(&mut restored, Side::Buy);
+        let ids: Vec<_> = restored.trades_since(0, 10).iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_Checkpoint_keeps_the_instrument() {
+        let mut platform = TradingPlatform::new();
+        let instrument = Instrument {
+            symbol: "BTC-USD".to_string(),
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            tick_size: "0.5".parse().unwrap(),
+            lot_size: "0.01".parse().unwrap(),
+            precision: Default::default(),
+            min_quantity: Quantity::ZERO,
+            max_quantity: None,
+            min_notional: Amount::ZERO,
+            price_band_bps: None,
+            status: Default::default(),
+        };
+        let command = Command::CreateInstrument(instrument.clone());
+        command.apply(&mut platform, NOW).unwrap();
+
+        let path = TempPath::new("checkpoint.json");
+        Checkpoint::capture(&platform, 1).write(&path.0).unwrap();
+        let restored = Checkpoint::read(&path.0).unwrap().unwrap().restore();
+        assert_eq!(restored.instrument(), Some(&instrument));
+
+        // Trade values wouldn't be exact with this many decimals
+        let mut checkpoint = Checkpoint::capture(&platform, 1);
+        checkpoint.instrument = Some(Instrument {
+            precision: Precision { price: 4, quantity: 4 },
+            ..instrument
+        });
+        checkpoint.write(&path.0).unwrap();
+        assert_eq!(Checkpoint::read(&path.0).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
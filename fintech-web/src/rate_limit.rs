@@ -0,0 +1,357 @@
+use crate::auth::{API_KEY_HEADER, ApiKeys, Permission};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use warp::{Filter, Rejection, Reply, reject::Reject};
+
+/// Buckets are pruned once there are more than this many callers
+const MAX_BUCKETS: usize = 10_000;
+
+/// How many requests a caller may burst and how quickly the allowance refills.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limit {
+    /// Bucket size, i.e. the number of requests that can be made at once
+    pub burst: u32,
+    /// Tokens added to the bucket every second
+    pub per_second: f64,
+}
+
+/// The limits for each endpoint class
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub read: Limit,
+    pub trade: Limit,
+    pub admin: Limit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            read: Limit {
+                burst: 50,
+                per_second: 20.0,
+            },
+            trade: Limit {
+                burst: 20,
+                per_second: 10.0,
+            },
+            admin: Limit {
+                burst: 10,
+                per_second: 2.0,
+            },
+        }
+    }
+}
+
+impl RateLimits {
+    /// Returns the limit for an endpoint class
+    pub fn of(&self, class: Permission) -> Limit {
+        match class {
+            Permission::Read => self.read,
+            Permission::Trade => self.trade,
+            Permission::Admin => self.admin,
+        }
+    }
+
+    /// Checks that every limit allows at least some traffic
+    pub fn validate(&self) -> Result<(), String> {
+        for (class, limit) in [("read", self.read), ("trade", self.trade), ("admin", self.admin)] {
+            if limit.burst == 0 || limit.per_second.is_nan() || limit.per_second <= 0.0 {
+                return Err(format!("Limit for '{}' must be positive", class));
+            }
+        }
+        Ok(())
+    }
+
+    /// Overrides the defaults with `class=burst/rate` pairs, e.g. `read=100/50,trade=5/1`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut limits = RateLimits::default();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (class, limit) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Expected 'class=burst/rate', got '{}'", pair))?;
+            let (burst, rate) = limit
+                .split_once('/')
+                .ok_or_else(|| format!("Expected 'burst/rate', got '{}'", limit))?;
+            let limit = Limit {
+                burst: burst
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid burst '{}'", burst))?,
+                per_second: rate
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid rate '{}'", rate))?,
+            };
+            match class.trim() {
+                "read" => limits.read = limit,
+                "trade" => limits.trade = limit,
+                "admin" => limits.admin = limit,
+                other => return Err(format!("Unknown endpoint class '{}'", other)),
+            }
+        }
+        limits.validate().map(|_| limits)
+    }
+}
+
+/// A classic token bucket: every request takes a token, tokens refill continuously.
+#[derive(Clone, Debug)]
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn full(limit: &Limit, now: Instant) -> Self {
+        TokenBucket {
+            tokens: limit.burst as f64,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst as f64);
+        self.last_refill = now;
+    }
+
+    /// Takes a token and returns how many are left, or how long to wait for the next one
+    fn try_take(&mut self, limit: &Limit, now: Instant) -> Result<u32, Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(self.tokens.floor() as u32)
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second))
+        }
+    }
+}
+
+/// The current usage of a caller's bucket, reported in response headers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Usage {
+    pub limit: u32,
+    pub remaining: u32,
+}
+
+impl Usage {
+    /// Adds the `X-RateLimit-*` headers to a reply
+    pub fn apply(&self, reply: impl Reply) -> warp::reply::Response {
+        let mut response = reply.into_response();
+        let headers = response.headers_mut();
+        headers.insert("x-ratelimit-limit", self.limit.into());
+        headers.insert("x-ratelimit-remaining", self.remaining.into());
+        response
+    }
+}
+
+/// The caller exhausted its allowance
+#[derive(Debug)]
+pub struct RateLimited {
+    pub limit: u32,
+    pub retry_after: Duration,
+}
+
+impl RateLimited {
+    /// The value of the `Retry-After` header in whole seconds (at least 1)
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil().max(1.0) as u64
+    }
+}
+
+impl Reject for RateLimited {}
+
+/// Token buckets per endpoint class and caller (API key or IP address).
+#[derive(Debug)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    keys: Arc<ApiKeys>,
+    buckets: Mutex<HashMap<(Permission, String), TokenBucket>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter without any buckets. Only the `keys` issued there get a bucket of their own.
+    pub fn new(limits: RateLimits, keys: Arc<ApiKeys>) -> Self {
+        RateLimiter {
+            limits,
+            keys,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Identifies the caller by API key once it's authenticated, otherwise by the remote IP address.
+    /// A made-up key would get a fresh bucket with every request.
+    fn caller_of(&self, key: Option<&str>, addr: Option<SocketAddr>) -> String {
+        match (self.keys.authenticate(key), addr) {
+            (Some(key), _) => format!("key:{}", key),
+            (None, Some(addr)) => format!("ip:{}", addr.ip()),
+            (None, None) => "unknown".to_string(),
+        }
+    }
+
+    /// Takes a token from the `caller`'s bucket for this endpoint `class`
+    pub fn check(&self, class: Permission, caller: &str, now: Instant) -> Result<Usage, RateLimited> {
+        let limit = self.limits.of(class);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() > MAX_BUCKETS {
+            // Full buckets carry no information, drop them
+            buckets.retain(|(class, _), bucket| {
+                let limit = self.limits.of(*class);
+                bucket.refill(&limit, now);
+                bucket.tokens < limit.burst as f64
+            });
+        }
+        buckets
+            .entry((class, caller.to_string()))
+            .or_insert_with(|| TokenBucket::full(&limit, now))
+            .try_take(&limit, now)
+            .map(|remaining| Usage {
+                limit: limit.burst,
+                remaining,
+            })
+            .map_err(|retry_after| RateLimited {
+                limit: limit.burst,
+                retry_after,
+            })
+    }
+}
+
+/// A filter that takes a token for the caller or rejects with [`RateLimited`]
+pub fn check(
+    limiter: Arc<RateLimiter>,
+    class: Permission,
+) -> impl Filter<Extract = (Usage,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(API_KEY_HEADER)
+        .and(warp::addr::remote())
+        .and_then(move |key: Option<String>, addr: Option<SocketAddr>| {
+            let limiter = limiter.clone();
+            async move {
+                limiter
+                    .check(class, &limiter.caller_of(key.as_deref(), addr), Instant::now())
+                    .map_err(warp::reject::custom)
+            }
+        })
+}
+
+/// Awaits a handler and adds the caller's [`Usage`] to its reply
+pub async fn decorate<R: Reply>(
+    usage: Usage,
+    reply: impl Future<Output = Result<R, Infallible>>,
+) -> Result<warp::reply::Response, Infallible> {
+    reply.await.map(|r| usage.apply(r))
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::auth::Role;
+
+    fn limits(burst: u32, per_second: f64) -> RateLimits {
+        let limit = Limit { burst, per_second };
+        RateLimits {
+            read: limit,
+            trade: limit,
+            admin: limit,
+        }
+    }
+
+    #[test]
+    fn test_RateLimiter_check_allows_burst_then_rejects() {
+        let limiter = RateLimiter::new(limits(2, 1.0), Arc::new(ApiKeys::new()));
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.check(Permission::Trade, "ALICE", now).unwrap(),
+            Usage {
+                limit: 2,
+                remaining: 1
+            }
+        );
+        assert_eq!(
+            limiter.check(Permission::Trade, "ALICE", now).unwrap(),
+            Usage {
+                limit: 2,
+                remaining: 0
+            }
+        );
+        let rejected = limiter.check(Permission::Trade, "ALICE", now).unwrap_err();
+        assert_eq!(rejected.limit, 2);
+        assert_eq!(rejected.retry_after_secs(), 1);
+    }
+
+    #[test]
+    fn test_RateLimiter_check_refills_over_time() {
+        let limiter = RateLimiter::new(limits(1, 2.0), Arc::new(ApiKeys::new()));
+        let now = Instant::now();
+
+        assert!(limiter.check(Permission::Read, "ALICE", now).is_ok());
+        assert!(limiter.check(Permission::Read, "ALICE", now).is_err());
+        assert!(
+            limiter
+                .check(Permission::Read, "ALICE", now + Duration::from_millis(500))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_RateLimiter_check_separates_callers_and_classes() {
+        let limiter = RateLimiter::new(limits(1, 1.0), Arc::new(ApiKeys::new()));
+        let now = Instant::now();
+
+        assert!(limiter.check(Permission::Trade, "ALICE", now).is_ok());
+        assert!(limiter.check(Permission::Trade, "ALICE", now).is_err());
+        assert!(limiter.check(Permission::Trade, "BOB", now).is_ok());
+        assert!(limiter.check(Permission::Read, "ALICE", now).is_ok());
+    }
+
+    #[test]
+    fn test_RateLimiter_caller_of_only_trusts_issued_keys() {
+        let mut keys = ApiKeys::new();
+        keys.insert("bot", Role::Trader);
+        let limiter = RateLimiter::new(limits(1, 1.0), Arc::new(keys));
+        let addr = Some(SocketAddr::from(([10, 0, 0, 1], 4000)));
+
+        assert_eq!(limiter.caller_of(Some("bot"), addr), "key:bot");
+        assert_eq!(limiter.caller_of(Some("made-up"), addr), "ip:10.0.0.1");
+        assert_eq!(limiter.caller_of(None, addr), "ip:10.0.0.1");
+        assert_eq!(limiter.caller_of(Some("made-up"), None), "unknown");
+
+        let limiter = RateLimiter::new(limits(1, 1.0), Arc::new(ApiKeys::disabled()));
+        assert_eq!(limiter.caller_of(Some("bot"), addr), "ip:10.0.0.1");
+    }
+
+    #[test]
+    fn test_RateLimits_parse_works() {
+        let limits = RateLimits::parse("read=100/50, trade=5/0.5").unwrap();
+        assert_eq!(
+            limits.read,
+            Limit {
+                burst: 100,
+                per_second: 50.0
+            }
+        );
+        assert_eq!(
+            limits.trade,
+            Limit {
+                burst: 5,
+                per_second: 0.5
+            }
+        );
+        assert_eq!(limits.admin, RateLimits::default().admin);
+
+        assert!(RateLimits::parse("read=0/1").is_err());
+        assert!(RateLimits::parse("bulk=1/1").is_err());
+        assert!(RateLimits::parse("read=1").is_err());
+    }
+}
@@ -0,0 +1,1373 @@
+use crate::{
+    core::{Amount, BookUpdate, Candle, Instrument, Interval, Order, SessionState, TradingSession, PartialOrder, Receipt, Ticker, Trade},
+    errors::ApplicationError,
+    metrics::METRICS,
+    persistence::{Checkpoint, Persistence, Wal},
+    session::{Clock, SystemClock},
+    snapshot::{Replica, Snapshot},
+    trading_platform::TradingPlatform,
+    tx::Tx,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{Span, field};
+
+/// Default number of commands that can wait for the sequencer before callers are held back
+pub const DEFAULT_QUEUE_CAPACITY: usize = 1024;
+
+/// Trades buffered per subscriber before a slow one misses some
+const TRADE_BUFFER: usize = 4096;
+
+/// Book updates buffered per subscriber before a slow one misses some
+const BOOK_UPDATE_BUFFER: usize = 4096;
+
+/// Number of idempotency keys remembered, older ones are forgotten first
+const IDEMPOTENCY_KEYS: usize = 10_000;
+
+/// A request to the [`TradingPlatform`], applied in the order the sequencer receives them.
+/// State-changing commands are written to the [`Wal`] as is.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Command {
+    /// Add funds to an account
+    Deposit { account: String, amount: Amount },
+    /// Remove funds from an account
+    Withdraw { account: String, amount: Amount },
+    /// Transfer funds between accounts
+    Send {
+        sender: String,
+        recipient: String,
+        amount: Amount,
+    },
+    /// Match an order and settle the outcome
+    Order(Order),
+    /// Remove a resting order
+    Cancel { signer: String, ordinal: u64 },
+    /// Fetch the complete order book
+    Orderbook,
+    /// Fetch an account's balance
+    Balance { account: String },
+    /// Fetch up to `limit` trades after the one with the id `since`
+    Trades { since: u64, limit: usize },
+    /// Fetch the candles of `interval` overlapping `from..to`
+    Candles { interval: Interval, from: u64, to: u64 },
+    /// Fetch the statistics of the 24 hours up to `now`
+    Ticker { now: u64 },
+    /// Fetch the listed instruments
+    Instruments,
+    /// List the instrument traded on the book
+    CreateInstrument(Instrument),
+    /// Change the definition of the instrument listed as `symbol`
+    UpdateInstrument { symbol: String, instrument: Instrument },
+    /// Fetch the trading session
+    Session,
+    /// Move the book to another session state
+    SetSession { state: SessionState },
+    /// Halt the listed instrument with `symbol`, or the whole venue without one
+    Halt { symbol: Option<String> },
+    /// Lift a halt of the listed instrument with `symbol`, or of the whole venue without one
+    Resume { symbol: Option<String> },
+}
+
+/// The outcome of a successfully applied [`Command`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    Tx(Tx),
+    Transfer(Tx, Tx),
+    Receipt(Receipt),
+    Cancelled(PartialOrder),
+    Orderbook(Vec<PartialOrder>),
+    Balance(Amount),
+    Trades(Vec<Trade>),
+    Candles(Vec<Candle>),
+    Ticker(Ticker),
+    Instrument(Instrument),
+    Instruments(Vec<Instrument>),
+    Session(TradingSession),
+}
+
+impl Command {
+    /// A short name for logs and metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Deposit { .. } => "deposit",
+            Command::Withdraw { .. } => "withdraw",
+            Command::Send { .. } => "send",
+            Command::Order(_) => "order",
+            Command::Cancel { .. } => "cancel",
+            Command::Orderbook => "orderbook",
+            Command::Balance { .. } => "balance",
+            Command::Trades { .. } => "trades",
+            Command::Candles { .. } => "candles",
+            Command::Ticker { .. } => "ticker",
+            Command::Instruments => "instruments",
+            Command::CreateInstrument(_) => "create_instrument",
+            Command::UpdateInstrument { .. } => "update_instrument",
+            Command::Session => "session",
+            Command::SetSession { .. } => "set_session",
+            Command::Halt { .. } => "halt",
+            Command::Resume { .. } => "resume",
+        }
+    }
+
+    /// The account a command acts for. Admin commands act for the venue, `None`.
+    pub fn account(&self) -> Option<&str> {
+        match self {
+            Command::Deposit { account, .. } | Command::Withdraw { account, .. } | Command::Balance { account } => {
+                Some(account)
+            }
+            Command::Send { sender, .. } => Some(sender),
+            Command::Order(order) => Some(&order.signer),
+            Command::Cancel { signer, .. } => Some(signer),
+            _ => None,
+        }
+    }
+
+    /// Whether the command only reads state
+    pub fn is_read(&self) -> bool {
+        matches!(
+            self,
+            Command::Orderbook
+                | Command::Balance { .. }
+                | Command::Trades { .. }
+                | Command::Candles { .. }
+                | Command::Ticker { .. }
+                | Command::Instruments
+                | Command::Session
+        )
+    }
+
+    /// Applies the command to the platform at `now`, in milliseconds since the Unix epoch. Trades take their time from it.
+    pub fn apply(self, platform: &mut TradingPlatform, now: u64) -> Result<Response, ApplicationError> {
+        match self {
+            Command::Deposit { account, amount } => platform.deposit(&account, amount).map(Response::Tx),
+            Command::Withdraw { account, amount } => {
+                platform.withdraw(&account, amount).map(Response::Tx)
+            }
+            Command::Send {
+                sender,
+                recipient,
+                amount,
+            } => platform
+                .send(&sender, &recipient, amount)
+                .map(|(t1, t2)| Response::Transfer(t1, t2)),
+            Command::Order(order) => platform.order(order, now).map(Response::Receipt),
+            Command::Cancel { signer, ordinal } => platform.cancel(&signer, ordinal).map(Response::Cancelled),
+            Command::Orderbook => Ok(Response::Orderbook(platform.orderbook())),
+            Command::Balance { account } => platform.balance_of(&account).map(|b| Response::Balance(*b)),
+            Command::Trades { since, limit } => Ok(Response::Trades(platform.trades_since(since, limit))),
+            Command::Candles { interval, from, to } => Ok(Response::Candles(platform.candles(interval, from, to))),
+            Command::Ticker { now } => Ok(Response::Ticker(platform.ticker(now))),
+            Command::Instruments => Ok(Response::Instruments(platform.instrument().into_iter().cloned().collect())),
+            Command::CreateInstrument(instrument) => platform.create_instrument(instrument).map(Response::Instrument),
+            Command::UpdateInstrument { symbol, instrument } => {
+                platform.update_instrument(&symbol, instrument).map(Response::Instrument)
+            }
+            Command::Session => Ok(Response::Session(platform.session().clone())),
+            Command::SetSession { state } => Ok(Response::Session(platform.set_session_state(state, now))),
+            Command::Halt { symbol } => platform.halt(symbol.as_deref()).map(Response::Session),
+            Command::Resume { symbol } => platform.resume(symbol.as_deref(), now).map(Response::Session),
+        }
+    }
+}
+
+/// A command on its way to the sequencer
+struct Envelope {
+    command: Command,
+    /// Set by callers that may retry, so a command is only applied once
+    idempotency_key: Option<String>,
+    submitted_at: Instant,
+    /// The caller's span, to continue its trace
+    span: Span,
+    reply: oneshot::Sender<Result<Response, ApplicationError>>,
+}
+
+/// An idempotency key together with the account the command acts for, so callers only share keys with themselves
+type ScopedKey = (Option<String>, String);
+
+/// The outcomes of recent state-changing commands by idempotency key
+#[derive(Default)]
+struct RecentResults {
+    results: HashMap<ScopedKey, (Command, Result<Response, ApplicationError>)>,
+    /// Keys from oldest to newest
+    keys: VecDeque<ScopedKey>,
+}
+
+impl RecentResults {
+    fn scoped(key: &str, command: &Command) -> ScopedKey {
+        (command.account().map(String::from), key.to_string())
+    }
+
+    /// The first outcome for a retried `command`, or an error if the key was used for another command
+    fn replay(&self, key: &str, command: &Command) -> Option<Result<Response, ApplicationError>> {
+        self.results.get(&RecentResults::scoped(key, command)).map(|(first, result)| {
+            if first == command {
+                result.clone()
+            } else {
+                Err(ApplicationError::IdempotencyKeyReused(key.to_string()))
+            }
+        })
+    }
+
+    fn remember(&mut self, key: String, command: Command, result: Result<Response, ApplicationError>) {
+        if self.keys.len() >= IDEMPOTENCY_KEYS
+            && let Some(oldest) = self.keys.pop_front()
+        {
+            self.results.remove(&oldest);
+        }
+        let key = RecentResults::scoped(&key, &command);
+        self.keys.push_back(key.clone());
+        self.results.insert(key, (command, result));
+    }
+}
+
+/// Owns the [`TradingPlatform`] and applies one [`Command`] at a time, so there is no lock to contend for.
+pub struct Sequencer {
+    platform: TradingPlatform,
+    commands: mpsc::Receiver<Envelope>,
+    /// Number of state-changing commands applied so far
+    sequence: u64,
+    /// Where snapshots for readers are published
+    replica: Replica,
+    /// Sequence number of the last published snapshot
+    published: u64,
+    /// The longest a busy sequencer may defer publishing a snapshot
+    max_staleness: Duration,
+    /// Where state is persisted
+    persistence: Persistence,
+    /// The open write-ahead log, once recovered
+    wal: Option<Wal>,
+    /// Sequence number of the last checkpoint written or loaded
+    checkpointed: u64,
+    /// Set once applying a command panicked, the platform may be half-updated since
+    poisoned: bool,
+    /// Set once recovery is done and commands are being applied
+    ready: Arc<AtomicBool>,
+    /// Outcomes to answer retries with
+    recent: RecentResults,
+    /// Where executions are published
+    trades: broadcast::Sender<Trade>,
+    /// The id of the last trade published
+    last_trade: u64,
+    /// Where changes to the resting orders are published
+    book: broadcast::Sender<BookUpdate>,
+    /// Sequence number of the last book update
+    last_book_update: u64,
+}
+
+impl Sequencer {
+    /// Creates a sequencer around the `platform` and a handle to send it commands.
+    /// At most `capacity` commands are queued, after that senders wait (backpressure).
+    /// Snapshots are published whenever the queue runs empty, and at least every `max_staleness` while busy.
+    pub fn new(
+        platform: TradingPlatform,
+        capacity: usize,
+        max_staleness: Duration,
+    ) -> (Self, SequencerHandle) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let replica = Replica::new(Snapshot::capture(&platform, 0));
+        let ready = Arc::new(AtomicBool::new(false));
+        let (trades, _) = broadcast::channel(TRADE_BUFFER);
+        let (book, _) = broadcast::channel(BOOK_UPDATE_BUFFER);
+        (
+            Sequencer {
+                platform,
+                commands: rx,
+                sequence: 0,
+                replica: replica.clone(),
+                published: 0,
+                max_staleness,
+                persistence: Persistence::default(),
+                wal: None,
+                checkpointed: 0,
+                poisoned: false,
+                ready: ready.clone(),
+                recent: RecentResults::default(),
+                trades: trades.clone(),
+                last_trade: 0,
+                book: book.clone(),
+                last_book_update: 0,
+            },
+            SequencerHandle {
+                commands: tx,
+                replica,
+                ready,
+                trades,
+                book,
+            },
+        )
+    }
+
+    /// Restores state from `persistence` when started and logs every state-changing command before applying it
+    pub fn with_persistence(mut self, persistence: Persistence) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    /// Starts the sequencer on its own task and returns the handle
+    pub fn spawn(platform: TradingPlatform, capacity: usize, max_staleness: Duration) -> SequencerHandle {
+        let (sequencer, handle) = Sequencer::new(platform, capacity, max_staleness);
+        tokio::spawn(sequencer.run());
+        handle
+    }
+
+    /// Recovers persisted state, then processes commands until every [`SequencerHandle`] is dropped.
+    /// Commands still queued at that point are applied before the WAL is synced, a final checkpoint written and
+    /// the platform returned.
+    /// A panic while applying a command stops the sequencer: callers get [`ApplicationError::Unavailable`]
+    /// instead of operating on a half-updated platform. The command is marked as poisoned in the WAL, so the
+    /// next start skips it, and no final checkpoint is written. The same goes for a failed recovery or WAL
+    /// write, the commands queued behind a failed write are answered without being applied because they can't
+    /// be logged.
+    pub async fn run(mut self) -> TradingPlatform {
+        if let Err(e) = self.recover() {
+            tracing::error!(error = %e, "Recovery failed, not accepting commands");
+            return self.platform;
+        }
+        self.ready.store(true, Ordering::Release);
+
+        // Only ends once every handle is gone and the queue is drained, so everything queued gets applied
+        while let Some(envelope) = self.commands.recv().await {
+            if let Err(e) = self.process(envelope) {
+                tracing::error!(error = %e, "Stopping the sequencer");
+                self.ready.store(false, Ordering::Release);
+                self.commands.close();
+                while let Ok(Envelope { reply, .. }) = self.commands.try_recv() {
+                    let _ = reply.send(Err(ApplicationError::Unavailable("sequencer stopped".to_string())));
+                }
+                break;
+            }
+        }
+        self.ready.store(false, Ordering::Release);
+        if self.poisoned {
+            tracing::warn!("Not writing a checkpoint of a half-updated platform, the next start replays the WAL");
+        } else if let Err(e) = self.checkpoint() {
+            tracing::error!(error = %e, "Couldn't write the final checkpoint");
+        }
+        tracing::info!(commands = self.sequence, "sequencer stopped");
+        self.platform
+    }
+
+    /// Applies one command and replies to the caller. Fails if the command couldn't be logged or applying it panicked.
+    fn process(&mut self, envelope: Envelope) -> io::Result<()> {
+        let Envelope {
+            command,
+            idempotency_key,
+            submitted_at,
+            span: caller,
+            reply,
+        } = envelope;
+        let name = command.name();
+        let is_read = command.is_read();
+        // Continue the caller's trace, so one request can be followed into matching and settlement
+        let span = tracing::info_span!(parent: &caller, "sequencer", command = name, sequence = field::Empty);
+        let _entered = span.enter();
+        let started_at = Instant::now();
+        METRICS
+            .queue_wait
+            .with_label_values(&[name])
+            .observe(started_at.duration_since(submitted_at).as_secs_f64());
+
+        let idempotency_key = idempotency_key.filter(|_| !is_read);
+        if let Some(key) = &idempotency_key
+            && let Some(result) = self.recent.replay(key, &command)
+        {
+            // A retry: answer like the first time without applying it again
+            tracing::info!(idempotency_key = %key, "Replaying result");
+            let _ = reply.send(result);
+            return Ok(());
+        }
+        // Stamped once and logged, so a replay trades at the same time
+        let now = SystemClock.now_millis();
+        if !is_read && let Err(e) = self.log(&command, idempotency_key.as_deref(), now) {
+            let _ = reply.send(Err(ApplicationError::Unavailable("write-ahead log failed".to_string())));
+            return Err(e);
+        }
+        let remembered = idempotency_key.map(|key| (key, command.clone()));
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| command.apply(&mut self.platform, now))) {
+            Ok(result) => result,
+            Err(_) => {
+                self.poisoned = true;
+                let _ = reply.send(Err(ApplicationError::Unavailable("sequencer stopped".to_string())));
+                if !is_read && let Some(wal) = &mut self.wal {
+                    wal.poison(self.sequence + 1)?;
+                }
+                return Err(io::Error::other(format!("applying {} panicked", name)));
+            }
+        };
+
+        METRICS
+            .command_latency
+            .with_label_values(&[name])
+            .observe(started_at.elapsed().as_secs_f64());
+        METRICS.queue_depth.set(self.commands.len() as i64);
+        match &result {
+            Ok(Response::Receipt(receipt)) => {
+                METRICS.observe_order(Ok(receipt));
+                self.publish_trades();
+            }
+            Err(e) if name == "order" => METRICS.observe_order(Err(e)),
+            // Ending an auction executes it
+            Ok(Response::Session(_)) => METRICS.observe_trades(&self.publish_trades()),
+            _ => {}
+        }
+        if !is_read {
+            self.sequence += 1;
+            span.record("sequence", self.sequence);
+            self.publish_book_update();
+            let (bids, asks) = self.platform.book_depth();
+            METRICS.observe_book(bids, asks);
+            let every = self.persistence.checkpoint_every;
+            if every > 0 && self.sequence - self.checkpointed >= every {
+                // Tried again after as many commands if it fails, the WAL keeps everything until then
+                self.checkpointed = self.sequence;
+                if let Err(e) = self.checkpoint() {
+                    tracing::error!(error = %e, "Couldn't write a checkpoint");
+                }
+            }
+        }
+        // After reads too, otherwise a stream of them holds back the snapshot of the write before
+        self.maybe_publish();
+        if let Some((key, command)) = remembered {
+            self.recent.remember(key, command, result.clone());
+        }
+        // The caller may have gone away, that doesn't undo the command
+        let _ = reply.send(result);
+        Ok(())
+    }
+
+    /// Sends the trades since the last call to trade subscribers, if there are any, and returns them
+    fn publish_trades(&mut self) -> Vec<Trade> {
+        let trades = self.platform.trades_since(self.last_trade, usize::MAX);
+        for trade in &trades {
+            self.last_trade = trade.id;
+            let _ = self.trades.send(trade.clone());
+        }
+        trades
+    }
+
+    /// Sends what the last command changed about the resting orders to book subscribers, if anything
+    fn publish_book_update(&mut self) {
+        let events = self.platform.take_book_events();
+        if events.is_empty() {
+            return;
+        }
+        let _ = self.book.send(BookUpdate {
+            sequence: self.sequence,
+            previous: self.last_book_update,
+            events,
+        });
+        self.last_book_update = self.sequence;
+    }
+
+    /// Loads the last checkpoint and replays the WAL entries after it. An entry that panics is marked as
+    /// poisoned and the replay starts over from the configured platform without it.
+    fn recover(&mut self) -> io::Result<()> {
+        let configured = Checkpoint::capture(&self.platform, 0);
+        let fee_schedule = self.platform.fee_schedule().clone();
+        while let Some(poisoned) = self.replay()? {
+            tracing::error!(sequence = poisoned, "Replaying a WAL entry panicked, skipping it from now on");
+            if let Some(path) = &self.persistence.wal_path {
+                Wal::open(path)?.poison(poisoned)?;
+            }
+            self.platform = configured.clone().restore().with_fee_schedule(fee_schedule.clone());
+            self.sequence = 0;
+            self.checkpointed = 0;
+            self.recent = RecentResults::default();
+        }
+        if let Some(path) = &self.persistence.wal_path {
+            // Nobody could subscribe yet, the first snapshot includes all of it
+            self.platform.take_book_events();
+            self.wal = Some(Wal::open(path)?);
+        }
+        self.platform.take_touched_levels();
+        self.replica.publish(Snapshot::capture(&self.platform, self.sequence));
+        self.published = self.sequence;
+        self.last_book_update = self.sequence;
+        self.last_trade = self.platform.last_trade_id();
+        let (bids, asks) = self.platform.book_depth();
+        METRICS.observe_book(bids, asks);
+        Ok(())
+    }
+
+    /// Restores the checkpoint and applies the WAL entries after it. Returns the sequence number of an entry
+    /// that panicked, the platform is half-updated then.
+    fn replay(&mut self) -> io::Result<Option<u64>> {
+        if let Some(path) = &self.persistence.snapshot_path
+            && let Some(checkpoint) = Checkpoint::read(path)?
+        {
+            tracing::info!(sequence = checkpoint.sequence, path = %path.display(), "Loaded checkpoint");
+            self.sequence = checkpoint.sequence;
+            self.checkpointed = checkpoint.sequence;
+            // The fee schedule comes from the configuration, not the checkpoint
+            let fee_schedule = self.platform.fee_schedule().clone();
+            self.platform = checkpoint.restore().with_fee_schedule(fee_schedule);
+        }
+        if let Some(path) = &self.persistence.wal_path {
+            let mut replayed = 0;
+            let checkpointed = self.sequence;
+            for entry in Wal::read(path)?.into_iter().filter(|e| e.sequence > checkpointed) {
+                // Outcomes, including rejections, were reported when the command was first applied.
+                // They're only kept to answer retries.
+                let remembered = entry.idempotency_key.map(|key| (key, entry.command.clone()));
+                // Entries from before they were stamped replay at the time of the replay
+                let now = entry.timestamp.unwrap_or_else(|| SystemClock.now_millis());
+                let command = entry.command;
+                let platform = &mut self.platform;
+                let Ok(result) = panic::catch_unwind(AssertUnwindSafe(|| command.apply(platform, now))) else {
+                    return Ok(Some(entry.sequence));
+                };
+                if let Some((key, command)) = remembered {
+                    self.recent.remember(key, command, result);
+                }
+                self.sequence = entry.sequence;
+                replayed += 1;
+            }
+            tracing::info!(replayed, sequence = self.sequence, path = %path.display(), "Replayed WAL");
+        }
+        Ok(None)
+    }
+
+    /// Appends a state-changing command to the WAL before it's applied
+    fn log(&mut self, command: &Command, idempotency_key: Option<&str>, now: u64) -> io::Result<()> {
+        if let Some(wal) = &mut self.wal {
+            let started_at = Instant::now();
+            wal.append(self.sequence + 1, command, idempotency_key, now)?;
+            METRICS.wal_append.observe(started_at.elapsed().as_secs_f64());
+        }
+        Ok(())
+    }
+
+    /// Syncs the WAL and writes a checkpoint, after which the WAL isn't needed anymore
+    fn checkpoint(&mut self) -> io::Result<()> {
+        if let Some(wal) = &mut self.wal {
+            wal.sync()?;
+        }
+        if let Some(path) = &self.persistence.snapshot_path {
+            Checkpoint::capture(&self.platform, self.sequence).write(path)?;
+            tracing::info!(sequence = self.sequence, path = %path.display(), "Wrote checkpoint");
+            if let Some(wal) = &mut self.wal {
+                wal.truncate()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Publishes a snapshot if there are unpublished writes and the queue is drained or the current one got too old.
+    /// Runs after every command. Publishing before replying means callers always read their own writes once the queue is idle.
+    fn maybe_publish(&mut self) {
+        let age = self.replica.load().taken_at.elapsed();
+        if self.published < self.sequence && (self.commands.is_empty() || age >= self.max_staleness) {
+            let touched = self.platform.take_touched_levels();
+            let snapshot = self.replica.load().update(&self.platform, &touched, self.sequence);
+            self.replica.publish(snapshot);
+            self.published = self.sequence;
+        }
+    }
+}
+
+/// A cheap, cloneable handle to submit commands to the [`Sequencer`]
+#[derive(Clone, Debug)]
+pub struct SequencerHandle {
+    commands: mpsc::Sender<Envelope>,
+    replica: Replica,
+    ready: Arc<AtomicBool>,
+    trades: broadcast::Sender<Trade>,
+    book: broadcast::Sender<BookUpdate>,
+}
+
+impl SequencerHandle {
+    /// Whether the sequencer recovered its state and is applying commands
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Whether the sequencer is still running, it stops after a failed recovery or WAL write
+    pub fn is_running(&self) -> bool {
+        !self.commands.is_closed()
+    }
+
+    /// Reports the sequencer as not ready, e.g. while the server drains for shutdown
+    pub fn mark_unready(&self) {
+        self.ready.store(false, Ordering::Release);
+    }
+
+    /// Returns the latest published snapshot without waiting for the sequencer
+    pub fn snapshot(&self) -> Arc<Snapshot> {
+        self.replica.load()
+    }
+
+    /// Receives every trade from now on. Subscribers that fall more than a few thousand trades behind miss some.
+    pub fn subscribe_trades(&self) -> broadcast::Receiver<Trade> {
+        self.trades.subscribe()
+    }
+
+    /// Receives every change to the resting orders from now on, one [`BookUpdate`] per command.
+    /// Subscribers that fall more than a few thousand updates behind miss some.
+    pub fn subscribe_book(&self) -> broadcast::Receiver<BookUpdate> {
+        self.book.subscribe()
+    }
+
+    /// Sends a command and waits for the sequencer to apply it
+    pub async fn execute(&self, command: Command) -> Result<Response, ApplicationError> {
+        self.submit(command, None).await
+    }
+
+    /// Sends a command and waits for the sequencer to apply it. A state-changing command with an
+    /// `idempotency_key` that was seen recently isn't applied again: the first outcome is returned.
+    pub async fn submit(
+        &self,
+        command: Command,
+        idempotency_key: Option<String>,
+    ) -> Result<Response, ApplicationError> {
+        let (tx, rx) = oneshot::channel();
+        self.commands
+            .send(Envelope {
+                command,
+                idempotency_key,
+                submitted_at: Instant::now(),
+                span: Span::current(),
+                reply: tx,
+            })
+            .await
+            .map_err(|_| ApplicationError::Unavailable("sequencer stopped".to_string()))?;
+        rx.await
+            .map_err(|_| ApplicationError::Unavailable("sequencer dropped the command".to_string()))?
+    }
+
+    /// Deposit funds
+    pub async fn deposit(&self, account: &str, amount: Amount) -> Result<Tx, ApplicationError> {
+        match self
+            .execute(Command::Deposit {
+                account: account.to_string(),
+                amount,
+            })
+            .await?
+        {
+            Response::Tx(tx) => Ok(tx),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Withdraw funds
+    pub async fn withdraw(&self, account: &str, amount: Amount) -> Result<Tx, ApplicationError> {
+        match self
+            .execute(Command::Withdraw {
+                account: account.to_string(),
+                amount,
+            })
+            .await?
+        {
+            Response::Tx(tx) => Ok(tx),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Transfer funds between sender and recipient
+    pub async fn send(
+        &self,
+        sender: &str,
+        recipient: &str,
+        amount: Amount,
+    ) -> Result<(Tx, Tx), ApplicationError> {
+        match self
+            .execute(Command::Send {
+                sender: sender.to_string(),
+                recipient: recipient.to_string(),
+                amount,
+            })
+            .await?
+        {
+            Response::Transfer(t1, t2) => Ok((t1, t2)),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Process an order
+    pub async fn order(&self, order: Order) -> Result<Receipt, ApplicationError> {
+        match self.execute(Command::Order(order)).await? {
+            Response::Receipt(receipt) => Ok(receipt),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Cancel a resting order
+    pub async fn cancel(&self, signer: &str, ordinal: u64) -> Result<PartialOrder, ApplicationError> {
+        match self
+            .execute(Command::Cancel {
+                signer: signer.to_string(),
+                ordinal,
+            })
+            .await?
+        {
+            Response::Cancelled(order) => Ok(order),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches the complete order book
+    pub async fn orderbook(&self) -> Result<Vec<PartialOrder>, ApplicationError> {
+        match self.execute(Command::Orderbook).await? {
+            Response::Orderbook(orderbook) => Ok(orderbook),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches up to `limit` trades after the one with the id `since`, oldest first
+    pub async fn trades(&self, since: u64, limit: usize) -> Result<Vec<Trade>, ApplicationError> {
+        match self.execute(Command::Trades { since, limit }).await? {
+            Response::Trades(trades) => Ok(trades),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches the candles of `interval` overlapping `from..to`, oldest first
+    pub async fn candles(&self, interval: Interval, from: u64, to: u64) -> Result<Vec<Candle>, ApplicationError> {
+        match self.execute(Command::Candles { interval, from, to }).await? {
+            Response::Candles(candles) => Ok(candles),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches the statistics of the 24 hours up to `now` (milliseconds since the Unix epoch)
+    pub async fn ticker(&self, now: u64) -> Result<Ticker, ApplicationError> {
+        match self.execute(Command::Ticker { now }).await? {
+            Response::Ticker(ticker) => Ok(ticker),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches the listed instruments
+    pub async fn instruments(&self) -> Result<Vec<Instrument>, ApplicationError> {
+        match self.execute(Command::Instruments).await? {
+            Response::Instruments(instruments) => Ok(instruments),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Lists the `instrument`, or changes the listed one with the same symbol to this definition if it differs
+    pub async fn list_instrument(&self, instrument: Instrument) -> Result<Instrument, ApplicationError> {
+        let command = match self.instruments().await?.into_iter().find(|listed| listed.symbol == instrument.symbol) {
+            Some(listed) if listed == instrument => return Ok(listed),
+            Some(_) => Command::UpdateInstrument {
+                symbol: instrument.symbol.clone(),
+                instrument,
+            },
+            None => Command::CreateInstrument(instrument),
+        };
+        match self.execute(command).await? {
+            Response::Instrument(instrument) => Ok(instrument),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches the trading session
+    pub async fn session(&self) -> Result<TradingSession, ApplicationError> {
+        match self.execute(Command::Session).await? {
+            Response::Session(session) => Ok(session),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetches the balance of an account
+    pub async fn balance_of(&self, account: &str) -> Result<Amount, ApplicationError> {
+        match self
+            .execute(Command::Balance {
+                account: account.to_string(),
+            })
+            .await?
+        {
+            Response::Balance(balance) => Ok(balance),
+            other => Err(unexpected(other)),
+        }
+    }
+}
+
+/// Every command maps to exactly one kind of response, so this is a bug
+pub fn unexpected(response: Response) -> ApplicationError {
+    ApplicationError::Unavailable(format!("unexpected response {:?}", response))
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::{
+        core::{BookEvent, Price, Quantity, Side},
+        snapshot::DEFAULT_MAX_STALENESS,
+    };
+
+    #[tokio::test]
+    async fn test_Sequencer_applies_commands_in_order() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        handle.deposit("BOB", Amount::units(100)).await.unwrap();
+        let alice_receipt = handle
+            .order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(alice_receipt.ordinal, 1);
+        assert_eq!(handle.orderbook().await.unwrap().len(), 1);
+
+        let bob_receipt = handle
+            .order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob_receipt.matches.len(), 1);
+        assert_eq!(handle.balance_of("ALICE").await, Ok(Amount::units(110)));
+        assert_eq!(handle.balance_of("BOB").await, Ok(Amount::units(90)));
+        assert!(handle.orderbook().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_snapshot_reads_own_writes() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        assert_eq!(handle.snapshot().sequence, 0);
+        assert!(handle.snapshot().balances.is_empty());
+
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        handle
+            .order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Sell,
+                signer: "ALICE".to_string(),
+            })
+            .await
+            .unwrap();
+
+        let snapshot = handle.snapshot();
+        assert_eq!(snapshot.sequence, 2);
+        assert_eq!(snapshot.balances.get("ALICE"), Some(&Amount::units(100)));
+        assert_eq!(snapshot.orderbook().len(), 1);
+
+        // Reads don't advance the sequence
+        handle.balance_of("ALICE").await.unwrap();
+        assert_eq!(handle.snapshot().sequence, 2);
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_publishes_within_max_staleness_while_reads_keep_coming() {
+        let max_staleness = Duration::from_millis(10);
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 64, max_staleness);
+        // Queued before the sequencer starts, the deposit first and reads behind it
+        let deposit = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.deposit("ALICE", Amount::units(100)).await }
+        });
+        tokio::task::yield_now().await;
+        let stop = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..8)
+            .map(|_| {
+                let (handle, stop) = (handle.clone(), stop.clone());
+                tokio::spawn(async move {
+                    while !stop.load(Ordering::Relaxed) {
+                        handle.orderbook().await.unwrap();
+                    }
+                })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        tokio::spawn(sequencer.run());
+
+        deposit.await.unwrap().unwrap();
+        tokio::time::sleep(max_staleness * 5).await;
+        let snapshot = handle.snapshot();
+        assert_eq!(snapshot.sequence, 1);
+        assert_eq!(snapshot.balances.get("ALICE"), Some(&Amount::units(100)));
+
+        stop.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_returns_application_errors() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        assert_eq!(
+            handle.withdraw("ALICE", Amount::units(1)).await,
+            Err(ApplicationError::AccountNotFound("ALICE".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_list_instrument_creates_or_updates() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let instrument = Instrument {
+            symbol: "BTC-USD".to_string(),
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            tick_size: "0.5".parse().unwrap(),
+            lot_size: "0.01".parse().unwrap(),
+            precision: Default::default(),
+            min_quantity: Quantity::ZERO,
+            max_quantity: None,
+            min_notional: Amount::ZERO,
+            price_band_bps: None,
+            status: Default::default(),
+        };
+        assert_eq!(handle.list_instrument(instrument.clone()).await, Ok(instrument.clone()));
+        // Listing it again changes nothing
+        assert_eq!(handle.list_instrument(instrument.clone()).await, Ok(instrument.clone()));
+        assert_eq!(handle.snapshot().sequence, 1);
+
+        let changed = Instrument {
+            tick_size: Price::units(1),
+            ..instrument
+        };
+        assert_eq!(handle.list_instrument(changed.clone()).await, Ok(changed.clone()));
+        assert_eq!(handle.instruments().await, Ok(vec![changed]));
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_run_applies_queued_commands_on_shutdown() {
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        // Queued before the sequencer starts, their handles are the last ones once these are dropped
+        let deposits: Vec<_> = ["ALICE", "BOB", "CHARLIE"]
+            .into_iter()
+            .map(|account| {
+                let handle = handle.clone();
+                tokio::spawn(async move { handle.deposit(account, Amount::units(100)).await })
+            })
+            .collect();
+        tokio::task::yield_now().await;
+        drop(handle);
+
+        let mut platform = sequencer.run().await;
+        for deposit in deposits {
+            assert!(deposit.await.unwrap().is_ok());
+        }
+        for account in ["ALICE", "BOB", "CHARLIE"] {
+            assert_eq!(platform.balance_of(account), Ok(&Amount::units(100)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_run_returns_platform_when_handles_are_dropped() {
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let task = tokio::spawn(sequencer.run());
+
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        drop(handle);
+
+        let mut platform = task.await.unwrap();
+        assert_eq!(platform.balance_of("ALICE"), Ok(&Amount::units(100)));
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("fintech-{}-{}", uuid::Uuid::new_v4().simple(), name))
+    }
+
+    async fn run_until_dropped(persistence: Persistence, commands: Vec<Command>) -> TradingPlatform {
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let task = tokio::spawn(sequencer.with_persistence(persistence).run());
+        for command in commands {
+            handle.execute(command).await.unwrap();
+        }
+        assert!(handle.is_ready());
+        drop(handle);
+        task.await.unwrap()
+    }
+
+    fn sell(signer: &str) -> Command {
+        Command::Order(Order {
+            price: Price::units(10),
+            amount: Quantity::units(1),
+            side: Side::Sell,
+            signer: signer.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_replays_wal_on_start() {
+        let persistence = Persistence {
+            wal_path: Some(temp_path("wal.jsonl")),
+            snapshot_path: None,
+            checkpoint_every: 0,
+        };
+        let deposit = Command::Deposit {
+            account: "ALICE".to_string(),
+            amount: Amount::units(100),
+        };
+        run_until_dropped(persistence.clone(), vec![deposit, sell("ALICE"), Command::Orderbook]).await;
+        // Reads aren't logged
+        assert_eq!(Wal::read(persistence.wal_path.as_ref().unwrap()).unwrap().len(), 2);
+
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        assert_eq!(handle.balance_of("ALICE").await, Ok(Amount::units(100)));
+        assert_eq!(handle.snapshot().sequence, 2);
+        assert_eq!(handle.snapshot().orderbook().len(), 1);
+        assert!(handle.is_ready());
+
+        let _ = std::fs::remove_file(persistence.wal_path.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_replays_trades_at_their_original_time() {
+        let persistence = Persistence {
+            wal_path: Some(temp_path("wal.jsonl")),
+            snapshot_path: None,
+            checkpoint_every: 0,
+        };
+        let deposit = |account: &str| Command::Deposit {
+            account: account.to_string(),
+            amount: Amount::units(100),
+        };
+        let buy = Command::Order(Order {
+            price: Price::units(10),
+            amount: Quantity::units(1),
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+        });
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let task = tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        for command in [deposit("ALICE"), deposit("BOB"), sell("ALICE"), buy] {
+            handle.execute(command).await.unwrap();
+        }
+        let trades = handle.trades(0, 10).await.unwrap();
+        assert_eq!(trades.len(), 1);
+        drop(handle);
+        task.await.unwrap();
+
+        // Long enough for the clock to move on
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        assert_eq!(handle.trades(0, 10).await, Ok(trades));
+
+        let _ = std::fs::remove_file(persistence.wal_path.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_writes_checkpoint_on_shutdown() {
+        let persistence = Persistence {
+            wal_path: Some(temp_path("wal.jsonl")),
+            snapshot_path: Some(temp_path("checkpoint.json")),
+            checkpoint_every: 0,
+        };
+        let deposit = Command::Deposit {
+            account: "ALICE".to_string(),
+            amount: Amount::units(100),
+        };
+        run_until_dropped(persistence.clone(), vec![deposit, sell("ALICE")]).await;
+
+        let checkpoint = Checkpoint::read(persistence.snapshot_path.as_ref().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(checkpoint.sequence, 2);
+        assert_eq!(checkpoint.orderbook.len(), 1);
+        assert!(Wal::read(persistence.wal_path.as_ref().unwrap()).unwrap().is_empty());
+
+        // The next run continues from the checkpoint
+        let platform = run_until_dropped(persistence.clone(), vec![sell("ALICE")]).await;
+        assert_eq!(platform.orderbook().len(), 2);
+        assert_eq!(platform.ordinal(), 2);
+        let checkpoint = Checkpoint::read(persistence.snapshot_path.as_ref().unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(checkpoint.sequence, 3);
+
+        let _ = std::fs::remove_file(persistence.wal_path.unwrap());
+        let _ = std::fs::remove_file(persistence.snapshot_path.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_stops_when_recovery_fails() {
+        let wal_path = temp_path("wal.jsonl");
+        std::fs::write(&wal_path, "garbage\n{}\n").unwrap();
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let task = tokio::spawn(
+            sequencer
+                .with_persistence(Persistence {
+                    wal_path: Some(wal_path.clone()),
+                    snapshot_path: None,
+                    checkpoint_every: 0,
+                })
+                .run(),
+        );
+        task.await.unwrap();
+
+        assert!(!handle.is_ready());
+        assert!(!handle.is_running());
+        assert!(matches!(
+            handle.deposit("ALICE", Amount::units(100)).await,
+            Err(ApplicationError::Unavailable(_))
+        ));
+        let _ = std::fs::remove_file(wal_path);
+    }
+
+    /// Writes a checkpoint with a resting bid whose value overflows, as if written before orders were checked.
+    /// Selling into it panics.
+    fn write_overflowing_checkpoint(path: &std::path::Path) {
+        let mut checkpoint = Checkpoint::capture(&TradingPlatform::new(), 0);
+        checkpoint.ordinal = 1;
+        checkpoint.orderbook.push(PartialOrder {
+            price: Price::MAX,
+            amount: Quantity::units(2),
+            remaining: Quantity::units(2),
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+            ordinal: 1,
+        });
+        checkpoint.write(path).unwrap();
+    }
+
+    fn sell_into_overflow() -> Command {
+        Command::Order(Order {
+            price: Price::units(1),
+            amount: Quantity::units(2),
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_skips_a_command_that_panicked_after_a_restart() {
+        let persistence = Persistence {
+            wal_path: Some(temp_path("wal.jsonl")),
+            snapshot_path: Some(temp_path("checkpoint.json")),
+            checkpoint_every: 0,
+        };
+        write_overflowing_checkpoint(persistence.snapshot_path.as_ref().unwrap());
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let task = tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        assert!(matches!(
+            handle.execute(sell_into_overflow()).await,
+            Err(ApplicationError::Unavailable(_))
+        ));
+        task.await.unwrap();
+        assert!(!handle.is_ready());
+
+        // The half-updated platform isn't checkpointed, the WAL has everything but the poisoned command
+        let checkpoint = Checkpoint::read(persistence.snapshot_path.as_ref().unwrap()).unwrap().unwrap();
+        assert_eq!(checkpoint.sequence, 0);
+        assert_eq!(Wal::read(persistence.wal_path.as_ref().unwrap()).unwrap().len(), 1);
+
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        assert_eq!(handle.balance_of("ALICE").await, Ok(Amount::units(100)));
+        assert!(handle.is_ready());
+        assert_eq!(handle.snapshot().sequence, 1);
+
+        let _ = std::fs::remove_file(persistence.wal_path.unwrap());
+        let _ = std::fs::remove_file(persistence.snapshot_path.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_recovers_without_an_entry_that_panics_on_replay() {
+        let persistence = Persistence {
+            wal_path: Some(temp_path("wal.jsonl")),
+            snapshot_path: Some(temp_path("checkpoint.json")),
+            checkpoint_every: 0,
+        };
+        write_overflowing_checkpoint(persistence.snapshot_path.as_ref().unwrap());
+        // As if the process died while applying the second entry, before it could be marked
+        let mut wal = Wal::open(persistence.wal_path.as_ref().unwrap()).unwrap();
+        let deposit = |account: &str| Command::Deposit {
+            account: account.to_string(),
+            amount: Amount::units(100),
+        };
+        wal.append(1, &deposit("ALICE"), None, 0).unwrap();
+        wal.append(2, &sell_into_overflow(), None, 0).unwrap();
+        wal.append(3, &deposit("CHARLIE"), None, 0).unwrap();
+        drop(wal);
+
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        assert_eq!(handle.balance_of("ALICE").await, Ok(Amount::units(100)));
+        assert_eq!(handle.balance_of("CHARLIE").await, Ok(Amount::units(100)));
+        assert!(handle.is_ready());
+        assert_eq!(handle.snapshot().sequence, 3);
+        assert_eq!(handle.snapshot().orderbook().len(), 1);
+        assert_eq!(Wal::read(persistence.wal_path.as_ref().unwrap()).unwrap().len(), 2);
+
+        let _ = std::fs::remove_file(persistence.wal_path.unwrap());
+        let _ = std::fs::remove_file(persistence.snapshot_path.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_Sequencer_checkpoints_periodically() {
+        let persistence = Persistence {
+            wal_path: Some(temp_path("wal.jsonl")),
+            snapshot_path: Some(temp_path("checkpoint.json")),
+            checkpoint_every: 2,
+        };
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        tokio::spawn(sequencer.with_persistence(persistence.clone()).run());
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        assert_eq!(Checkpoint::read(persistence.snapshot_path.as_ref().unwrap()).unwrap(), None);
+        handle.execute(sell("ALICE")).await.unwrap();
+        handle.execute(sell("ALICE")).await.unwrap();
+
+        let checkpoint = Checkpoint::read(persistence.snapshot_path.as_ref().unwrap()).unwrap().unwrap();
+        assert_eq!(checkpoint.sequence, 2);
+        assert_eq!(checkpoint.orderbook.len(), 1);
+        // Only what came after the checkpoint is left in the WAL
+        let entries = Wal::read(persistence.wal_path.as_ref().unwrap()).unwrap();
+        assert_eq!(entries.iter().map(|e| e.sequence).collect::<Vec<_>>(), vec![3]);
+
+        let _ = std::fs::remove_file(persistence.wal_path.unwrap());
+        let _ = std::fs::remove_file(persistence.snapshot_path.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_submit_applies_idempotent_commands_once() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let deposit = Command::Deposit {
+            account: "ALICE".to_string(),
+            amount: Amount::units(100),
+        };
+        let key = Some("retry-me".to_string());
+
+        let first = handle.submit(deposit.clone(), key.clone()).await;
+        let retry = handle.submit(deposit, key.clone()).await;
+        assert_eq!(first, retry);
+        assert_eq!(handle.balance_of("ALICE").await, Ok(Amount::units(100)));
+        assert_eq!(handle.snapshot().sequence, 1);
+
+        assert_eq!(
+            handle.submit(sell("ALICE"), key).await,
+            Err(ApplicationError::IdempotencyKeyReused("retry-me".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_submit_scopes_idempotency_keys_by_account() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let deposit = |account: &str| Command::Deposit {
+            account: account.to_string(),
+            amount: Amount::units(100),
+        };
+        let key = Some("same-key".to_string());
+
+        assert!(handle.submit(deposit("ALICE"), key.clone()).await.is_ok());
+        // Someone else picking the same key neither gets ALICE's result nor an error
+        assert_eq!(
+            handle.submit(deposit("BOB"), key).await,
+            Ok(Response::Tx(Tx::Deposit {
+                account: "BOB".to_string(),
+                amount: Amount::units(100)
+            }))
+        );
+        assert_eq!(handle.balance_of("ALICE").await, Ok(Amount::units(100)));
+        assert_eq!(handle.balance_of("BOB").await, Ok(Amount::units(100)));
+        assert_eq!(handle.snapshot().sequence, 2);
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_cancel_and_trades() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let mut trades = handle.subscribe_trades();
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        handle.deposit("BOB", Amount::units(100)).await.unwrap();
+        handle.execute(sell("ALICE")).await.unwrap();
+        handle.execute(sell("ALICE")).await.unwrap();
+
+        assert_eq!(handle.cancel("ALICE", 2).await.unwrap().ordinal, 2);
+        assert_eq!(
+            handle.cancel("ALICE", 2).await,
+            Err(ApplicationError::OrderNotFound(2))
+        );
+
+        handle
+            .order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            })
+            .await
+            .unwrap();
+        let trade = trades.recv().await.unwrap();
+        assert_eq!(
+            trade,
+            Trade {
+                id: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                maker_ordinal: 1,
+                taker_ordinal: 3,
+                aggressor: Side::Buy,
+                timestamp: trade.timestamp,
+            }
+        );
+        assert!(trade.timestamp > 0);
+        assert_eq!(handle.trades(0, 10).await.unwrap(), vec![trade]);
+        assert!(handle.trades(1, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_subscribe_book_chains_updates() {
+        let handle = Sequencer::spawn(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        let mut book = handle.subscribe_book();
+        handle.deposit("ALICE", Amount::units(100)).await.unwrap();
+        handle.execute(sell("ALICE")).await.unwrap();
+        handle.deposit("BOB", Amount::units(100)).await.unwrap();
+        handle.cancel("ALICE", 1).await.unwrap();
+
+        // Deposits don't touch the book, they leave gaps in the sequence
+        let added = book.recv().await.unwrap();
+        let [BookEvent::Add { order_id, .. }] = added.events[..] else {
+            panic!("expected the order to rest, got {:?}", added.events);
+        };
+        assert_ne!(order_id, 1, "the ordinal isn't published");
+        assert_eq!(
+            added,
+            BookUpdate {
+                sequence: 2,
+                previous: 0,
+                events: vec![BookEvent::Add {
+                    order_id,
+                    side: Side::Sell,
+                    price: Price::units(10),
+                    amount: Quantity::units(1),
+                }],
+            }
+        );
+        assert_eq!(
+            book.recv().await.unwrap(),
+            BookUpdate {
+                sequence: 4,
+                previous: 2,
+                events: vec![BookEvent::Delete { order_id }],
+            }
+        );
+        assert!(handle.snapshot().orders().asks.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_SequencerHandle_execute_fails_after_sequencer_stopped() {
+        let (sequencer, handle) = Sequencer::new(TradingPlatform::new(), 8, DEFAULT_MAX_STALENESS);
+        drop(sequencer);
+        assert!(matches!(
+            handle.deposit("ALICE", Amount::units(100)).await,
+            Err(ApplicationError::Unavailable(_))
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    core::SessionState,
+    errors::ApplicationError,
+    sequencer::{Command, SequencerHandle},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// Milliseconds in a day, schedules repeat daily
+const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+
+/// How often the [`Scheduler`] looks at the clock
+pub const DEFAULT_TICK: Duration = Duration::from_secs(1);
+
+/// Where the [`Scheduler`] gets the time from, so tests can move it by hand
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the Unix epoch
+    fn now_millis(&self) -> u64;
+}
+
+/// The system's wall clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+/// A time of day in UTC, written as `HH:MM`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    minutes: u32,
+}
+
+impl TimeOfDay {
+    /// `hour`:`minute`, `None` if either is out of range
+    pub fn new(hour: u32, minute: u32) -> Option<Self> {
+        (hour < 24 && minute < 60).then_some(TimeOfDay {
+            minutes: hour * 60 + minute,
+        })
+    }
+
+    /// The time of day of `millis` since the Unix epoch
+    pub fn of(millis: u64) -> Self {
+        TimeOfDay {
+            minutes: ((millis % DAY_MILLIS) / 60_000) as u32,
+        }
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value
+            .split_once(':')
+            .filter(|(hour, minute)| hour.len() == 2 && minute.len() == 2)
+            .and_then(|(hour, minute)| TimeOfDay::new(hour.parse().ok()?, minute.parse().ok()?))
+            .ok_or_else(|| format!("'{}' isn't a time of day like 08:30", value))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.minutes / 60, self.minutes % 60)
+    }
+}
+
+/// The book moves to `state` at `start` every day
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Phase {
+    pub start: TimeOfDay,
+    pub state: SessionState,
+}
+
+/// The phases of a trading day. Without phases the book stays in whatever state an admin set,
+/// continuous trading from the start.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Schedule {
+    /// Ordered by `start`. The last phase lasts until the first one of the next day.
+    pub phases: Vec<Phase>,
+}
+
+impl Schedule {
+    /// Checks that the phases are ordered and none is a halt, halts are up to admins
+    pub fn validate(&self) -> Result<(), String> {
+        if self.phases.windows(2).any(|w| w[0].start >= w[1].start) {
+            return Err("phases must be ordered by increasing start".to_string());
+        }
+        if let Some(phase) = self.phases.iter().find(|phase| phase.state == SessionState::Halted) {
+            return Err(format!("phase {}: halts can't be scheduled", phase.start));
+        }
+        Ok(())
+    }
+
+    /// The scheduled state at `millis` since the Unix epoch, `None` without phases
+    pub fn state_at(&self, millis: u64) -> Option<SessionState> {
+        let time = TimeOfDay::of(millis);
+        self.phases
+            .iter()
+            .rev()
+            .find(|phase| phase.start <= time)
+            .or(self.phases.last())
+            .map(|phase| phase.state)
+    }
+}
+
+/// Moves the book through the [`Schedule`] by submitting [`Command::SetSession`] whenever the scheduled
+/// state changes. Going through the sequencer puts the transitions in the WAL, so a replay sees them
+/// at the same point. A state an admin sets in between holds until the next phase starts, and so does the
+/// state a restart recovers: the phase under way when the scheduler starts counts as applied.
+pub struct Scheduler {
+    schedule: Schedule,
+    clock: Arc<dyn Clock>,
+    /// The state of the last phase that started
+    applied: Option<SessionState>,
+}
+
+impl Scheduler {
+    pub fn new(schedule: Schedule, clock: Arc<dyn Clock>) -> Self {
+        let applied = schedule.state_at(clock.now_millis());
+        Scheduler {
+            schedule,
+            clock,
+            applied,
+        }
+    }
+
+    /// Submits the scheduled state if a new phase started since the last tick, returns the state submitted
+    pub async fn tick(&mut self, tp: &SequencerHandle) -> Result<Option<SessionState>, ApplicationError> {
+        let Some(state) = self.schedule.state_at(self.clock.now_millis()) else {
+            return Ok(None);
+        };
+        if self.applied == Some(state) {
+            return Ok(None);
+        }
+        tp.execute(Command::SetSession { state }).await?;
+        tracing::info!(?state, "Scheduled session state");
+        self.applied = Some(state);
+        Ok(Some(state))
+    }
+
+    /// Ticks every `interval` until the sequencer stops. Holds a handle, so abort it before waiting for the
+    /// sequencer to shut down.
+    pub async fn run(mut self, tp: SequencerHandle, interval: Duration) {
+        let mut ticks = tokio::time::interval(interval);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            match self.tick(&tp).await {
+                Ok(_) => {}
+                Err(_) if !tp.is_running() => break,
+                Err(e) => tracing::warn!(error = %e, "Couldn't apply the scheduled session state"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::{sequencer::Sequencer, trading_platform::TradingPlatform};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// A clock that only moves when told to
+    #[derive(Default)]
+    struct ManualClock(AtomicU64);
+
+    impl ManualClock {
+        fn set(&self, hour: u64, minute: u64) {
+            self.0.store(3 * DAY_MILLIS + (hour * 60 + minute) * 60_000, Ordering::SeqCst);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now_millis(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    fn schedule() -> Schedule {
+        let phase = |start: &str, state| Phase {
+            start: TimeOfDay::try_from(start.to_string()).unwrap(),
+            state,
+        };
+        Schedule {
+            phases: vec![
+                phase("08:00", SessionState::PreOpen),
+                phase("09:00", SessionState::Continuous),
+                phase("17:30", SessionState::Closed),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_TimeOfDay_parses_hours_and_minutes() {
+        assert_eq!(TimeOfDay::try_from("08:30".to_string()), Ok(TimeOfDay::new(8, 30).unwrap()));
+        assert_eq!(TimeOfDay::new(23, 59).unwrap().to_string(), "23:59");
+        for invalid in ["24:00", "8:30", "08:60", "0830", "ab:cd"] {
+            assert!(TimeOfDay::try_from(invalid.to_string()).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_Schedule_state_at_wraps_around_midnight() {
+        let schedule = schedule();
+        let at = |hour: u64, minute: u64| schedule.state_at(DAY_MILLIS + (hour * 60 + minute) * 60_000);
+        assert_eq!(at(7, 59), Some(SessionState::Closed));
+        assert_eq!(at(8, 0), Some(SessionState::PreOpen));
+        assert_eq!(at(12, 0), Some(SessionState::Continuous));
+        assert_eq!(at(17, 30), Some(SessionState::Closed));
+        assert_eq!(Schedule::default().state_at(0), None);
+    }
+
+    #[test]
+    fn test_Schedule_validate_rejects_unordered_phases_and_halts() {
+        assert!(schedule().validate().is_ok());
+        let mut unordered = schedule();
+        unordered.phases.swap(0, 1);
+        assert!(unordered.validate().is_err());
+        let mut halted = schedule();
+        halted.phases[1].state = SessionState::Halted;
+        assert!(halted.validate().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_Scheduler_tick_follows_the_clock() {
+        let tp = Sequencer::spawn(TradingPlatform::new(), 16, Duration::ZERO);
+        let clock = Arc::new(ManualClock::default());
+        let mut scheduler = Scheduler::new(schedule(), clock.clone());
+
+        clock.set(8, 15);
+        assert_eq!(scheduler.tick(&tp).await, Ok(Some(SessionState::PreOpen)));
+        assert_eq!(tp.session().await.unwrap().state, SessionState::PreOpen);
+
+        // An admin's change holds until the next phase
+        tp.execute(Command::SetSession {
+            state: SessionState::Continuous,
+        })
+        .await
+        .unwrap();
+        clock.set(8, 45);
+        assert_eq!(scheduler.tick(&tp).await, Ok(None));
+        assert_eq!(tp.session().await.unwrap().state, SessionState::Continuous);
+
+        clock.set(17, 30);
+        assert_eq!(scheduler.tick(&tp).await, Ok(Some(SessionState::Closed)));
+        assert_eq!(tp.session().await.unwrap().state, SessionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_Scheduler_keeps_the_recovered_state_until_the_next_phase() {
+        let tp = Sequencer::spawn(TradingPlatform::new(), 16, Duration::ZERO);
+        // What an admin set before the restart, recovered from the WAL
+        tp.execute(Command::SetSession {
+            state: SessionState::Closed,
+        })
+        .await
+        .unwrap();
+        let clock = Arc::new(ManualClock::default());
+        clock.set(8, 30);
+        let mut scheduler = Scheduler::new(schedule(), clock.clone());
+
+        assert_eq!(scheduler.tick(&tp).await, Ok(None));
+        assert_eq!(tp.session().await.unwrap().state, SessionState::Closed);
+
+        clock.set(9, 0);
+        assert_eq!(scheduler.tick(&tp).await, Ok(Some(SessionState::Continuous)));
+        assert_eq!(tp.session().await.unwrap().state, SessionState::Continuous);
+    }
+}
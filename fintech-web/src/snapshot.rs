@@ -0,0 +1,260 @@
+use crate::{
+    core::{Amount, Auction, BookTop, Depth, L3Book, Level, PartialOrder, Price, Side, Touched},
+    trading_platform::TradingPlatform,
+};
+use arc_swap::ArcSwap;
+use im::{HashMap, OrdMap};
+use std::{
+    collections::BTreeSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Default upper bound for how long the sequencer may hold back a snapshot while it's busy
+pub const DEFAULT_MAX_STALENESS: Duration = Duration::from_millis(50);
+
+/// An immutable view of the book and balances after a given command, for serving reads
+/// without going through the sequencer. It shares the price levels and accounts that didn't change
+/// with the snapshot before it, so publishing one costs what changed rather than the whole book.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    /// Sequence number of the last command included in this snapshot
+    pub sequence: u64,
+    /// The bid levels by price
+    pub bids: OrdMap<Price, Arc<Level>>,
+    /// The ask levels by price
+    pub asks: OrdMap<Price, Arc<Level>>,
+    /// All account balances
+    pub balances: HashMap<String, Amount>,
+    /// The call auction and its indicative price
+    pub auction: Auction,
+    /// When the snapshot was taken
+    pub taken_at: Instant,
+}
+
+impl Snapshot {
+    /// Captures the current state of the `platform`
+    pub fn capture(platform: &TradingPlatform, sequence: u64) -> Self {
+        let empty = Snapshot {
+            sequence,
+            bids: OrdMap::new(),
+            asks: OrdMap::new(),
+            balances: HashMap::new(),
+            auction: Auction::default(),
+            taken_at: Instant::now(),
+        };
+        empty.update(platform, &platform.levels(), sequence)
+    }
+
+    /// The state of the `platform` after `sequence`, given the price levels `touched` since this snapshot.
+    /// Only those levels are read from the platform again.
+    pub fn update(&self, platform: &TradingPlatform, touched: &Touched, sequence: u64) -> Self {
+        let side = |levels: &OrdMap<Price, Arc<Level>>, side: Side, prices: &BTreeSet<Price>| {
+            let mut levels = levels.clone();
+            for price in prices {
+                match platform.level_at(&side, *price) {
+                    Some(level) => levels.insert(*price, Arc::new(level)),
+                    None => levels.remove(price),
+                };
+            }
+            levels
+        };
+        Snapshot {
+            sequence,
+            bids: side(&self.bids, Side::Buy, &touched.bids),
+            asks: side(&self.asks, Side::Sell, &touched.asks),
+            balances: platform.accounts.balances(),
+            auction: platform.auction(),
+            taken_at: Instant::now(),
+        }
+    }
+
+    /// The complete order book, ordered by ordinal
+    pub fn orderbook(&self) -> Vec<PartialOrder> {
+        let mut orderbook: Vec<PartialOrder> = self
+            .asks
+            .values()
+            .chain(self.bids.values())
+            .flat_map(|level| level.orders.iter().cloned())
+            .collect();
+        orderbook.sort_by_key(|o| o.ordinal);
+        orderbook
+    }
+
+    /// Up to `levels` aggregated price levels per side, best prices first
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            bids: self
+                .bids
+                .values()
+                .rev()
+                .take(levels)
+                .map(|level| level.summary.clone())
+                .collect(),
+            asks: self
+                .asks
+                .values()
+                .take(levels)
+                .map(|level| level.summary.clone())
+                .collect(),
+        }
+    }
+
+    /// The best bid and offer
+    pub fn top(&self) -> BookTop {
+        self.depth(1).top()
+    }
+
+    /// Every resting order without its signer, best prices first and in queue order within a price
+    pub fn orders(&self) -> L3Book {
+        L3Book {
+            bids: self
+                .bids
+                .values()
+                .rev()
+                .flat_map(|level| level.queue.iter().cloned())
+                .collect(),
+            asks: self
+                .asks
+                .values()
+                .flat_map(|level| level.queue.iter().cloned())
+                .collect(),
+        }
+    }
+}
+
+/// The latest [`Snapshot`], swapped atomically so readers never wait for the writer.
+#[derive(Clone, Debug)]
+pub struct Replica {
+    current: Arc<ArcSwap<Snapshot>>,
+}
+
+impl Replica {
+    /// Creates a replica starting out with `snapshot`
+    pub fn new(snapshot: Snapshot) -> Self {
+        Replica {
+            current: Arc::new(ArcSwap::from_pointee(snapshot)),
+        }
+    }
+
+    /// Returns the latest published snapshot
+    pub fn load(&self) -> Arc<Snapshot> {
+        self.current.load_full()
+    }
+
+    /// Replaces the published snapshot
+    pub fn publish(&self, snapshot: Snapshot) {
+        self.current.store(Arc::new(snapshot));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use crate::core::{Order, Quantity, SessionState};
+
+    /// When the commands in these tests are applied
+    const NOW: u64 = 1_700_000_000_000;
+
+    fn order(side: Side, price: u64, amount: u64, signer: &str) -> Order {
+        Order {
+            price: Price::units(price),
+            amount: Quantity::units(amount),
+            side,
+            signer: signer.to_string(),
+        }
+    }
+
+    /// Publishes like the sequencer does, only reading the levels touched since `previous` again
+    fn publish(previous: &Snapshot, platform: &mut TradingPlatform) -> Snapshot {
+        let touched = platform.take_touched_levels();
+        previous.update(platform, &touched, previous.sequence + 1)
+    }
+
+    fn assert_captures(snapshot: &Snapshot, platform: &TradingPlatform) {
+        let full = Snapshot::capture(platform, snapshot.sequence);
+        assert_eq!(snapshot.bids, full.bids);
+        assert_eq!(snapshot.asks, full.asks);
+        assert_eq!(snapshot.balances, full.balances);
+        assert_eq!(snapshot.auction, full.auction);
+        assert_eq!(snapshot.orderbook(), platform.orderbook());
+        assert_eq!(snapshot.orders(), platform.orders());
+        assert_eq!(snapshot.depth(usize::MAX), platform.depth(usize::MAX));
+    }
+
+    #[test]
+    fn test_Snapshot_update_matches_a_full_capture() {
+        let mut platform = TradingPlatform::new();
+        let mut snapshot = Snapshot::capture(&platform, 0);
+        for account in ["ALICE", "BOB", "CHARLIE"] {
+            platform.deposit(account, Amount::units(1000)).unwrap();
+        }
+        snapshot = publish(&snapshot, &mut platform);
+        assert_captures(&snapshot, &platform);
+
+        let steps: [fn(&mut TradingPlatform); 8] = [
+            |p| {
+                p.order(order(Side::Sell, 10, 2, "ALICE"), NOW).unwrap();
+            },
+            |p| {
+                p.order(order(Side::Sell, 11, 1, "ALICE"), NOW).unwrap();
+            },
+            |p| {
+                p.order(order(Side::Buy, 9, 1, "BOB"), NOW).unwrap();
+            },
+            // Takes all of 10 and rests the rest at 10
+            |p| {
+                p.order(order(Side::Buy, 10, 3, "CHARLIE"), NOW).unwrap();
+            },
+            |p| {
+                p.cancel("ALICE", 2).unwrap();
+            },
+            |p| {
+                p.set_session_state(SessionState::Auction, NOW);
+            },
+            |p| {
+                p.order(order(Side::Sell, 8, 2, "BOB"), NOW).unwrap();
+            },
+            // Uncrosses the auction
+            |p| {
+                p.set_session_state(SessionState::Continuous, NOW);
+            },
+        ];
+        for step in steps {
+            step(&mut platform);
+            snapshot = publish(&snapshot, &mut platform);
+            assert_captures(&snapshot, &platform);
+        }
+    }
+
+    #[test]
+    fn test_Snapshot_update_shares_untouched_levels() {
+        let mut platform = TradingPlatform::new();
+        platform.deposit("ALICE", Amount::units(1000)).unwrap();
+        platform
+            .order(order(Side::Sell, 10, 1, "ALICE"), NOW)
+            .unwrap();
+        platform.take_touched_levels();
+        let before = Snapshot::capture(&platform, 1);
+
+        platform
+            .order(order(Side::Buy, 9, 1, "ALICE"), NOW)
+            .unwrap();
+        let after = publish(&before, &mut platform);
+        assert!(Arc::ptr_eq(
+            &before.asks[&Price::units(10)],
+            &after.asks[&Price::units(10)]
+        ));
+        assert_eq!(
+            after.top().bid.map(|level| level.price),
+            Some(Price::units(9))
+        );
+        assert_eq!(
+            after.top().ask.map(|level| level.price),
+            Some(Price::units(10))
+        );
+    }
+}
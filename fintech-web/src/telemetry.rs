@@ -0,0 +1,256 @@
+use crate::config::{LogFormat, LoggingConfig};
+use serde_json::{Map, Value, json};
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+use tracing::{
+    Id, Subscriber,
+    field::{Field, Visit},
+    span::{Attributes, Record},
+};
+use tracing_subscriber::{
+    EnvFilter, Layer,
+    layer::{Context, SubscriberExt},
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+/// The header carrying the request's correlation id
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest request id accepted from callers
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Creates a new correlation id for requests that didn't bring one
+pub fn new_request_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Whether a caller's request id can be used as is. Anything else is replaced, so ids can't forge log lines.
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Installs the global subscriber: log lines go to stdout in the configured format and,
+/// if configured, finished spans are exported to a file. Events of the `log` crate are forwarded as well.
+pub fn init(config: &LoggingConfig) -> Result<(), String> {
+    let filter = EnvFilter::try_new(&config.level).map_err(|e| format!("Invalid log filter: {}", e))?;
+    let exporter = match &config.span_export_path {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Couldn't open '{}': {}", path.display(), e))?;
+            Some(SpanExporter::new(file))
+        }
+        None => None,
+    };
+    let output = match config.format {
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(output)
+        .with(exporter)
+        .try_init()
+        .map_err(|e| format!("Couldn't install the log subscriber: {}", e))
+}
+
+/// Collects span fields as JSON values
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{:?}", value)));
+    }
+}
+
+/// What the exporter keeps about an open span
+struct SpanData {
+    start: SystemTime,
+    started_at: Instant,
+    attributes: Map<String, Value>,
+}
+
+/// A [`Layer`] that writes every closed span as an OTLP-shaped JSON line (trace and span ids,
+/// parent, name, start/end time and attributes). It stands in for an OTLP collector: point it at a
+/// file and follow one request from HTTP through the sequencer, matching and settlement.
+/// The trace id is the `request_id` of the root span.
+pub struct SpanExporter<W: Write + Send + 'static> {
+    sink: Mutex<W>,
+}
+
+impl<W: Write + Send + 'static> SpanExporter<W> {
+    /// Exports spans to `sink`, one JSON object per line
+    pub fn new(sink: W) -> Self {
+        SpanExporter {
+            sink: Mutex::new(sink),
+        }
+    }
+}
+
+fn nanos(time: SystemTime) -> u128 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default()
+}
+
+impl<S, W> Layer<S> for SpanExporter<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: Write + Send + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut attributes = Map::new();
+        attrs.record(&mut JsonVisitor(&mut attributes));
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanData {
+                start: SystemTime::now(),
+                started_at: Instant::now(),
+                attributes,
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id)
+            && let Some(data) = span.extensions_mut().get_mut::<SpanData>()
+        {
+            values.record(&mut JsonVisitor(&mut data.attributes));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let trace_id = span
+            .scope()
+            .from_root()
+            .next()
+            .and_then(|root| {
+                let extensions = root.extensions();
+                extensions
+                    .get::<SpanData>()
+                    .and_then(|data| data.attributes.get("request_id").cloned())
+                    .or_else(|| Some(json!(format!("{:016x}", root.id().into_u64()))))
+            })
+            .unwrap_or(Value::Null);
+        let extensions = span.extensions();
+        let Some(data) = extensions.get::<SpanData>() else {
+            return;
+        };
+        let end = data.start + data.started_at.elapsed();
+        let record = json!({
+            "traceId": trace_id,
+            "spanId": format!("{:016x}", id.into_u64()),
+            "parentSpanId": span.parent().map(|p| format!("{:016x}", p.id().into_u64())),
+            "name": span.name(),
+            "target": span.metadata().target(),
+            "startTimeUnixNano": nanos(data.start).to_string(),
+            "endTimeUnixNano": nanos(end).to_string(),
+            "attributes": data.attributes,
+        });
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(sink, "{}", record).and_then(|_| sink.flush()) {
+            eprintln!("Couldn't export span: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // reduce the warnings for naming tests
+    #![allow(non_snake_case)]
+
+    use super::*;
+    use std::sync::Arc;
+
+    /// A sink the test can read back
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_is_valid_request_id_works() {
+        assert!(is_valid_request_id("4bf92f3577b34da6"));
+        assert!(is_valid_request_id("client-1:req.42"));
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("a\nlevel=error"));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[test]
+    fn test_SpanExporter_writes_closed_spans_with_trace_id() {
+        let buffer = Buffer::default();
+        let subscriber =
+            tracing_subscriber::registry().with(SpanExporter::new(buffer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request", request_id = "abc", route = "order");
+            let _request = request.enter();
+            let order = tracing::info_span!("order", ordinal = tracing::field::Empty);
+            order.in_scope(|| {
+                order.record("ordinal", 7);
+            });
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let spans: Vec<Value> = output
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(spans.len(), 2);
+
+        // Children close first
+        assert_eq!(spans[0]["name"], "order");
+        assert_eq!(spans[0]["traceId"], "abc");
+        assert_eq!(spans[0]["attributes"]["ordinal"], 7);
+        assert_eq!(spans[0]["parentSpanId"], spans[1]["spanId"]);
+
+        assert_eq!(spans[1]["name"], "request");
+        assert_eq!(spans[1]["traceId"], "abc");
+        assert_eq!(spans[1]["parentSpanId"], Value::Null);
+        assert_eq!(spans[1]["attributes"]["route"], "order");
+    }
+}
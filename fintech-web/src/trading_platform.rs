@@ -1,28 +1,287 @@
 
 use crate::{
     accounting::Accounts,
-    core::{MatchingEngine, Order, PartialOrder, Receipt, Side},
+    core::{Amount, Auction, BookEvent, Candle, Candles, Depth, FillFee, Instrument, Interval, L3Book, Level, MarketStats, MatchingEngine, Order, PartialOrder, Precision, Price, Receipt, SessionState, Side, Ticker, Touched, Trade, TradeTape, TradingSession},
     errors::{ApplicationError},
+    fees::{self, FEE_ACCOUNT, FeeSchedule, Volumes},
     tx::Tx,
 };
+use std::collections::HashMap;
 
 /// The core of the core: the [`TradingPlatform`]. Manages accounts, validates-, and orchestrates the processing of each order.
 pub struct TradingPlatform {
     pub accounts : Accounts, 
     matching_engine : MatchingEngine,
+    /// Rolling 24 hour statistics of the trades
+    stats: MarketStats,
+    /// Maker and taker rates by volume tier
+    fee_schedule: FeeSchedule,
+    /// Recent trading volume per account, for the fee tiers
+    volumes: Volumes,
+    /// The instrument traded on the book, orders are checked against it once it's listed
+    instrument: Option<Instrument>,
+    /// Which orders and cancels are accepted right now
+    session: TradingSession,
     tx_log : Vec<Tx>
 }
 
+/// What settling one match moves between the accounts, see [`TradingPlatform::settlements`]
+#[derive(Debug)]
+struct Settlement {
+    maker: String,
+    taker: String,
+    /// Price times amount
+    value: Amount,
+    /// `(from, to, amount)` in the order they're paid: the value, the taker fee and the maker fee or rebate
+    legs: Vec<(String, String, Amount)>,
+    fee: FillFee,
+}
+
+/// A match that can't be settled because `account` can't pay or receive its part
+#[derive(Debug)]
+struct Unsettled {
+    /// Of the match among the ones checked together
+    index: usize,
+    account: String,
+    error: ApplicationError,
+}
+
+/// The fee account couldn't take a fee or pay a rebate. That's the platform's fault, not the counterparty's.
+fn fee_account_failed(error: ApplicationError) -> ApplicationError {
+    ApplicationError::Unavailable(format!("fees can't be settled: {}", error))
+}
+
 impl TradingPlatform {
     /// Creates a new instance without any data.
     pub fn new() -> Self {
         TradingPlatform {
             accounts: Accounts::new(),
             matching_engine: MatchingEngine::new(),
+            stats: MarketStats::default(),
+            fee_schedule: FeeSchedule::default(),
+            volumes: Volumes::default(),
+            instrument: None,
+            session: TradingSession::default(),
             tx_log: Vec::new(),
         }
     }
 
+    /// Charges fees on every match according to the `schedule`
+    pub fn with_fee_schedule(mut self, schedule: FeeSchedule) -> Self {
+        self.fee_schedule = schedule;
+        self
+    }
+
+    /// Decimals allowed in order prices and amounts, the listed instrument's or the default ones
+    pub fn precision(&self) -> Precision {
+        self.instrument.as_ref().map_or_else(Precision::default, |instrument| instrument.precision)
+    }
+
+    /// The listed instrument, if any
+    pub fn instrument(&self) -> Option<&Instrument> {
+        self.instrument.as_ref()
+    }
+
+    /// Lists the instrument traded on the book. There's one book, so there can only be one instrument.
+    pub fn create_instrument(&mut self, instrument: Instrument) -> Result<Instrument, ApplicationError> {
+        instrument.validate().map_err(ApplicationError::InvalidInstrument)?;
+        if let Some(listed) = &self.instrument {
+            return Err(ApplicationError::InstrumentExists(listed.symbol.clone()));
+        }
+        self.instrument = Some(instrument.clone());
+        Ok(instrument)
+    }
+
+    /// Replaces the definition of the instrument listed as `symbol`, the symbol itself can't change.
+    /// Resting orders stay as they are, only new orders are checked against the new definition.
+    pub fn update_instrument(&mut self, symbol: &str, instrument: Instrument) -> Result<Instrument, ApplicationError> {
+        let precision = self.listed(symbol)?.precision;
+        if instrument.symbol != symbol {
+            return Err(ApplicationError::InvalidInstrument(format!(
+                "the symbol can't change from '{}' to '{}'",
+                symbol, instrument.symbol
+            )));
+        }
+        instrument.validate().map_err(ApplicationError::InvalidInstrument)?;
+        // Resting orders were checked against the old precision, their trade values could round
+        let resting = !self.matching_engine.bids.is_empty() || !self.matching_engine.asks.is_empty();
+        if instrument.precision != precision && resting {
+            return Err(ApplicationError::InvalidInstrument(
+                "the precision can't change while orders are resting".to_string(),
+            ));
+        }
+        self.instrument = Some(instrument.clone());
+        Ok(instrument)
+    }
+
+    /// The trading session
+    pub fn session(&self) -> &TradingSession {
+        &self.session
+    }
+
+    /// Moves the book to `state`, e.g. when the schedule says so. Entering [`SessionState::Auction`] starts
+    /// a call auction, leaving it for anything but a halt ends it at `now` (milliseconds since the Unix epoch).
+    pub fn set_session_state(&mut self, state: SessionState, now: u64) -> TradingSession {
+        self.session.set_state(state);
+        self.follow_session(now);
+        self.session.clone()
+    }
+
+    /// Halts the listed instrument with this `symbol`, or the whole venue without one
+    pub fn halt(&mut self, symbol: Option<&str>) -> Result<TradingSession, ApplicationError> {
+        match symbol {
+            Some(symbol) => {
+                self.listed(symbol)?;
+                self.session.halt();
+            }
+            None => self.session.venue_halted = true,
+        }
+        Ok(self.session.clone())
+    }
+
+    /// Lifts the halt of the listed instrument with this `symbol`, or of the whole venue without one.
+    /// Lifting the venue's halt leaves the instrument's in place. An auction the book resumes out of ends at `now`.
+    pub fn resume(&mut self, symbol: Option<&str>, now: u64) -> Result<TradingSession, ApplicationError> {
+        match symbol {
+            Some(symbol) => {
+                self.listed(symbol)?;
+                self.session.resume();
+                self.follow_session(now);
+            }
+            None => self.session.venue_halted = false,
+        }
+        Ok(self.session.clone())
+    }
+
+    /// The call auction, with what it would execute if it ended now
+    pub fn auction(&self) -> Auction {
+        let active = self.matching_engine.in_auction();
+        Auction {
+            active,
+            indicative: active
+                .then(|| self.matching_engine.indicative(self.stats.last_price()))
+                .flatten(),
+        }
+    }
+
+    /// Starts the call auction when the book enters [`SessionState::Auction`] and uncrosses it once the book
+    /// moves on. A halt only pauses the auction.
+    fn follow_session(&mut self, now: u64) {
+        match (self.matching_engine.in_auction(), self.session.state) {
+            (false, SessionState::Auction) => self.matching_engine.start_auction(),
+            (true, SessionState::Auction | SessionState::Halted) | (false, _) => {}
+            (true, _) => self.uncross(now),
+        }
+    }
+
+    /// Executes the auction at a single price and settles every fill like a match, the later order of each pays
+    /// the taker fee. Every fill is paid for before anything trades, an order whose signer can't pay for its fill
+    /// leaves the book and the auction is worked out again without it. If the fee account is the one that can't
+    /// settle, nothing executes and the auction keeps collecting orders until the next session change.
+    fn uncross(&mut self, now: u64) {
+        let reference = self.stats.last_price();
+        let settlements = loop {
+            let fills = self.matching_engine.preview_uncross(reference);
+            let checked = self.settlements(
+                fills.iter().map(|fill| (&fill.maker, fill.taker.signer.as_str(), &fill.taker.side)),
+                now,
+            );
+            match checked {
+                Ok(settlements) => break settlements,
+                Err(unsettled) if unsettled.account == FEE_ACCOUNT => {
+                    tracing::error!(error = %fee_account_failed(unsettled.error), "Couldn't uncross the auction");
+                    return;
+                }
+                Err(unsettled) => {
+                    let fill = &fills[unsettled.index];
+                    let order = if fill.taker.signer == unsettled.account { &fill.taker } else { &fill.maker };
+                    self.exclude(order, unsettled.error);
+                }
+            }
+        };
+        let last_trade = self.last_trade_id();
+        let (uncross, fills) = self.matching_engine.uncross(reference, now);
+        let Some(uncross) = uncross else {
+            tracing::info!("Ended the auction without crossing orders");
+            return;
+        };
+        for trade in self.trades_since(last_trade, usize::MAX) {
+            self.stats.record(&trade);
+        }
+        let _uncross = tracing::info_span!("uncross", price = %uncross.price, volume = %uncross.volume).entered();
+        for settlement in settlements {
+            // Checked above
+            if let Err(e) = self.settle(settlement, now) {
+                tracing::error!(error = %e, "Couldn't settle an auction fill");
+            }
+        }
+        tracing::info!(fills = fills.len(), "Uncrossed the auction");
+    }
+
+    /// The listed instrument if it has this `symbol`
+    fn listed(&self, symbol: &str) -> Result<&Instrument, ApplicationError> {
+        self.instrument
+            .as_ref()
+            .filter(|listed| listed.symbol == symbol)
+            .ok_or_else(|| ApplicationError::InstrumentNotFound(symbol.to_string()))
+    }
+
+    /// The maker and taker rates
+    pub fn fee_schedule(&self) -> &FeeSchedule {
+        &self.fee_schedule
+    }
+
+    /// Recent trading volume per account, for checkpoints
+    pub fn volumes(&self) -> &Volumes {
+        &self.volumes
+    }
+
+    /// Rebuilds a platform from account balances, resting orders, recent trades, candles, statistics,
+    /// trading volumes, the listed instrument, the session and whether an auction is running, continuing the ordinals after `ordinal` and the trade ids after the last trade.
+    /// The fee schedule is configuration, set it with [`TradingPlatform::with_fee_schedule`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn restore(
+        ordinal: u64,
+        balances: HashMap<String, Amount>,
+        orderbook: Vec<PartialOrder>,
+        trades: Vec<Trade>,
+        candles: Candles,
+        stats: MarketStats,
+        volumes: Volumes,
+        instrument: Option<Instrument>,
+        session: TradingSession,
+        auction: bool,
+    ) -> Self {
+        let mut platform = TradingPlatform::new();
+        for (account, balance) in balances {
+            // A fresh account can't overflow
+            let _ = platform.accounts.deposit(&account, balance);
+        }
+        platform.matching_engine.ordinal = ordinal;
+        platform.matching_engine.trades = TradeTape::restore(trades);
+        platform.matching_engine.candles = candles;
+        platform.stats = stats;
+        platform.volumes = volumes;
+        platform.instrument = instrument;
+        platform.session = session;
+        if auction {
+            platform.matching_engine.start_auction();
+        }
+        for order in orderbook {
+            let side = match order.side {
+                Side::Buy => &mut platform.matching_engine.bids,
+                Side::Sell => &mut platform.matching_engine.asks,
+            };
+            side.entry(order.price).or_default().push(order);
+        }
+        platform
+    }
+
+    /// The ordinal of the latest order
+    pub fn ordinal(&self) -> u64 {
+        self.matching_engine.ordinal
+    }
+
     /// Fetches the complete order book at this time
     pub fn orderbook(&self) -> Vec<PartialOrder> {
         let mut orderbook = Vec::new(); 
@@ -34,18 +293,88 @@ impl TradingPlatform {
         orderbook
     }
 
+    /// Up to `levels` aggregated price levels per side, without signers
+    pub fn depth(&self, levels: usize) -> Depth {
+        self.matching_engine.depth(levels)
+    }
+
+    /// Every resting order without its signer
+    pub fn orders(&self) -> L3Book {
+        self.matching_engine.orders()
+    }
+
+    /// Hands out the changes to the resting orders since the last call
+    pub fn take_book_events(&mut self) -> Vec<BookEvent> {
+        self.matching_engine.take_events()
+    }
+
+    /// Hands out the price levels whose orders changed since the last call
+    pub fn take_touched_levels(&mut self) -> Touched {
+        self.matching_engine.take_touched()
+    }
+
+    /// Every price level that has orders resting
+    pub fn levels(&self) -> Touched {
+        self.matching_engine.levels()
+    }
+
+    /// The orders resting at `price` on the `side`, if there are any
+    pub fn level_at(&self, side: &Side, price: Price) -> Option<Level> {
+        self.matching_engine.level_at(side, price)
+    }
+
+    /// Up to `limit` trades after the one with the id `since`, oldest first
+    pub fn trades_since(&self, since: u64, limit: usize) -> Vec<Trade> {
+        self.matching_engine.trades.since(since, limit)
+    }
+
+    /// The candles of `interval` overlapping `from..to`, see [`Candles::range`]
+    pub fn candles(&self, interval: Interval, from: u64, to: u64) -> Vec<Candle> {
+        self.matching_engine.candles.range(interval, from, to)
+    }
+
+    /// Every candle kept, for checkpoints
+    pub fn all_candles(&self) -> &Candles {
+        &self.matching_engine.candles
+    }
+
+    /// The statistics of the 24 hours up to `now` (milliseconds since the Unix epoch)
+    pub fn ticker(&self, now: u64) -> Ticker {
+        self.stats.ticker(now)
+    }
+
+    /// The state of the statistics, for checkpoints
+    pub fn market_stats(&self) -> &MarketStats {
+        &self.stats
+    }
+
+    /// The id of the latest trade, 0 if there were none
+    pub fn last_trade_id(&self) -> u64 {
+        self.matching_engine.trades.last_id()
+    }
+
+    /// Counts the resting orders on the bid and ask side
+    pub fn book_depth(&self) -> (usize, usize) {
+        let count = |side: &std::collections::BTreeMap<Price, std::collections::BinaryHeap<PartialOrder>>| {
+            side.values().map(|orders| orders.len()).sum()
+        };
+        (count(&self.matching_engine.bids), count(&self.matching_engine.asks))
+    }
+
     /// Fetches the balance of a specific account
-    pub fn balance_of(&mut self, signer: &str) -> Result<&u64, ApplicationError> {
+    pub fn balance_of(&mut self, signer: &str) -> Result<&Amount, ApplicationError> {
         self.accounts.balance_of(signer)
     }
 
     /// Deposit funds
-    pub fn deposit(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+    pub fn deposit(&mut self, signer: &str, amount: Amount) -> Result<Tx, ApplicationError> {
+        TradingPlatform::unreserved(signer)?;
         self.accounts.deposit(signer, amount)
     }
 
     /// Withdraw funds
-    pub fn withdraw(&mut self, signer: &str, amount: u64) -> Result<Tx, ApplicationError> {
+    pub fn withdraw(&mut self, signer: &str, amount: Amount) -> Result<Tx, ApplicationError> {
+        TradingPlatform::unreserved(signer)?;
         self.accounts.withdraw(signer, amount)
     }
 
@@ -54,15 +383,40 @@ impl TradingPlatform {
         &mut self,
         sender: &str,
         recipient: &str,
-        amount: u64,
+        amount: Amount,
     ) -> Result<(Tx, Tx), ApplicationError> {
+        TradingPlatform::unreserved(sender)?;
+        TradingPlatform::unreserved(recipient)?;
         self.accounts.send(sender, recipient, amount)
     }
 
+    /// Fails for the fee account, only settlements move funds in and out of it
+    fn unreserved(account: &str) -> Result<(), ApplicationError> {
+        if account == FEE_ACCOUNT {
+            return Err(ApplicationError::ReservedAccount(account.to_string()));
+        }
+        Ok(())
+    }
+
     /// Process a given order and apply the outcome to the accounts involved. Note that there are very few safeguards in place.
-    pub fn order(&mut self, order: Order) -> Result<Receipt, ApplicationError> {
-        let total_amount = order.amount * order.price;
-        // Make sure the account has a deposit
+    /// Each match is settled with a transfer between the signers and the fees of both sides, see [`FeeSchedule`].
+    /// All of them are checked before the order matches, a resting order whose signer can't pay for its match is cancelled.
+    /// The order arrives at `now`, in milliseconds since the Unix epoch, which is when its trades happen.
+    pub fn order(&mut self, order: Order, now: u64) -> Result<Receipt, ApplicationError> {
+        TradingPlatform::unreserved(&order.signer)?;
+        self.session.check_order()?;
+        self.precision()
+            .check(order.price, order.amount)
+            .map_err(ApplicationError::InvalidOrder)?;
+        if let Some(instrument) = &self.instrument {
+            instrument.check(&order, self.stats.last_price())?;
+        }
+        let too_large = || ApplicationError::InvalidOrder(format!("{} @ {} is too large", order.amount, order.price));
+        let value = order.price.checked_mul(order.amount).ok_or_else(too_large)?;
+        let total_amount = value
+            .checked_add(fees::fee(value, self.fee_schedule.max_taker_bps()))
+            .ok_or_else(too_large)?;
+        // Make sure the account has a deposit, including the highest taker fee
         match self.balance_of(&order.signer) {
             Ok(balance) if &order.side == &Side::Buy && balance < &total_amount => {
                 return Err(ApplicationError::AccountUnderFunded(
@@ -75,23 +429,163 @@ impl TradingPlatform {
         }
         let signer = order.signer.clone();
         let side = order.side.clone();
-        // Do the actual matching
-        let receipt = self.matching_engine.process(order)?;
-
-        let result: Result<Vec<_>, ApplicationError> = receipt
-            .matches
-            .iter()
-            .map(|m| {
-                match side {
-                    Side::Buy => self.send(&signer, &m.signer, m.amount * m.price),
-                    Side::Sell => self.send(&m.signer, &signer, m.amount * m.price),
+        // Every match is paid for before anything trades, a resting order whose signer can't pay leaves the book
+        let settlements = loop {
+            let matches = self.matching_engine.preview(&order)?;
+            let fills = matches.iter().map(|m| (m, signer.as_str(), &side));
+            match self.settlements(fills, now) {
+                Ok(settlements) => break settlements,
+                // Not the counterparty's fault, nothing may trade until it's sorted out
+                Err(unsettled) if unsettled.account == FEE_ACCOUNT => return Err(fee_account_failed(unsettled.error)),
+                Err(unsettled) if unsettled.account != signer => {
+                    self.exclude(&matches[unsettled.index], unsettled.error)
                 }
-                .map(|(t1, t2)| vec![t1, t2])
-            })
-            .collect();
-        self.tx_log.extend(result?.into_iter().flatten());
+                Err(unsettled) => return Err(unsettled.error),
+            }
+        };
+        let last_trade = self.last_trade_id();
+        // Do the actual matching
+        let mut receipt = tracing::info_span!("matching", signer = %signer, ?side, price = %order.price, amount = %order.amount)
+            .in_scope(|| self.matching_engine.process(order, now))?;
+        // One trade per match
+        let trades = self.trades_since(last_trade, usize::MAX);
+        for trade in &trades {
+            self.stats.record(trade);
+        }
+        let settlement = tracing::info_span!(
+            "settlement",
+            ordinal = receipt.ordinal,
+            matches = ?receipt.matches.iter().map(|m| m.ordinal).collect::<Vec<_>>()
+        );
+        let _settlement = settlement.enter();
+
+        for settlement in settlements {
+            receipt.fees.push(self.settle(settlement, now)?);
+        }
         Ok(receipt)
     }
+
+    /// What settling each of the `fills` moves between the accounts, checked against their balances one after the
+    /// other. A fill is the maker order, the taker's signer and the taker's side. Tiers go by the volume before the
+    /// first fill, trades at `timestamp` count towards it. Nothing changes until the settlements are applied with
+    /// [`TradingPlatform::settle`].
+    fn settlements<'a>(
+        &self,
+        fills: impl IntoIterator<Item = (&'a PartialOrder, &'a str, &'a Side)>,
+        timestamp: u64,
+    ) -> Result<Vec<Settlement>, Unsettled> {
+        let mut balances = HashMap::new();
+        let mut settlements = vec![];
+        for (index, (m, taker, side)) in fills.into_iter().enumerate() {
+            // Within the order's value, which didn't overflow
+            let value = m.price.checked_mul(m.amount).expect("a match's value fits");
+            let (buyer, seller) = match side {
+                Side::Buy => (taker, m.signer.as_str()),
+                Side::Sell => (m.signer.as_str(), taker),
+            };
+            let (maker_bps, _) = self.fee_schedule.rates(self.volumes.thirty_day(&m.signer, timestamp));
+            let (_, taker_bps) = self.fee_schedule.rates(self.volumes.thirty_day(taker, timestamp));
+            let taker_fee = fees::fee(value, taker_bps);
+            let mut legs = vec![
+                (buyer.to_string(), seller.to_string(), value),
+                (taker.to_string(), FEE_ACCOUNT.to_string(), taker_fee),
+            ];
+            // The taker fee covers the rebate
+            let (maker_fee, maker_rebate) = if maker_bps < 0 {
+                let rebate = fees::rebate(value, maker_bps.unsigned_abs());
+                legs.push((FEE_ACCOUNT.to_string(), m.signer.clone(), rebate));
+                (Amount::ZERO, rebate)
+            } else {
+                let fee = fees::fee(value, maker_bps as u64);
+                legs.push((m.signer.clone(), FEE_ACCOUNT.to_string(), fee));
+                (fee, Amount::ZERO)
+            };
+            for (from, to, amount) in &legs {
+                self.check_leg(&mut balances, from, to, *amount)
+                    .map_err(|(account, error)| Unsettled { index, account, error })?;
+            }
+            settlements.push(Settlement {
+                maker: m.signer.clone(),
+                taker: taker.to_string(),
+                value,
+                legs,
+                fee: FillFee {
+                    maker_ordinal: m.ordinal,
+                    maker_fee,
+                    maker_rebate,
+                    taker_fee,
+                },
+            });
+        }
+        Ok(settlements)
+    }
+
+    /// Moves `amount` between the `balances` the earlier legs left, or the accounts' balances. Returns the account
+    /// that can't pay or receive it with the error.
+    fn check_leg(
+        &self,
+        balances: &mut HashMap<String, Amount>,
+        from: &str,
+        to: &str,
+        amount: Amount,
+    ) -> Result<(), (String, ApplicationError)> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let balance = |balances: &HashMap<String, Amount>, account: &str| {
+            balances.get(account).copied().or_else(|| self.accounts.balance_of(account).ok().copied())
+        };
+        let paid = balance(balances, from)
+            .ok_or_else(|| ApplicationError::AccountNotFound(from.to_string()))
+            .and_then(|b| {
+                b.checked_sub(amount)
+                    .ok_or_else(|| ApplicationError::AccountUnderFunded(from.to_string(), amount))
+            })
+            .map_err(|e| (from.to_string(), e))?;
+        balances.insert(from.to_string(), paid);
+        let received = balance(balances, to)
+            .unwrap_or(Amount::ZERO)
+            .checked_add(amount)
+            .ok_or_else(|| (to.to_string(), ApplicationError::AccountOverFunded(to.to_string(), amount)))?;
+        balances.insert(to.to_string(), received);
+        Ok(())
+    }
+
+    /// Applies a checked [`Settlement`]: the transfer between the signers and the fees of both sides. Adds the value
+    /// to both signers' trading volume at `timestamp`.
+    fn settle(&mut self, settlement: Settlement, timestamp: u64) -> Result<FillFee, ApplicationError> {
+        for (from, to, amount) in &settlement.legs {
+            self.pay(from, to, *amount)?;
+        }
+        self.volumes.record(&settlement.maker, settlement.value, timestamp);
+        self.volumes.record(&settlement.taker, settlement.value, timestamp);
+        Ok(settlement.fee)
+    }
+
+    /// Moves `amount` between two accounts, nothing happens for 0
+    fn pay(&mut self, from: &str, to: &str, amount: Amount) -> Result<(), ApplicationError> {
+        if amount.is_zero() {
+            return Ok(());
+        }
+        let withdraw = self.accounts.withdraw(from, amount)?;
+        let deposit = self.accounts.deposit(to, amount)?;
+        self.tx_log.extend([withdraw, deposit]);
+        Ok(())
+    }
+
+    /// Takes a resting order out of the book because its signer can't pay for a match
+    fn exclude(&mut self, order: &PartialOrder, error: ApplicationError) {
+        tracing::warn!(error = %error, ordinal = order.ordinal, signer = %order.signer, "Cancelled an order that can't be settled");
+        // It's in the book, the preview just found it there
+        let _ = self.matching_engine.cancel(order.ordinal, &order.signer);
+    }
+
+    /// Cancels the `signer`'s resting order with this `ordinal`. Nothing is reserved for resting orders,
+    /// so there's nothing to release.
+    pub fn cancel(&mut self, signer: &str, ordinal: u64) -> Result<PartialOrder, ApplicationError> {
+        self.session.check_cancel()?;
+        self.matching_engine.cancel(ordinal, signer)
+    }
 }
 
 #[cfg(test)]
@@ -100,6 +594,10 @@ mod tests {
     #![allow(non_snake_case)]
 
     use super::*;
+    use crate::core::{InstrumentStatus, Quantity, Uncross};
+
+    /// The time orders and session changes in these tests happen at
+    const NOW: u64 = 1_700_000_000_000;
 
     #[test]
     fn test_TradingPlatform_order_requires_deposit_to_order() {
@@ -107,11 +605,11 @@ mod tests {
 
         assert_eq!(
             trading_platform.order(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            }),
+            }, NOW),
             Err(ApplicationError::AccountNotFound("ALICE".to_string()))
         );
         assert!(trading_platform.matching_engine.asks.is_empty());
@@ -123,35 +621,35 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
 
         let alice_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let bob_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![PartialOrder {
-                price: 10,
-                amount: 1,
-                remaining: 0,
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                remaining: Quantity::ZERO,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
                 ordinal: 1
@@ -161,8 +659,8 @@ mod tests {
         assert_eq!(trading_platform.matching_engine.bids.len(), 1);
 
         // Check the account balances
-        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&110));
-        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&90));
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(110)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(90)));
     }
 
     #[test]
@@ -170,35 +668,35 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
 
         let alice_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let bob_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![PartialOrder {
-                price: 10,
-                amount: 2,
-                remaining: 0,
+                price: Price::units(10),
+                amount: Quantity::units(2),
+                remaining: Quantity::ZERO,
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
                 ordinal: 1
@@ -210,8 +708,8 @@ mod tests {
         assert!(trading_platform.matching_engine.bids.is_empty());
 
         // Check the account balances
-        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&120));
-        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&80));
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(120)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(80)));
     }
 
     #[test]
@@ -219,56 +717,56 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("CHARLIE", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("CHARLIE", Amount::units(100)).is_ok());
 
         let alice_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let charlie_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(charlie_receipt.matches, vec![]);
         assert_eq!(charlie_receipt.ordinal, 2);
 
         let bob_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![
                 PartialOrder {
-                    price: 10,
-                    amount: 1,
-                    remaining: 0,
+                    price: Price::units(10),
+                    amount: Quantity::units(1),
+                    remaining: Quantity::ZERO,
                     side: Side::Sell,
                     signer: "ALICE".to_string(),
                     ordinal: 1
                 },
                 PartialOrder {
-                    price: 10,
-                    amount: 1,
-                    remaining: 0,
+                    price: Price::units(10),
+                    amount: Quantity::units(1),
+                    remaining: Quantity::ZERO,
                     side: Side::Sell,
                     signer: "CHARLIE".to_string(),
                     ordinal: 2
@@ -280,9 +778,9 @@ mod tests {
         assert!(trading_platform.matching_engine.bids.is_empty());
 
         // Check account balances
-        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&110));
-        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&80));
-        assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&110));
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(110)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(80)));
+        assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&Amount::units(110)));
     }
 
     #[test]
@@ -290,46 +788,46 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("CHARLIE", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("CHARLIE", Amount::units(100)).is_ok());
 
         let alice_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let charlie_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 1,
+                price: Price::units(10),
+                amount: Quantity::units(1),
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(charlie_receipt.matches, vec![]);
         assert_eq!(charlie_receipt.ordinal, 2);
 
         let bob_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Buy,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(
             bob_receipt.matches,
             vec![PartialOrder {
-                price: 10,
-                amount: 1,
-                remaining: 0,
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                remaining: Quantity::ZERO,
                 side: Side::Sell,
                 signer: "CHARLIE".to_string(),
                 ordinal: 2
@@ -339,8 +837,8 @@ mod tests {
         assert_eq!(trading_platform.matching_engine.asks.len(), 1);
         assert_eq!(trading_platform.matching_engine.bids.len(), 1);
         // Check account balances
-        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&90));
-        assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&110));
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(90)));
+        assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&Amount::units(110)));
     }
 
     #[test]
@@ -348,34 +846,665 @@ mod tests {
         let mut trading_platform = TradingPlatform::new();
 
         // Set up accounts
-        assert!(trading_platform.accounts.deposit("ALICE", 100).is_ok());
-        assert!(trading_platform.accounts.deposit("BOB", 100).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
 
         let alice_receipt = trading_platform
             .order(Order {
-                price: 10,
-                amount: 2,
+                price: Price::units(10),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "ALICE".to_string(),
-            })
+            }, NOW)
             .unwrap();
         assert_eq!(alice_receipt.matches, vec![]);
         assert_eq!(alice_receipt.ordinal, 1);
 
         let bob_receipt = trading_platform
             .order(Order {
-                price: 11,
-                amount: 2,
+                price: Price::units(11),
+                amount: Quantity::units(2),
                 side: Side::Sell,
                 signer: "BOB".to_string(),
-            })
+            }, NOW)
             .unwrap();
 
         assert_eq!(bob_receipt.matches, vec![]);
         assert_eq!(trading_platform.orderbook().len(), 2);
 
         // Check the account balances
-        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&100));
-        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&100));
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(100)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(100)));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_updates_the_ticker() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+
+        for (price, side, signer) in [(10, Side::Sell, "ALICE"), (12, Side::Sell, "ALICE"), (12, Side::Buy, "BOB")] {
+            trading_platform
+                .order(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(2),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+        }
+
+        let now = trading_platform.trades_since(0, 10).last().unwrap().timestamp;
+        let ticker = trading_platform.ticker(now);
+        assert_eq!(ticker.last, Some(Price::units(10)));
+        assert_eq!(ticker.volume, Quantity::units(2));
+        assert_eq!(ticker.quote_volume, Amount::units(20));
+        assert_eq!(ticker.trades, 1);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_charges_fees_and_pays_rebates() {
+        let schedule = FeeSchedule {
+            tiers: vec![
+                fees::FeeTier {
+                    min_volume: Amount::ZERO,
+                    maker_bps: 100,
+                    taker_bps: 200,
+                },
+                fees::FeeTier {
+                    min_volume: Amount::units(1_000),
+                    maker_bps: -50,
+                    taker_bps: 100,
+                },
+            ],
+        };
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(schedule);
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(10_000)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(10_000)).is_ok());
+        let order = |trading_platform: &mut TradingPlatform, side, signer: &str, amount| {
+            trading_platform
+                .order(Order {
+                    price: Price::units(100),
+                    amount: Quantity::units(amount),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap()
+        };
+
+        // 1000 traded: ALICE pays 1% as maker, BOB 2% as taker
+        order(&mut trading_platform, Side::Sell, "ALICE", 20);
+        let receipt = order(&mut trading_platform, Side::Buy, "BOB", 10);
+        assert_eq!(
+            receipt.fees,
+            vec![FillFee {
+                maker_ordinal: 1,
+                maker_fee: Amount::units(10),
+                maker_rebate: Amount::ZERO,
+                taker_fee: Amount::units(20),
+            }]
+        );
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(10_990)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(8_980)));
+        assert_eq!(trading_platform.accounts.balance_of(FEE_ACCOUNT), Ok(&Amount::units(30)));
+
+        // Both are in the next tier now, ALICE earns a rebate
+        let receipt = order(&mut trading_platform, Side::Buy, "BOB", 10);
+        assert_eq!(receipt.fees[0].maker_fee, Amount::ZERO);
+        assert_eq!(receipt.fees[0].maker_rebate, Amount::units(5));
+        assert_eq!(receipt.fees[0].taker_fee, Amount::units(10));
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(11_995)));
+        assert_eq!(trading_platform.accounts.balance_of(FEE_ACCOUNT), Ok(&Amount::units(35)));
+        // A withdrawal and a deposit for the transfer and each fee
+        assert_eq!(trading_platform.tx_log.len(), 2 * 6);
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_requires_funds_for_the_taker_fee() {
+        let schedule = FeeSchedule {
+            tiers: vec![fees::FeeTier {
+                min_volume: Amount::ZERO,
+                maker_bps: 0,
+                taker_bps: 100,
+            }],
+        };
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(schedule);
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        assert_eq!(
+            trading_platform.order(Order {
+                price: Price::units(10),
+                amount: Quantity::units(10),
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            }, NOW),
+            Err(ApplicationError::AccountUnderFunded("BOB".to_string(), Amount::units(101)))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_cancels_makers_that_cant_pay() {
+        let schedule = FeeSchedule {
+            tiers: vec![fees::FeeTier {
+                min_volume: Amount::ZERO,
+                maker_bps: 100,
+                taker_bps: 100,
+            }],
+        };
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(schedule);
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(101)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        let bid = Order {
+            price: Price::units(100),
+            amount: Quantity::units(1),
+            side: Side::Buy,
+            signer: "ALICE".to_string(),
+        };
+        assert!(trading_platform.order(bid, NOW).is_ok());
+        // The bid rests, but ALICE can't pay its value and the maker fee anymore
+        assert!(trading_platform.withdraw("ALICE", Amount::units(1)).is_ok());
+
+        let receipt = trading_platform
+            .order(Order {
+                price: Price::units(100),
+                amount: Quantity::units(1),
+                side: Side::Sell,
+                signer: "BOB".to_string(),
+            }, NOW)
+            .unwrap();
+        assert_eq!(receipt.matches, vec![]);
+        assert_eq!(trading_platform.last_trade_id(), 0);
+        let orderbook = trading_platform.orderbook();
+        assert_eq!(orderbook.len(), 1);
+        assert_eq!(orderbook[0].signer, "BOB");
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(100)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(100)));
+        assert!(trading_platform.tx_log.is_empty());
+        assert!(trading_platform
+            .take_book_events()
+            .contains(&BookEvent::Delete { order_id: trading_platform.matching_engine.public_id(1) }));
+    }
+
+    /// Rebates the fee account can't pay, it never took a fee
+    fn unpayable_rebates() -> FeeSchedule {
+        FeeSchedule {
+            tiers: vec![fees::FeeTier {
+                min_volume: Amount::ZERO,
+                maker_bps: -100,
+                taker_bps: 0,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_keeps_makers_when_the_fee_account_cant_settle() {
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(unpayable_rebates());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        let order = |side, signer: &str| Order {
+            price: Price::units(100),
+            amount: Quantity::units(1),
+            side,
+            signer: signer.to_string(),
+        };
+        assert!(trading_platform.order(order(Side::Buy, "ALICE"), NOW).is_ok());
+
+        assert!(matches!(
+            trading_platform.order(order(Side::Sell, "BOB"), NOW),
+            Err(ApplicationError::Unavailable(_))
+        ));
+        // ALICE's bid wasn't at fault
+        let orderbook = trading_platform.orderbook();
+        assert_eq!(orderbook.len(), 1);
+        assert_eq!(orderbook[0].signer, "ALICE");
+        assert_eq!(trading_platform.last_trade_id(), 0);
+        assert!(trading_platform.tx_log.is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_reserves_the_fee_account() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.deposit("ALICE", Amount::units(100)).is_ok());
+        let reserved = ApplicationError::ReservedAccount(FEE_ACCOUNT.to_string());
+
+        assert_eq!(trading_platform.deposit(FEE_ACCOUNT, Amount::units(1)).unwrap_err(), reserved);
+        assert_eq!(trading_platform.withdraw(FEE_ACCOUNT, Amount::units(1)).unwrap_err(), reserved);
+        assert_eq!(trading_platform.send("ALICE", FEE_ACCOUNT, Amount::units(1)).unwrap_err(), reserved);
+        assert_eq!(trading_platform.send(FEE_ACCOUNT, "ALICE", Amount::units(1)).unwrap_err(), reserved);
+        let order = Order {
+            price: Price::units(10),
+            amount: Quantity::units(1),
+            side: Side::Sell,
+            signer: FEE_ACCOUNT.to_string(),
+        };
+        assert_eq!(trading_platform.order(order, NOW).unwrap_err(), reserved);
+        assert_eq!(trading_platform.balance_of("ALICE"), Ok(&Amount::units(100)));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_settles_fractional_prices_and_amounts() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        let order = |price: &str, amount: &str, side, signer: &str| Order {
+            price: price.parse().unwrap(),
+            amount: amount.parse().unwrap(),
+            side,
+            signer: signer.to_string(),
+        };
+
+        assert!(trading_platform.order(order("10.25", "1.5", Side::Sell, "ALICE"), NOW).is_ok());
+        let receipt = trading_platform.order(order("10.25", "0.1234", Side::Buy, "BOB"), NOW).unwrap();
+        assert_eq!(receipt.matches[0].remaining, "1.3766".parse().unwrap());
+        // 10.25 * 0.1234 = 1.26485
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&"101.26485".parse().unwrap()));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&"98.73515".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_rejects_too_many_decimals() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        let order = |price: &str, amount: &str| Order {
+            price: price.parse().unwrap(),
+            amount: amount.parse().unwrap(),
+            side: Side::Buy,
+            signer: "BOB".to_string(),
+        };
+
+        assert!(matches!(
+            trading_platform.order(order("10.125", "1"), NOW),
+            Err(ApplicationError::InvalidOrder(_))
+        ));
+        assert!(matches!(
+            trading_platform.order(order("10", "0.00001"), NOW),
+            Err(ApplicationError::InvalidOrder(_))
+        ));
+        // The listed instrument's precision applies
+        let mut precise = TradingPlatform::new();
+        let instrument = Instrument {
+            tick_size: "0.001".parse().unwrap(),
+            lot_size: "0.001".parse().unwrap(),
+            precision: Precision { price: 3, quantity: 3 },
+            ..btc_usd()
+        };
+        assert!(precise.create_instrument(instrument).is_ok());
+        assert!(precise.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        assert!(precise.order(order("10.125", "1"), NOW).is_ok());
+        assert!(trading_platform.orderbook().is_empty());
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_rejects_values_that_overflow() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+        assert!(matches!(
+            trading_platform.order(Order {
+                price: Price::units(u64::MAX / 1_000_000),
+                amount: Quantity::units(1_000_000),
+                side: Side::Buy,
+                signer: "BOB".to_string(),
+            }, NOW),
+            Err(ApplicationError::InvalidOrder(_))
+        ));
+    }
+
+    fn btc_usd() -> Instrument {
+        Instrument {
+            symbol: "BTC-USD".to_string(),
+            base: "BTC".to_string(),
+            quote: "USD".to_string(),
+            tick_size: "0.5".parse().unwrap(),
+            lot_size: "0.01".parse().unwrap(),
+            precision: Precision::default(),
+            min_quantity: "0.1".parse().unwrap(),
+            max_quantity: Some(Quantity::units(10)),
+            min_notional: Amount::units(5),
+            price_band_bps: Some(1000),
+            status: InstrumentStatus::Active,
+        }
+    }
+
+    #[test]
+    fn test_TradingPlatform_create_instrument_lists_one_valid_instrument() {
+        let mut trading_platform = TradingPlatform::new();
+        let invalid = Instrument {
+            tick_size: "0.001".parse().unwrap(),
+            lot_size: "0.0001".parse().unwrap(),
+            ..btc_usd()
+        };
+        assert!(matches!(
+            trading_platform.create_instrument(invalid),
+            Err(ApplicationError::InvalidInstrument(_))
+        ));
+        assert_eq!(trading_platform.create_instrument(btc_usd()), Ok(btc_usd()));
+        assert_eq!(trading_platform.instrument(), Some(&btc_usd()));
+        let other = Instrument {
+            symbol: "ETH-USD".to_string(),
+            ..btc_usd()
+        };
+        assert_eq!(
+            trading_platform.create_instrument(other),
+            Err(ApplicationError::InstrumentExists("BTC-USD".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_TradingPlatform_update_instrument_keeps_the_symbol() {
+        let mut trading_platform = TradingPlatform::new();
+        assert_eq!(
+            trading_platform.update_instrument("BTC-USD", btc_usd()),
+            Err(ApplicationError::InstrumentNotFound("BTC-USD".to_string()))
+        );
+        assert!(trading_platform.create_instrument(btc_usd()).is_ok());
+        let renamed = Instrument {
+            symbol: "XBT-USD".to_string(),
+            ..btc_usd()
+        };
+        assert!(matches!(
+            trading_platform.update_instrument("BTC-USD", renamed),
+            Err(ApplicationError::InvalidInstrument(_))
+        ));
+        let suspended = Instrument {
+            status: InstrumentStatus::Suspended,
+            ..btc_usd()
+        };
+        assert_eq!(trading_platform.update_instrument("BTC-USD", suspended.clone()), Ok(suspended.clone()));
+        assert_eq!(trading_platform.instrument(), Some(&suspended));
+    }
+
+    #[test]
+    fn test_TradingPlatform_update_instrument_keeps_the_precision_while_orders_rest() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.create_instrument(btc_usd()).is_ok());
+        assert!(trading_platform.deposit("ALICE", Amount::units(100)).is_ok());
+        let sell = Order {
+            price: Price::units(10),
+            amount: Quantity::units(1),
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        };
+        let ordinal = trading_platform.order(sell, NOW).unwrap().ordinal;
+        let finer = Instrument {
+            precision: Precision { price: 1, quantity: 5 },
+            ..btc_usd()
+        };
+        assert!(matches!(
+            trading_platform.update_instrument("BTC-USD", finer.clone()),
+            Err(ApplicationError::InvalidInstrument(_))
+        ));
+        // Anything else may still change
+        let wider_band = Instrument {
+            price_band_bps: Some(2000),
+            ..btc_usd()
+        };
+        assert_eq!(trading_platform.update_instrument("BTC-USD", wider_band.clone()), Ok(wider_band));
+
+        assert!(trading_platform.cancel("ALICE", ordinal).is_ok());
+        assert_eq!(trading_platform.update_instrument("BTC-USD", finer.clone()), Ok(finer));
+    }
+
+    #[test]
+    fn test_TradingPlatform_order_checks_the_instrument() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.create_instrument(btc_usd()).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(1000)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(1000)).is_ok());
+        let order = |price: &str, amount: &str, side: Side, signer: &str| Order {
+            price: price.parse().unwrap(),
+            amount: amount.parse().unwrap(),
+            side,
+            signer: signer.to_string(),
+        };
+
+        assert_eq!(
+            trading_platform.order(order("10.25", "1", Side::Sell, "ALICE"), NOW),
+            Err(ApplicationError::PriceOffTick("10.25".parse().unwrap(), "0.5".parse().unwrap()))
+        );
+        assert_eq!(
+            trading_platform.order(order("10.5", "1.005", Side::Sell, "ALICE"), NOW),
+            Err(ApplicationError::QuantityOffLot("1.005".parse().unwrap(), "0.01".parse().unwrap()))
+        );
+        assert!(matches!(
+            trading_platform.order(order("10.5", "0.05", Side::Sell, "ALICE"), NOW),
+            Err(ApplicationError::QuantityOutOfRange(..))
+        ));
+        assert!(matches!(
+            trading_platform.order(order("10.5", "11", Side::Sell, "ALICE"), NOW),
+            Err(ApplicationError::QuantityOutOfRange(..))
+        ));
+        assert_eq!(
+            trading_platform.order(order("10", "0.4", Side::Sell, "ALICE"), NOW),
+            Err(ApplicationError::NotionalTooSmall(Amount::units(4), Amount::units(5)))
+        );
+        // Without trades there's no band yet
+        assert!(trading_platform.order(order("100", "1", Side::Sell, "ALICE"), NOW).is_ok());
+        assert!(trading_platform.order(order("100", "1", Side::Buy, "BOB"), NOW).is_ok());
+        assert_eq!(
+            trading_platform.order(order("110.5", "1", Side::Sell, "ALICE"), NOW),
+            Err(ApplicationError::PriceOutsideBand(
+                "110.5".parse().unwrap(),
+                Price::units(90),
+                Price::units(110)
+            ))
+        );
+        let resting = trading_platform.order(order("110", "1", Side::Sell, "ALICE"), NOW).unwrap();
+
+        let suspended = Instrument {
+            status: InstrumentStatus::Suspended,
+            ..btc_usd()
+        };
+        assert!(trading_platform.update_instrument("BTC-USD", suspended).is_ok());
+        assert_eq!(
+            trading_platform.order(order("100", "1", Side::Buy, "BOB"), NOW),
+            Err(ApplicationError::InstrumentSuspended("BTC-USD".to_string()))
+        );
+        // Resting orders can still be cancelled
+        assert!(trading_platform.cancel("ALICE", resting.ordinal).is_ok());
+    }
+
+    #[test]
+    fn test_TradingPlatform_session_gates_orders_and_cancels() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        let order = Order {
+            price: Price::units(10),
+            amount: Quantity::units(1),
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        };
+        let resting = trading_platform.order(order.clone(), NOW).unwrap();
+
+        for (state, cancels) in [
+            (SessionState::PreOpen, true),
+            (SessionState::Closed, false),
+        ] {
+            trading_platform.set_session_state(state, NOW);
+            assert_eq!(
+                trading_platform.order(order.clone(), NOW),
+                Err(ApplicationError::MarketNotOpen(state))
+            );
+            assert_eq!(
+                trading_platform.session.check_cancel().is_ok(),
+                cancels,
+                "{:?}",
+                state
+            );
+        }
+        trading_platform.set_session_state(SessionState::Continuous, NOW);
+        assert!(trading_platform.cancel("ALICE", resting.ordinal).is_ok());
+    }
+
+    #[test]
+    fn test_TradingPlatform_halt_and_resume() {
+        let mut trading_platform = TradingPlatform::new();
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        let order = Order {
+            price: Price::units(10),
+            amount: Quantity::units(1),
+            side: Side::Sell,
+            signer: "ALICE".to_string(),
+        };
+        let resting = trading_platform.order(order.clone(), NOW).unwrap();
+        assert_eq!(
+            trading_platform.halt(Some("BTC-USD")),
+            Err(ApplicationError::InstrumentNotFound("BTC-USD".to_string()))
+        );
+        assert!(trading_platform.create_instrument(btc_usd()).is_ok());
+
+        // A halted instrument only accepts cancels, the schedule moves on underneath
+        let session = trading_platform.halt(Some("BTC-USD")).unwrap();
+        assert_eq!(session.state, SessionState::Halted);
+        assert_eq!(
+            trading_platform.order(order.clone(), NOW),
+            Err(ApplicationError::MarketNotOpen(SessionState::Halted))
+        );
+        trading_platform.set_session_state(SessionState::Closed, NOW);
+        assert_eq!(trading_platform.session().state, SessionState::Halted);
+        assert_eq!(trading_platform.resume(Some("BTC-USD"), NOW).unwrap().state, SessionState::Closed);
+        trading_platform.set_session_state(SessionState::Continuous, NOW);
+
+        // A halted venue accepts nothing, lifting it leaves the instrument's halt in place
+        trading_platform.halt(None).unwrap();
+        trading_platform.halt(Some("BTC-USD")).unwrap();
+        assert_eq!(
+            trading_platform.cancel("ALICE", resting.ordinal),
+            Err(ApplicationError::MarketNotOpen(SessionState::Halted))
+        );
+        let session = trading_platform.resume(None, NOW).unwrap();
+        assert!(!session.venue_halted);
+        assert_eq!(session.state, SessionState::Halted);
+        assert!(trading_platform.cancel("ALICE", resting.ordinal).is_ok());
+        trading_platform.resume(Some("BTC-USD"), NOW).unwrap();
+        assert!(trading_platform.order(order, NOW).is_ok());
+    }
+
+    #[test]
+    fn test_TradingPlatform_auction_uncrosses_when_the_session_moves_on() {
+        let schedule = FeeSchedule {
+            tiers: vec![fees::FeeTier {
+                min_volume: Amount::ZERO,
+                maker_bps: 0,
+                taker_bps: 100,
+            }],
+        };
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(schedule);
+        assert!(trading_platform.create_instrument(btc_usd()).is_ok());
+        assert!(trading_platform.accounts.deposit("ALICE", Amount::units(100)).is_ok());
+        assert!(trading_platform.accounts.deposit("BOB", Amount::units(100)).is_ok());
+
+        trading_platform.set_session_state(SessionState::Auction, NOW);
+        assert!(trading_platform.auction().active);
+        for (side, price, signer) in [(Side::Sell, 10, "ALICE"), (Side::Buy, 11, "BOB")] {
+            let receipt = trading_platform
+                .order(Order {
+                    price: Price::units(price),
+                    amount: Quantity::units(2),
+                    side,
+                    signer: signer.to_string(),
+                }, NOW)
+                .unwrap();
+            assert_eq!(receipt.matches, vec![]);
+        }
+        // Both prices execute everything, without a last trade the lower of the two wins
+        assert_eq!(
+            trading_platform.auction().indicative,
+            Some(Uncross {
+                price: Price::units(10),
+                volume: Quantity::units(2),
+                surplus: Quantity::ZERO,
+                surplus_side: None,
+            })
+        );
+
+        // A halt pauses the auction, even when the schedule moves on underneath
+        trading_platform.halt(Some("BTC-USD")).unwrap();
+        trading_platform.set_session_state(SessionState::Continuous, NOW);
+        assert!(trading_platform.auction().active);
+        assert_eq!(trading_platform.last_trade_id(), 0);
+
+        // Everything executes at one price, the later order pays the taker fee
+        trading_platform.resume(Some("BTC-USD"), NOW).unwrap();
+        assert_eq!(trading_platform.auction(), Auction::default());
+        let trades: Vec<_> = trading_platform
+            .trades_since(0, usize::MAX)
+            .into_iter()
+            .map(|t| (t.price, t.amount, t.maker_ordinal, t.taker_ordinal, t.aggressor))
+            .collect();
+        assert_eq!(trades, vec![(Price::units(10), Quantity::units(2), 1, 2, Side::Buy)]);
+        assert_eq!(trading_platform.market_stats().last_price(), Some(Price::units(10)));
+        assert!(trading_platform.orderbook().is_empty());
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(120)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&"79.8".parse().unwrap()));
+        assert_eq!(trading_platform.accounts.balance_of(FEE_ACCOUNT), Ok(&"0.2".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_TradingPlatform_auction_leaves_out_orders_that_cant_pay() {
+        let schedule = FeeSchedule {
+            tiers: vec![fees::FeeTier {
+                min_volume: Amount::ZERO,
+                maker_bps: 0,
+                taker_bps: 100,
+            }],
+        };
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(schedule);
+        for account in ["ALICE", "BOB", "CHARLIE"] {
+            assert!(trading_platform.accounts.deposit(account, Amount::units(100)).is_ok());
+        }
+        trading_platform.set_session_state(SessionState::Auction, NOW);
+        for (side, price, amount, signer) in [
+            (Side::Sell, 10, 2, "ALICE"),
+            (Side::Buy, 11, 2, "BOB"),
+            (Side::Buy, 10, 1, "CHARLIE"),
+        ] {
+            let order = Order {
+                price: Price::units(price),
+                amount: Quantity::units(amount),
+                side,
+                signer: signer.to_string(),
+            };
+            assert!(trading_platform.order(order, NOW).is_ok());
+        }
+        // BOB's bid would fill first, but BOB can't pay for it anymore
+        assert!(trading_platform.withdraw("BOB", Amount::units(90)).is_ok());
+
+        trading_platform.set_session_state(SessionState::Continuous, NOW);
+        let trades: Vec<_> = trading_platform
+            .trades_since(0, usize::MAX)
+            .into_iter()
+            .map(|t| (t.price, t.amount, t.maker_ordinal, t.taker_ordinal))
+            .collect();
+        assert_eq!(trades, vec![(Price::units(10), Quantity::units(1), 1, 3)]);
+        let orderbook = trading_platform.orderbook();
+        assert_eq!(orderbook.len(), 1);
+        assert_eq!((orderbook[0].ordinal, orderbook[0].remaining), (1, Quantity::units(1)));
+        // The books balance
+        assert_eq!(trading_platform.accounts.balance_of("ALICE"), Ok(&Amount::units(110)));
+        assert_eq!(trading_platform.accounts.balance_of("BOB"), Ok(&Amount::units(10)));
+        assert_eq!(trading_platform.accounts.balance_of("CHARLIE"), Ok(&"89.9".parse().unwrap()));
+        assert_eq!(trading_platform.accounts.balance_of(FEE_ACCOUNT), Ok(&"0.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_TradingPlatform_auction_keeps_collecting_when_the_fee_account_cant_settle() {
+        let mut trading_platform = TradingPlatform::new().with_fee_schedule(unpayable_rebates());
+        for account in ["ALICE", "BOB"] {
+            assert!(trading_platform.accounts.deposit(account, Amount::units(100)).is_ok());
+        }
+        trading_platform.set_session_state(SessionState::Auction, NOW);
+        for (side, signer) in [(Side::Sell, "ALICE"), (Side::Buy, "BOB")] {
+            let order = Order {
+                price: Price::units(10),
+                amount: Quantity::units(1),
+                side,
+                signer: signer.to_string(),
+            };
+            assert!(trading_platform.order(order, NOW).is_ok());
+        }
+
+        trading_platform.set_session_state(SessionState::Continuous, NOW);
+        assert!(trading_platform.auction().active);
+        assert_eq!(trading_platform.last_trade_id(), 0);
+        assert_eq!(trading_platform.orderbook().len(), 2);
     }
 }